@@ -11,17 +11,20 @@ use bdk::keys::{DerivableKey, ExtendedKey, GeneratedKey};
 use bdk::template::Bip84;
 use bdk::wallet::{ChangeSet, Update};
 use bdk::{FeeRate, KeychainKind, SignOptions, Wallet};
+use bdk_chain::ConfirmationTime;
 use bdk_esplora::EsploraExt;
 use bdk_file_store::Store;
 
 use lampo_common::bitcoin::consensus::deserialize;
 use lampo_common::bitcoin::hashes::hex::ToHex;
-use lampo_common::bitcoin::{PrivateKey, Script, Transaction};
+use lampo_common::bitcoin::psbt::PartiallySignedTransaction;
+use lampo_common::bitcoin::{OutPoint, PrivateKey, Transaction, TxOut};
 use lampo_common::conf::{LampoConf, Network};
 use lampo_common::error;
 use lampo_common::keys::LampoKeys;
+use lampo_common::ldk::events::bump_transaction;
 use lampo_common::model::response::{NewAddress, Utxo};
-use lampo_common::wallet::WalletManager;
+use lampo_common::wallet::{p2wpkh_utxo, WalletManager};
 
 pub struct BDKWalletManager {
     pub wallet: RefCell<Mutex<Wallet<Store<'static, ChangeSet>>>>,
@@ -157,19 +160,110 @@ impl WalletManager for BDKWalletManager {
         Ok(balance.confirmed)
     }
 
-    fn create_transaction(
+    fn create_batch_transaction(
         &self,
-        script: Script,
-        amount: u64,
+        outputs: &[(lampo_common::bitcoin::ScriptBuf, u64)],
         fee_rate: u32,
+        inputs: &[OutPoint],
+        minconf: Option<u32>,
     ) -> error::Result<Transaction> {
         self.sync()?;
         let wallet = self.wallet.borrow_mut();
         let mut wallet = wallet.lock().unwrap();
+        // the UTXOs without enough confirmations, that the
+        // coin selection should not use.
+        let mut unspendable = Vec::new();
+        if let Some(minconf) = minconf {
+            let tip = wallet
+                .latest_checkpoint()
+                .map(|checkpoint| checkpoint.height())
+                .unwrap_or_default();
+            for utxo in wallet.list_unspent() {
+                let confirmations = match utxo.confirmation_time {
+                    ConfirmationTime::Confirmed { height, .. } => tip.saturating_sub(height) + 1,
+                    ConfirmationTime::Unconfirmed { .. } => 0,
+                };
+                if confirmations < minconf {
+                    unspendable.push(utxo.outpoint);
+                }
+            }
+        }
+        let inputs = inputs
+            .iter()
+            .map(|input| {
+                Ok(bdk::bitcoin::consensus::deserialize(
+                    &lampo_common::bitcoin::consensus::serialize(input),
+                )?)
+            })
+            .collect::<error::Result<Vec<bdk::bitcoin::OutPoint>>>()?;
         let mut tx = wallet.build_tx();
-        tx.add_recipient(ScriptBuf::from_bytes(script.into_bytes()), amount)
-            .fee_rate(FeeRate::from_sat_per_kvb(fee_rate as f32))
+        for (script, amount) in outputs {
+            tx.add_recipient(ScriptBuf::from_bytes(script.to_bytes()), *amount);
+        }
+        // the feerate is in sats per 1000 weight, so 250 sats per vbyte.
+        tx.fee_rate(FeeRate::from_sat_per_vb(fee_rate as f32 / 250.0))
+            .unspendable(unspendable)
             .enable_rbf();
+        if !inputs.is_empty() {
+            tx.add_utxos(&inputs)?.manually_selected_only();
+        }
+        let mut psbt = tx.finish()?;
+        if !wallet.sign(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet not able to sing the psbt {psbt}");
+        }
+        if !wallet.finalize_psbt(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet impossible finalize the psbt: {psbt}");
+        };
+        let tx: Transaction = deserialize(&serialize(&psbt.extract_tx()))?;
+        Ok(tx)
+    }
+
+    fn withdraw(
+        &self,
+        script: lampo_common::bitcoin::ScriptBuf,
+        amount_sat: Option<u64>,
+        fee_rate: u32,
+        inputs: &[OutPoint],
+        reserved: &[OutPoint],
+        rbf: bool,
+    ) -> error::Result<Transaction> {
+        self.sync()?;
+        let wallet = self.wallet.borrow_mut();
+        let mut wallet = wallet.lock().unwrap();
+        let outpoints = |outpoints: &[OutPoint]| {
+            outpoints
+                .iter()
+                .map(|outpoint| {
+                    Ok(bdk::bitcoin::consensus::deserialize(
+                        &lampo_common::bitcoin::consensus::serialize(outpoint),
+                    )?)
+                })
+                .collect::<error::Result<Vec<bdk::bitcoin::OutPoint>>>()
+        };
+        let inputs = outpoints(inputs)?;
+        let script = ScriptBuf::from_bytes(script.to_bytes());
+        let mut tx = wallet.build_tx();
+        // the feerate is in sats per 1000 weight, so 250 sats per vbyte.
+        tx.fee_rate(FeeRate::from_sat_per_vb(fee_rate as f32 / 250.0))
+            .unspendable(outpoints(reserved)?);
+        match amount_sat {
+            Some(amount_sat) => {
+                tx.add_recipient(script, amount_sat);
+            }
+            None => {
+                // send all the funds, the fee is paid by the output.
+                tx.drain_to(script);
+                if inputs.is_empty() {
+                    tx.drain_wallet();
+                }
+            }
+        }
+        if !inputs.is_empty() {
+            tx.add_utxos(&inputs)?.manually_selected_only();
+        }
+        if rbf {
+            tx.enable_rbf();
+        }
         let mut psbt = tx.finish()?;
         if !wallet.sign(&mut psbt, SignOptions::default())? {
             error::bail!("wallet not able to sing the psbt {psbt}");
@@ -198,6 +292,54 @@ impl WalletManager for BDKWalletManager {
         Ok(txs)
     }
 
+    /// Nb. this is called at every fee bump and channel open, so it
+    /// uses the state of the last sync instead of syncing again.
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<bump_transaction::Utxo>> {
+        let wallet = self.wallet.borrow();
+        let wallet = wallet.lock().unwrap();
+        let mut utxos = Vec::new();
+        for utxo in wallet
+            .list_unspent()
+            .filter(|utxo| utxo.confirmation_time.is_confirmed())
+        {
+            let outpoint: OutPoint = deserialize(&serialize(&utxo.outpoint))?;
+            let output: TxOut = deserialize(&serialize(&utxo.txout))?;
+            // the wallet is using a BIP 84 descriptor
+            if let Some(utxo) = p2wpkh_utxo(outpoint, &output) {
+                utxos.push(utxo);
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn get_change_script(&self) -> error::Result<lampo_common::bitcoin::ScriptBuf> {
+        let address = self
+            .wallet
+            .borrow_mut()
+            .lock()
+            .unwrap()
+            .get_internal_address(bdk::wallet::AddressIndex::New);
+        Ok(lampo_common::bitcoin::ScriptBuf::from_bytes(
+            address.script_pubkey().into_bytes(),
+        ))
+    }
+
+    fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> error::Result<Transaction> {
+        let wallet = self.wallet.borrow_mut();
+        let wallet = wallet.lock().unwrap();
+        let mut psbt =
+            bdk::bitcoin::psbt::PartiallySignedTransaction::deserialize(&psbt.serialize())?;
+        // Nb. the inputs that are not owned by the wallet are not
+        // finalized, so we do not check the result here.
+        let options = SignOptions {
+            trust_witness_utxo: true,
+            ..Default::default()
+        };
+        wallet.sign(&mut psbt, options)?;
+        let tx: Transaction = deserialize(&serialize(&psbt.extract_tx()))?;
+        Ok(tx)
+    }
+
     fn sync(&self) -> error::Result<()> {
         // Scanning the chain...
         let esplora_url = match self.network {
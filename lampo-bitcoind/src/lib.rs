@@ -150,27 +150,29 @@ impl Backend for BitcoinCore {
     }
 
     fn brodcast_tx(&self, tx: &lampo_common::backend::Transaction) {
-        // FIXME: check the result.
+        if let Err(err) = self.send_raw_transaction(tx) {
+            log::error!(target: "bitcoind", "broadcast transaction return {err}");
+        }
+    }
+
+    fn send_raw_transaction(&self, tx: &lampo_common::backend::Transaction) -> error::Result<()> {
         let result: bitcoincore_rpc::Result<json::Value> = self.inner.call(
             "sendrawtransaction",
             &[lampo_common::bitcoin::consensus::encode::serialize_hex(&tx).into()],
         );
         log::info!(target: "bitcoind", "broadcast transaction return {:?}", result);
-        if result.is_ok() {
-            self.ours_txs.lock().unwrap().borrow_mut().push(tx.txid());
-            self.others_txs
-                .lock()
-                .unwrap()
-                .borrow_mut()
-                .retain(|(txid, _)| txid.to_string() == tx.txid().to_string());
-            let handler = self.handler.borrow();
-            let Some(handler) = handler.as_ref() else {
-                return;
-            };
+        result?;
+        self.ours_txs.lock().unwrap().borrow_mut().push(tx.txid());
+        self.others_txs
+            .lock()
+            .unwrap()
+            .borrow_mut()
+            .retain(|(txid, _)| txid.to_string() == tx.txid().to_string());
+        let handler = self.handler.borrow();
+        if let Some(handler) = handler.as_ref() {
             handler.emit(Event::OnChain(OnChainEvent::SendRawTransaction(tx.clone())));
-        } else {
-            log::error!(target: "bitcoind", "broadcast transaction return {:?}", result);
         }
+        Ok(())
     }
 
     /// Returning the fee rate estimation in sats.
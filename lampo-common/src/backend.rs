@@ -44,6 +44,13 @@ pub trait Backend {
 
     fn brodcast_tx(&self, tx: &Transaction);
 
+    /// Broadcast the transaction, and return the error
+    /// when the backend refuses it.
+    fn send_raw_transaction(&self, tx: &Transaction) -> error::Result<()> {
+        self.brodcast_tx(tx);
+        Ok(())
+    }
+
     fn is_lightway(&self) -> bool;
 
     /// You must follow this step if: you are not providing full blocks to LDK, i.e. if you're using BIP 157/158 or Electrum as your chain backend
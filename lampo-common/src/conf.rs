@@ -5,6 +5,8 @@ use clightningrpc_conf::{CLNConf, SyncCLNConf};
 pub use bitcoin::Network;
 pub use lightning::util::config::UserConfig;
 
+use crate::types::NodeId;
+
 #[derive(Clone, Debug)]
 pub struct LampoConf {
     pub inner: Option<CLNConf>,
@@ -23,6 +25,105 @@ pub struct LampoConf {
     pub log_level: String,
     pub alias: Option<String>,
     pub announce_addr: Option<String>,
+    /// Address (`host:port`) where the JSON RPC 2.0 server
+    /// accepts plain TCP connections.
+    pub rpc_tcp_bind: Option<String>,
+    /// Address (`host:port`) where the JSON RPC 2.0 server
+    /// accepts HTTP connections.
+    pub rpc_http_bind: Option<String>,
+    /// Bearer token required by the TCP and HTTP listeners.
+    pub rpc_token: Option<String>,
+    /// Basic auth credentials required by the TCP and HTTP listeners.
+    pub rpc_user: Option<String>,
+    pub rpc_pass: Option<String>,
+    /// Policy used to accept the channels opened by other nodes.
+    pub inbound_policy: InboundChannelPolicy,
+    /// Rapid Gossip Sync snapshot, a local file or the URL
+    /// of a server (e.g. `https://rapidsync.lightningdevkit.org/snapshot`).
+    pub rgs_source: Option<String>,
+    /// How often (in seconds) the Rapid Gossip Sync snapshot is refreshed.
+    pub rgs_refresh_interval: Option<u64>,
+    /// Negotiate the anchor outputs when opening a channel.
+    pub anchor_channels: bool,
+    /// On chain funds (in sats) kept for each anchor channel,
+    /// to be able to bump the fee of the commitment transaction.
+    pub anchor_reserve_sat: Option<u64>,
+    /// CLTV delta asked to the last hop of the keysend payments.
+    pub final_cltv_expiry_delta: Option<u32>,
+}
+
+/// Policy used to accept the channels opened by other nodes,
+/// by default every channel is accepted.
+#[derive(Clone, Debug, Default)]
+pub struct InboundChannelPolicy {
+    pub min_funding_sat: Option<u64>,
+    pub max_funding_sat: Option<u64>,
+    /// When it is not empty, only these peers can open a channel.
+    pub allow_peers: Vec<NodeId>,
+    pub deny_peers: Vec<NodeId>,
+    /// Max number of channels opened by a peer that are not ready yet.
+    pub max_pending_channels: Option<usize>,
+    /// Max number of channels opened by any peer that are not ready yet,
+    /// so many peers can not open pending channels at once.
+    pub max_total_pending_channels: Option<usize>,
+    /// Peers trusted to open zero-conf channels.
+    pub zero_conf_peers: Vec<NodeId>,
+}
+
+impl InboundChannelPolicy {
+    /// Check the request to open a channel, and return the
+    /// reason of the rejection when it is not accepted.
+    ///
+    /// `pending_channels` are the channels opened by the same
+    /// `peer` that are not ready yet, `total_pending_channels` the
+    /// ones opened by any peer.
+    pub fn check(
+        &self,
+        peer: &NodeId,
+        funding_sat: u64,
+        pending_channels: usize,
+        total_pending_channels: usize,
+    ) -> Result<(), String> {
+        if self.deny_peers.contains(peer) {
+            return Err(format!("peer `{peer}` is inside the deny list"));
+        }
+        if !self.allow_peers.is_empty() && !self.allow_peers.contains(peer) {
+            return Err(format!("peer `{peer}` is not inside the allow list"));
+        }
+        if let Some(min) = self.min_funding_sat {
+            if funding_sat < min {
+                return Err(format!(
+                    "funding of {funding_sat} sats is below the minimum of {min} sats"
+                ));
+            }
+        }
+        if let Some(max) = self.max_funding_sat {
+            if funding_sat > max {
+                return Err(format!(
+                    "funding of {funding_sat} sats is above the maximum of {max} sats"
+                ));
+            }
+        }
+        if let Some(max) = self.max_pending_channels {
+            if pending_channels >= max {
+                return Err(format!(
+                    "peer `{peer}` has already {pending_channels} pending channels"
+                ));
+            }
+        }
+        if let Some(max) = self.max_total_pending_channels {
+            if total_pending_channels >= max {
+                return Err(format!(
+                    "the node has already {total_pending_channels} pending channels"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_zero_conf(&self, peer: &NodeId) -> bool {
+        self.zero_conf_peers.contains(peer)
+    }
 }
 
 impl LampoConf {
@@ -38,7 +139,7 @@ impl LampoConf {
             inner: None,
             // default network is testnet
             network: Network::Testnet,
-            ldk_conf: UserConfig::default(),
+            ldk_conf: Self::default_ldk_conf(),
             // default port is 19735 for testnet
             port: 19735,
             root_path: lampo_home,
@@ -52,9 +153,28 @@ impl LampoConf {
             log_file: None,
             alias: None,
             announce_addr: None,
+            rpc_tcp_bind: None,
+            rpc_http_bind: None,
+            rpc_token: None,
+            rpc_user: None,
+            rpc_pass: None,
+            inbound_policy: InboundChannelPolicy::default(),
+            rgs_source: None,
+            rgs_refresh_interval: None,
+            anchor_channels: false,
+            anchor_reserve_sat: None,
+            final_cltv_expiry_delta: None,
         }
     }
 
+    /// The inbound channels are accepted by lampo, with
+    /// the `InboundChannelPolicy`.
+    fn default_ldk_conf() -> UserConfig {
+        let mut conf = UserConfig::default();
+        conf.manually_accept_inbound_channels = true;
+        conf
+    }
+
     pub fn prepare_dirs(&self) -> Result<(), anyhow::Error> {
         Self::prepare_directories(&self.root_path, Some(self.network))
     }
@@ -219,12 +339,48 @@ impl TryFrom<String> for LampoConf {
         let log_file = conf.get_conf("log-file").unwrap_or_else(|_| None);
         let alias = conf.get_conf("alias").unwrap_or(None);
         let announce_addr = conf.get_conf("announce-addr").unwrap_or_else(|_| None);
+        let rpc_tcp_bind = conf.get_conf("rpc-tcp-bind").unwrap_or(None);
+        let rpc_http_bind = conf.get_conf("rpc-http-bind").unwrap_or(None);
+        let rpc_token = conf.get_conf("rpc-token").unwrap_or(None);
+        let rpc_user = conf.get_conf("rpc-user").unwrap_or(None);
+        let rpc_pass = conf.get_conf("rpc-pass").unwrap_or(None);
+        let inbound_policy = Self::parse_inbound_policy(&conf)?;
+        let rgs_source = conf.get_conf("rgs-source").unwrap_or(None);
+        let rgs_refresh_interval = conf
+            .get_conf("rgs-refresh-interval")
+            .unwrap_or(None)
+            .map(|interval| u64::from_str(&interval.to_trimmed()))
+            .transpose()
+            .map_err(|err| anyhow::anyhow!("invalid `rgs-refresh-interval`: {err}"))?;
+        let anchor_channels = conf
+            .get_conf("anchor-channels")
+            .unwrap_or(None)
+            .map(|enabled| bool::from_str(&enabled.to_trimmed()))
+            .transpose()
+            .map_err(|err| anyhow::anyhow!("invalid `anchor-channels`: {err}"))?
+            .unwrap_or(false);
+        let anchor_reserve_sat = conf
+            .get_conf("anchor-reserve-sat")
+            .unwrap_or(None)
+            .map(|reserve| u64::from_str(&reserve.to_trimmed()))
+            .transpose()
+            .map_err(|err| anyhow::anyhow!("invalid `anchor-reserve-sat`: {err}"))?;
+        let final_cltv_expiry_delta = conf
+            .get_conf("final-cltv-expiry-delta")
+            .unwrap_or(None)
+            .map(|delta| u32::from_str(&delta.to_trimmed()))
+            .transpose()
+            .map_err(|err| anyhow::anyhow!("invalid `final-cltv-expiry-delta`: {err}"))?;
+        let mut ldk_conf = Self::default_ldk_conf();
+        ldk_conf
+            .channel_handshake_config
+            .negotiate_anchors_zero_fee_htlc_tx = anchor_channels;
 
         Ok(Self {
             inner: Some(conf),
             root_path,
             network,
-            ldk_conf: UserConfig::default(),
+            ldk_conf,
             port: u64::from_str(&port)?,
             node,
             core_url,
@@ -236,6 +392,17 @@ impl TryFrom<String> for LampoConf {
             log_level: level,
             alias,
             announce_addr,
+            rpc_tcp_bind: rpc_tcp_bind.map(|addr| addr.to_trimmed()),
+            rpc_http_bind: rpc_http_bind.map(|addr| addr.to_trimmed()),
+            rpc_token: rpc_token.map(|token| token.to_trimmed()),
+            rpc_user: rpc_user.map(|user| user.to_trimmed()),
+            rpc_pass: rpc_pass.map(|pass| pass.to_trimmed()),
+            inbound_policy,
+            rgs_source: rgs_source.map(|source| source.to_trimmed()),
+            rgs_refresh_interval,
+            anchor_channels,
+            anchor_reserve_sat,
+            final_cltv_expiry_delta,
         })
     }
 }
@@ -264,6 +431,36 @@ impl LampoConf {
         Ok(Some(value))
     }
 
+    fn parse_inbound_policy(conf: &CLNConf) -> Result<InboundChannelPolicy, anyhow::Error> {
+        let number = |key: &str| -> Result<Option<u64>, anyhow::Error> {
+            let Some(value) = conf.get_conf(key).map_err(|err| anyhow::anyhow!("{err}"))? else {
+                return Ok(None);
+            };
+            let value = u64::from_str(&value.to_trimmed())
+                .map_err(|err| anyhow::anyhow!("invalid `{key}`: {err}"))?;
+            Ok(Some(value))
+        };
+        let peers = |key: &str| -> Result<Vec<NodeId>, anyhow::Error> {
+            conf.get_confs(key)
+                .into_iter()
+                .map(|peer| {
+                    NodeId::from_str(&peer.to_trimmed())
+                        .map_err(|err| anyhow::anyhow!("invalid `{key}` `{peer}`: {err}"))
+                })
+                .collect()
+        };
+        Ok(InboundChannelPolicy {
+            min_funding_sat: number("inbound-min-funding-sat")?,
+            max_funding_sat: number("inbound-max-funding-sat")?,
+            allow_peers: peers("inbound-allow-peer")?,
+            deny_peers: peers("inbound-deny-peer")?,
+            max_pending_channels: number("inbound-max-pending-channels")?.map(|max| max as usize),
+            max_total_pending_channels: number("inbound-max-total-pending-channels")?
+                .map(|max| max as usize),
+            zero_conf_peers: peers("zero-conf-peer")?,
+        })
+    }
+
     pub fn set_network(&mut self, network: &str) -> anyhow::Result<()> {
         self.network = Network::from_str(network)?;
         Ok(())
@@ -280,3 +477,98 @@ impl TrimmedString for String {
         self.trim().to_owned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::types::NodeId;
+
+    use super::InboundChannelPolicy;
+
+    fn node_id(id: &str) -> NodeId {
+        NodeId::from_str(id).unwrap()
+    }
+
+    fn peers() -> (NodeId, NodeId) {
+        (
+            node_id("02049b60c296ffead3e7c8b124c5730153403a8314c1116c2d1b43cf9ac0de2d9d"),
+            node_id("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+        )
+    }
+
+    #[test]
+    fn accept_every_channel_by_default() {
+        let (peer, _) = peers();
+        let policy = InboundChannelPolicy::default();
+        assert!(policy.check(&peer, 1, 100, 100).is_ok());
+        assert!(!policy.is_zero_conf(&peer));
+    }
+
+    #[test]
+    fn check_funding_limits() {
+        let (peer, _) = peers();
+        let policy = InboundChannelPolicy {
+            min_funding_sat: Some(100_000),
+            max_funding_sat: Some(1_000_000),
+            ..Default::default()
+        };
+        assert!(policy.check(&peer, 99_999, 0, 0).is_err());
+        assert!(policy.check(&peer, 100_000, 0, 0).is_ok());
+        assert!(policy.check(&peer, 1_000_000, 0, 0).is_ok());
+        assert!(policy.check(&peer, 1_000_001, 0, 0).is_err());
+    }
+
+    #[test]
+    fn check_allow_and_deny_list() {
+        let (allowed, other) = peers();
+        let policy = InboundChannelPolicy {
+            allow_peers: vec![allowed],
+            ..Default::default()
+        };
+        assert!(policy.check(&allowed, 100_000, 0, 0).is_ok());
+        let err = policy.check(&other, 100_000, 0, 0).unwrap_err();
+        assert!(err.contains("allow list"), "{err}");
+
+        // the deny list wins over the allow list.
+        let policy = InboundChannelPolicy {
+            allow_peers: vec![allowed],
+            deny_peers: vec![allowed],
+            ..Default::default()
+        };
+        let err = policy.check(&allowed, 100_000, 0, 0).unwrap_err();
+        assert!(err.contains("deny list"), "{err}");
+    }
+
+    #[test]
+    fn check_pending_channels_per_peer() {
+        let (peer, _) = peers();
+        let policy = InboundChannelPolicy {
+            max_pending_channels: Some(2),
+            ..Default::default()
+        };
+        assert!(policy.check(&peer, 100_000, 0, 0).is_ok());
+        assert!(policy.check(&peer, 100_000, 1, 1).is_ok());
+        assert!(policy.check(&peer, 100_000, 2, 2).is_err());
+
+        let policy = InboundChannelPolicy {
+            max_pending_channels: Some(0),
+            ..Default::default()
+        };
+        assert!(policy.check(&peer, 100_000, 0, 0).is_err());
+    }
+
+    #[test]
+    fn check_pending_channels_of_the_node() {
+        let (peer, _) = peers();
+        let policy = InboundChannelPolicy {
+            max_pending_channels: Some(2),
+            max_total_pending_channels: Some(3),
+            ..Default::default()
+        };
+        assert!(policy.check(&peer, 100_000, 0, 2).is_ok());
+        let err = policy.check(&peer, 100_000, 0, 3).unwrap_err();
+        assert!(err.contains("the node has already 3"), "{err}");
+        assert!(policy.check(&peer, 100_000, 2, 2).is_err());
+    }
+}
@@ -1,14 +1,16 @@
 use crate::bitcoin::{OutPoint, Transaction};
 use crate::ldk::ln::features::ChannelTypeFeatures;
-use crate::model::response::{PaymentHop, PaymentState};
+use crate::model::response::{PaymentFailure, PaymentHop, PaymentState};
 use crate::types::{ChannelId, ChannelState, NodeId};
 
 #[derive(Clone, Debug)]
 pub enum LightningEvent {
-    // FIXME: add new peer event
     PeerConnect {
         counterparty_node_id: NodeId,
     },
+    PeerDisconnect {
+        counterparty_node_id: NodeId,
+    },
     ChannelPending {
         counterparty_node_id: NodeId,
         funding_transaction: OutPoint,
@@ -18,6 +20,13 @@ pub enum LightningEvent {
         channel_id: ChannelId,
         channel_type: ChannelTypeFeatures,
     },
+    /// A channel opened by the peer was rejected by the `InboundChannelPolicy`.
+    ChannelRejected {
+        counterparty_node_id: NodeId,
+        temporary_channel_id: ChannelId,
+        channel_value_satoshis: u64,
+        reason: String,
+    },
     FundingChannelStart {
         counterparty_node_id: NodeId,
         temporary_channel_id: ChannelId,
@@ -30,9 +39,20 @@ pub enum LightningEvent {
         funding_transaction: Transaction,
     },
     PaymentEvent {
+        payment_id: String,
         state: PaymentState,
         payment_hash: Option<String>,
         path: Vec<PaymentHop>,
+        payment_preimage: Option<String>,
+        fee_msat: Option<u64>,
+        failure: Option<PaymentFailure>,
+    },
+    /// One path of a payment was delivered, the payment itself
+    /// is terminated by the `PaymentEvent`.
+    PaymentPathSuccessful {
+        payment_id: String,
+        payment_hash: Option<String>,
+        path: Vec<PaymentHop>,
     },
     ChannelEvent {
         state: ChannelState,
@@ -15,6 +15,7 @@ pub mod ldk {
     pub use lightning_invoice as invoice;
     pub use lightning_net_tokio as net;
     pub use lightning_persister as persister;
+    pub use lightning_rapid_gossip_sync as rapid_gossip_sync;
 }
 
 pub mod error {
@@ -1,11 +1,16 @@
 mod close_channel;
 mod connect;
+mod forwards;
 mod getinfo;
+mod gossip;
 mod invoice;
 mod keysend;
 mod new_addr;
 mod on_chain;
 mod open_channel;
+mod payments;
+mod peers;
+mod route;
 
 pub use connect::Connect;
 pub use getinfo::GetInfo;
@@ -13,22 +18,31 @@ pub use getinfo::GetInfo;
 pub mod request {
     pub use crate::model::close_channel::request::*;
     pub use crate::model::connect::Connect;
+    pub use crate::model::forwards::request::*;
     pub use crate::model::getinfo::*;
+    pub use crate::model::gossip::request::*;
     pub use crate::model::invoice::request::*;
     pub use crate::model::keysend::request::*;
     pub use crate::model::new_addr::request::*;
-    #[allow(unused_imports)]
     pub use crate::model::on_chain::request::*;
     pub use crate::model::open_channel::request::*;
+    pub use crate::model::payments::request::*;
+    pub use crate::model::peers::request::*;
+    pub use crate::model::route::request::*;
 }
 
 pub mod response {
     pub use crate::model::close_channel::response::*;
     pub use crate::model::connect::Connect;
+    pub use crate::model::forwards::response::*;
     pub use crate::model::getinfo::*;
+    pub use crate::model::gossip::response::*;
     pub use crate::model::invoice::response::*;
     pub use crate::model::keysend::response::*;
     pub use crate::model::new_addr::response::*;
     pub use crate::model::on_chain::response::*;
     pub use crate::model::open_channel::response::*;
+    pub use crate::model::payments::response::*;
+    pub use crate::model::peers::response::*;
+    pub use crate::model::route::response::*;
 }
@@ -13,6 +13,16 @@ pub mod request {
         pub node_id: String,
         // Hex of the channel
         pub channel_id: Option<String>,
+        /// Broadcast our latest commitment transaction, without
+        /// waiting for the peer.
+        pub force: Option<bool>,
+        /// Address where our funds are sent by the cooperative close.
+        pub destination: Option<String>,
+        /// Feerate (in sats per 1000 weight) of the closing transaction.
+        pub feerate: Option<u32>,
+        /// Seconds to wait for the cooperative close before
+        /// force closing the channel, at most 5 minutes.
+        pub unilateral_timeout: Option<u64>,
     }
 
     impl CloseChannel {
@@ -72,6 +82,10 @@ pub mod tests {
         let req = crate::model::request::CloseChannel {
             node_id: node_id.clone(),
             channel_id: channel_hex,
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: None,
         };
         let channel_bytes = [
             10, 68, 103, 117, 38, 172, 140, 96, 118, 22, 189, 145, 37, 141, 126, 93, 241, 216, 111,
@@ -0,0 +1,59 @@
+//! Model for the payments forwarded by the node
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    use super::response::ForwardStatus;
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListForwards {
+        pub status: Option<ForwardStatus>,
+        /// Only the forwards resolved after this timestamp.
+        pub from: Option<u64>,
+        /// Only the forwards resolved before this timestamp.
+        pub to: Option<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ForwardStatus {
+        Settled,
+        Failed,
+    }
+
+    /// An HTLC forwarded by the node.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ForwardRecord {
+        pub in_channel_id: Option<String>,
+        pub out_channel_id: Option<String>,
+        pub in_msat: Option<u64>,
+        pub out_msat: Option<u64>,
+        pub fee_msat: Option<u64>,
+        pub status: ForwardStatus,
+        pub failure: Option<String>,
+        /// The HTLC was claimed with an on-chain transaction.
+        pub claim_from_onchain_tx: bool,
+        pub resolved_at: u64,
+    }
+
+    /// Fees earned by the node for the forwards over a channel.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ChannelForwardFees {
+        /// The channel used to forward the payments.
+        pub channel_id: Option<String>,
+        pub forwards: usize,
+        pub out_msat: u64,
+        pub fee_msat: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Forwards {
+        pub forwards: Vec<ForwardRecord>,
+        /// Fees earned per channel by the settled forwards listed.
+        pub fees: Vec<ChannelForwardFees>,
+    }
+}
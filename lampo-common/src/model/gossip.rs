@@ -0,0 +1,142 @@
+//! Model for the network graph learned from the gossip
+
+pub mod request {
+    use bitcoin::secp256k1::PublicKey;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListNodes {
+        pub node_id: Option<PublicKey>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListGossipChannels {
+        pub short_channel_id: Option<u64>,
+        /// Return only the channels of the node.
+        pub node_id: Option<PublicKey>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::ldk::routing::gossip::{ChannelInfo, ChannelUpdateInfo, NodeId, NodeInfo};
+
+    /// A node of the network graph.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GossipNode {
+        pub node_id: String,
+        pub alias: Option<String>,
+        pub addresses: Vec<String>,
+        /// Features announced by the node, hex encoded.
+        pub features: Option<String>,
+        pub last_update: Option<u32>,
+        pub channels: usize,
+    }
+
+    impl From<(&NodeId, &NodeInfo)> for GossipNode {
+        fn from((node_id, info): (&NodeId, &NodeInfo)) -> Self {
+            let announcement = info.announcement_info.as_ref();
+            Self {
+                node_id: node_id.to_string(),
+                alias: announcement.map(|info| info.alias.to_string()),
+                addresses: announcement
+                    .map(|info| {
+                        info.addresses()
+                            .iter()
+                            .map(|addr| addr.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                // Nb. the features are encoded in big endian, as in the gossip messages.
+                features: announcement.map(|info| {
+                    hex::encode(
+                        info.features
+                            .le_flags()
+                            .iter()
+                            .rev()
+                            .copied()
+                            .collect::<Vec<u8>>(),
+                    )
+                }),
+                last_update: announcement.map(|info| info.last_update),
+                channels: info.channels.len(),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Nodes {
+        pub nodes: Vec<GossipNode>,
+    }
+
+    /// The fee policy of one direction of a channel.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ChannelPolicy {
+        pub enabled: bool,
+        pub fee_base_msat: u32,
+        pub fee_proportional_millionths: u32,
+        pub cltv_expiry_delta: u16,
+        pub htlc_minimum_msat: u64,
+        pub htlc_maximum_msat: u64,
+        pub last_update: u32,
+    }
+
+    impl From<&ChannelUpdateInfo> for ChannelPolicy {
+        fn from(value: &ChannelUpdateInfo) -> Self {
+            Self {
+                enabled: value.enabled,
+                fee_base_msat: value.fees.base_msat,
+                fee_proportional_millionths: value.fees.proportional_millionths,
+                cltv_expiry_delta: value.cltv_expiry_delta,
+                htlc_minimum_msat: value.htlc_minimum_msat,
+                htlc_maximum_msat: value.htlc_maximum_msat,
+                last_update: value.last_update,
+            }
+        }
+    }
+
+    /// A public channel of the network graph.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GossipChannel {
+        pub short_channel_id: u64,
+        pub node_one: String,
+        pub node_two: String,
+        pub capacity_sat: Option<u64>,
+        /// Policy used to forward from `node_one` to `node_two`.
+        pub one_to_two: Option<ChannelPolicy>,
+        /// Policy used to forward from `node_two` to `node_one`.
+        pub two_to_one: Option<ChannelPolicy>,
+    }
+
+    impl From<(u64, &ChannelInfo)> for GossipChannel {
+        fn from((short_channel_id, info): (u64, &ChannelInfo)) -> Self {
+            Self {
+                short_channel_id,
+                node_one: info.node_one.to_string(),
+                node_two: info.node_two.to_string(),
+                capacity_sat: info.capacity_sats,
+                one_to_two: info.one_to_two.as_ref().map(ChannelPolicy::from),
+                two_to_one: info.two_to_one.as_ref().map(ChannelPolicy::from),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GossipChannels {
+        pub channels: Vec<GossipChannel>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GraphStats {
+        pub nodes: usize,
+        /// Nodes that sent a node announcement.
+        pub announced_nodes: usize,
+        pub channels: usize,
+        /// Channels with at least one direction disabled.
+        pub disabled_channels: usize,
+        /// Channels without any channel update yet.
+        pub channels_without_updates: usize,
+        pub total_capacity_sat: u64,
+    }
+}
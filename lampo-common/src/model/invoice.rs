@@ -1,10 +1,13 @@
 //! Model for the invoice stuff
 
 pub mod request {
+    use bitcoin::secp256k1::PublicKey;
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct GenerateInvoice {
+        /// Unique label used to find the invoice in `listinvoices`.
+        pub label: Option<String>,
         pub amount_msat: Option<u64>,
         pub description: String,
         pub expiring_in: Option<u32>,
@@ -26,6 +29,38 @@ pub mod request {
     pub struct Pay {
         pub invoice_str: String,
         pub amount: Option<u64>,
+        /// Return the `payment_id` right away instead of waiting
+        /// for the payment to complete.
+        #[serde(rename = "async")]
+        pub async_pay: Option<bool>,
+        /// How many seconds to wait for the payment to complete (at
+        /// most 5 minutes), after that the pending payment is returned.
+        pub timeout: Option<u64>,
+        #[serde(flatten)]
+        pub options: PayOptions,
+    }
+
+    /// Options that control how a payment is routed, when
+    /// not specified the defaults of the node are used.
+    #[derive(Clone, Serialize, Deserialize, Debug, Default)]
+    pub struct PayOptions {
+        /// Max fee paid to the routing nodes.
+        pub maxfee_msat: Option<u64>,
+        /// Max fee paid to the routing nodes, as a percentage
+        /// of the amount paid.
+        pub maxfeepercent: Option<f64>,
+        /// How many times the payment is retried.
+        pub retry: Option<u32>,
+        /// For how many seconds the payment is retried.
+        pub retry_for: Option<u64>,
+        /// Max number of parts the payment can be split into.
+        pub max_parts: Option<u8>,
+        /// Max total CLTV delta of the route.
+        pub max_cltv_expiry: Option<u32>,
+        #[serde(default)]
+        pub exclude_nodes: Vec<PublicKey>,
+        #[serde(default)]
+        pub exclude_channels: Vec<u64>,
     }
 }
 
@@ -75,7 +110,22 @@ pub mod response {
         pub path: Vec<PaymentHop>,
         pub payment_hash: Option<String>,
         pub state: PaymentState,
-        // FIXME: missing payment preimage
+        pub payment_preimage: Option<String>,
+        /// Total fees paid to the routing nodes.
+        pub fee_msat: Option<u64>,
+        pub failure: Option<PaymentFailure>,
+    }
+
+    /// Why a payment failed.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct PaymentFailure {
+        /// Why the payment was abandoned, e.g. `RetriesExhausted`.
+        pub reason: String,
+        /// Why the last attempt failed.
+        pub last_error: Option<String>,
+        /// The channel where the last attempt failed, if known.
+        pub short_channel_id: Option<u64>,
+        pub failing_hop: Option<PaymentHop>,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
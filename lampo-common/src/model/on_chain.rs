@@ -1,4 +1,47 @@
-pub mod request {}
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    use crate::error;
+
+    /// Send on chain funds from the wallet to an address.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Withdraw {
+        pub destination: String,
+        /// Amount in sats, or `all` to send all the confirmed funds.
+        pub amount: WithdrawAmount,
+        /// Feerate (in sats per 1000 weight) of the transaction.
+        pub feerate: Option<u32>,
+        /// Blocks within which the transaction should be confirmed,
+        /// used to estimate the feerate when it is not specified.
+        pub conf_target: Option<u64>,
+        /// UTXOs (as `txid:vout`) spent by the transaction,
+        /// by default the wallet selects them.
+        pub utxos: Option<Vec<String>>,
+        /// Allow to replace the transaction (BIP 125), by default true.
+        pub rbf: Option<bool>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(untagged)]
+    pub enum WithdrawAmount {
+        Sats(u64),
+        All(String),
+    }
+
+    impl WithdrawAmount {
+        /// Return the amount in sats, or `None` when all
+        /// the funds are withdrawn.
+        pub fn sats(&self) -> error::Result<Option<u64>> {
+            match self {
+                Self::Sats(amount) => Ok(Some(*amount)),
+                Self::All(all) if all == "all" => Ok(None),
+                Self::All(amount) => {
+                    error::bail!("invalid amount `{amount}`, expected sats or `all`")
+                }
+            }
+        }
+    }
+}
 
 pub mod response {
     use serde::{Deserialize, Serialize};
@@ -16,4 +59,28 @@ pub mod response {
     pub struct Utxos {
         pub transactions: Vec<Utxo>,
     }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Withdraw {
+        pub txid: String,
+        /// The transaction broadcasted, in hex.
+        pub tx: String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json;
+
+    use super::request::WithdrawAmount;
+
+    #[test]
+    fn withdraw_amount_in_sats() {
+        let amount: WithdrawAmount = json::from_value(json::json!(1000)).unwrap();
+        assert_eq!(amount.sats().unwrap(), Some(1000));
+        let amount: WithdrawAmount = json::from_value(json::json!("all")).unwrap();
+        assert_eq!(amount.sats().unwrap(), None);
+        let amount: WithdrawAmount = json::from_value(json::json!("1000sat")).unwrap();
+        assert!(amount.sats().is_err());
+    }
 }
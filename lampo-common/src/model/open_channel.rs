@@ -14,7 +14,54 @@ pub mod request {
         pub addr: Option<String>,
         pub port: Option<u64>,
         pub amount: u64,
+        /// Announce the channel to the network.
+        #[serde(default, alias = "announce")]
         pub public: bool,
+        #[serde(flatten)]
+        pub options: OpenChannelOptions,
+    }
+
+    /// Options of a single channel, that override the
+    /// node configuration.
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    pub struct OpenChannelOptions {
+        /// Amount pushed to the peer when the channel is opened.
+        pub push_msat: Option<u64>,
+        /// Blocks that the peer has to wait to claim its funds,
+        /// when it force closes the channel.
+        pub to_self_delay: Option<u16>,
+        /// Feerate (in sats per 1000 weight) of the funding transaction.
+        pub feerate: Option<u32>,
+        /// Blocks within which the funding transaction should be
+        /// confirmed, used to estimate the feerate when it is not specified.
+        pub conf_target: Option<u64>,
+        /// Address where our funds are sent when the channel is closed.
+        pub close_to: Option<String>,
+        /// Features of the channel, by default the features are
+        /// negotiated with the node configuration.
+        pub channel_type: Option<Vec<ChannelTypeFeature>>,
+        /// Id of the channel chosen by the user, that is reported
+        /// by the channel events.
+        pub user_channel_id: Option<u128>,
+        /// UTXOs (as `txid:vout`) spent by the funding transaction,
+        /// by default the wallet selects them.
+        pub utxos: Option<Vec<String>>,
+        /// Minimum confirmations of the UTXOs selected by the wallet.
+        pub minconf: Option<u32>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ChannelTypeFeature {
+        /// Use the anchor outputs, the fee of the commitment
+        /// transaction is bumped with the on chain funds.
+        Anchors,
+        /// Use the channel before the funding transaction is
+        /// confirmed, when the peer allows it.
+        ZeroConf,
+        /// Use an alias instead of the real short channel id,
+        /// only for channels that are not announced.
+        ScidAlias,
     }
 
     impl OpenChannel {
@@ -23,6 +70,53 @@ pub mod request {
             Ok(node_id)
         }
     }
+
+    /// Open more channels funded by the same transaction.
+    ///
+    /// The funding options are shared by all the channels, so they
+    /// can not be specified inside the destinations.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct MultiFundChannel {
+        pub destinations: Vec<OpenChannel>,
+        /// Feerate (in sats per 1000 weight) of the funding transaction.
+        pub feerate: Option<u32>,
+        /// Blocks within which the funding transaction should be
+        /// confirmed, used to estimate the feerate when it is not specified.
+        pub conf_target: Option<u64>,
+        /// UTXOs (as `txid:vout`) spent by the funding transaction,
+        /// by default the wallet selects them.
+        pub utxos: Option<Vec<String>>,
+        /// Minimum confirmations of the UTXOs selected by the wallet.
+        pub minconf: Option<u32>,
+    }
+
+    /// Complete the opening of a channel started with `fundchannel_start`,
+    /// with a funding transaction signed outside of the node.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct FundChannelComplete {
+        pub temporary_channel_id: String,
+        /// The signed and finalized PSBT, in base64.
+        pub psbt: Option<String>,
+        /// The signed raw transaction, in hex.
+        pub tx: Option<String>,
+    }
+
+    /// Update the forwarding fees of one channel, of all the channels
+    /// with a peer or of all the channels when no filter is specified.
+    ///
+    /// Nb. the HTLC limits are negotiated when the channel is opened,
+    /// and LDK does not allow to change them later, so an unknown
+    /// field (e.g. `htlc_maximum_msat`) is refused instead of ignored.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    pub struct SetChannelFee {
+        pub channel_id: Option<String>,
+        pub short_channel_id: Option<u64>,
+        pub peer_id: Option<String>,
+        pub fee_base_msat: Option<u32>,
+        pub fee_ppm: Option<u32>,
+        pub cltv_expiry_delta: Option<u16>,
+    }
 }
 
 pub mod response {
@@ -32,6 +126,7 @@ pub mod response {
 
     use crate::bitcoin::Transaction;
     use crate::error;
+    use crate::ldk::util::config::ChannelConfig;
     use crate::types::NodeId;
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -46,9 +141,47 @@ pub mod response {
         pub public: bool,
         pub push_mst: u64,
         pub to_self_delay: u64,
+        /// Id of the channel until the funding transaction is created.
+        pub temporary_channel_id: String,
+        pub user_channel_id: u128,
+        pub state: OpenChannelState,
+        /// The funding transaction, that is not known while the
+        /// channel is pending.
         pub tx: Option<Transaction>,
     }
 
+    #[derive(Serialize, Deserialize)]
+    pub struct MultiFundChannel {
+        pub channels: Vec<OpenChannel>,
+    }
+
+    /// The funding output that the transaction built
+    /// outside of the node has to pay.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct FundChannelStart {
+        pub node_id: String,
+        pub temporary_channel_id: String,
+        pub user_channel_id: u128,
+        pub funding_address: String,
+        /// The script of the funding output, in hex.
+        pub scriptpubkey: String,
+        pub amount: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct FundChannelComplete {
+        pub channel_id: String,
+        pub txid: String,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OpenChannelState {
+        /// Waiting that the peer accepts the channel, the funding
+        /// transaction is reported by the `funding_end` event.
+        Pending,
+    }
+
     impl OpenChannel {
         pub fn node_id(&self) -> error::Result<NodeId> {
             let node_id = NodeId::from_str(&self.node_id)?;
@@ -69,5 +202,41 @@ pub mod response {
         pub public: bool,
         pub available_balance_for_send_msat: u64,
         pub available_balance_for_recv_msat: u64,
+        /// Fees charged to forward the payments over the channel.
+        pub fee_config: Option<ChannelFeeConfig>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct ChannelFeeConfig {
+        pub fee_base_msat: u32,
+        pub fee_ppm: u32,
+        pub cltv_expiry_delta: u16,
+    }
+
+    impl From<ChannelConfig> for ChannelFeeConfig {
+        fn from(value: ChannelConfig) -> Self {
+            Self {
+                fee_base_msat: value.forwarding_fee_base_msat,
+                fee_ppm: value.forwarding_fee_proportional_millionths,
+                cltv_expiry_delta: value.cltv_expiry_delta,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json;
+
+    use super::request::SetChannelFee;
+
+    #[test]
+    fn set_channel_fee_refuses_the_htlc_limits() {
+        let request = json::json!({ "peer_id": "peer", "fee_ppm": 10 });
+        let request: SetChannelFee = json::from_value(request).unwrap();
+        assert_eq!(request.fee_ppm, Some(10));
+
+        let request = json::json!({ "fee_ppm": 10, "htlc_maximum_msat": 1000 });
+        assert!(json::from_value::<SetChannelFee>(request).is_err());
     }
 }
@@ -0,0 +1,130 @@
+//! Model for the payments and invoices stored by the node
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListInvoices {
+        pub label: Option<String>,
+        pub payment_hash: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListPayments {
+        pub payment_hash: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListSendPays {
+        pub payment_hash: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct PayStatus {
+        pub payment_id: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct WaitSendPay {
+        pub payment_id: String,
+        /// How many seconds to wait before giving up, by default
+        /// (and at most) we wait 5 minutes.
+        pub timeout: Option<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::PaymentHop;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum InvoiceStatus {
+        Unpaid,
+        Paid,
+        Expired,
+    }
+
+    /// An invoice issued by the node.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct InvoiceRecord {
+        pub label: Option<String>,
+        pub bolt11: String,
+        pub payment_hash: String,
+        pub description: Option<String>,
+        pub amount_msat: Option<u64>,
+        pub status: InvoiceStatus,
+        pub amount_received_msat: Option<u64>,
+        pub payment_preimage: Option<String>,
+        pub created_at: u64,
+        pub expires_at: u64,
+        pub paid_at: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Invoices {
+        pub invoices: Vec<InvoiceRecord>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum PaymentStatus {
+        Pending,
+        Complete,
+        Failed,
+    }
+
+    /// A payment sent by the node, that can be made by
+    /// more than one attempt (see `SendPayRecord`).
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct PaymentRecord {
+        pub payment_id: String,
+        pub payment_hash: Option<String>,
+        pub destination: Option<String>,
+        /// The bolt11 invoice or the bolt12 offer paid.
+        pub invoice: Option<String>,
+        pub amount_msat: Option<u64>,
+        pub fee_msat: Option<u64>,
+        pub status: PaymentStatus,
+        pub payment_preimage: Option<String>,
+        pub failure_reason: Option<String>,
+        /// `None` when the payment is known only by LDK, and
+        /// its creation time is unknown.
+        pub created_at: Option<u64>,
+        pub completed_at: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Payments {
+        pub payments: Vec<PaymentRecord>,
+    }
+
+    /// A single path attempted to deliver a payment.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct SendPayRecord {
+        pub payment_id: String,
+        pub payment_hash: Option<String>,
+        pub status: PaymentStatus,
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        pub path: Vec<PaymentHop>,
+        pub failure: Option<String>,
+        /// The channel where the attempt failed, if known.
+        pub short_channel_id: Option<u64>,
+        pub created_at: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct SendPays {
+        pub sendpays: Vec<SendPayRecord>,
+    }
+
+    /// A payment with all the attempts made so far.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct PaymentDetails {
+        #[serde(flatten)]
+        pub payment: PaymentRecord,
+        pub attempts: Vec<SendPayRecord>,
+    }
+}
@@ -0,0 +1,48 @@
+//! Model for the peers connected with the node
+
+pub mod request {
+    use bitcoin::secp256k1::PublicKey;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListPeers {
+        pub peer_id: Option<PublicKey>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Disconnect {
+        pub node_id: PublicKey,
+        /// Disconnect the peer also when there are
+        /// channels open with it.
+        pub force: Option<bool>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::Channel;
+
+    /// A peer connected with the node.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Peer {
+        pub node_id: String,
+        /// The address of the peer, when known.
+        pub address: Option<String>,
+        /// Features sent by the peer inside the `init` message.
+        pub features: String,
+        /// True when the connection was opened by the peer.
+        pub inbound: bool,
+        pub channels: Vec<Channel>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Peers {
+        pub peers: Vec<Peer>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Disconnect {
+        pub node_id: String,
+    }
+}
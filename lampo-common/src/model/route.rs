@@ -0,0 +1,56 @@
+//! Model for the routes computed by the node
+
+pub mod request {
+    use bitcoin::secp256k1::PublicKey;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GetRoute {
+        pub destination: PublicKey,
+        pub amount_msat: u64,
+        /// CLTV delta expected by the destination.
+        pub cltv: Option<u32>,
+        pub maxfee_msat: Option<u64>,
+        #[serde(default)]
+        pub exclude_nodes: Vec<PublicKey>,
+        #[serde(default)]
+        pub exclude_channels: Vec<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct RouteHop {
+        pub node_id: String,
+        pub short_channel_id: u64,
+        /// Amount that flows through the channel.
+        pub amount_msat: u64,
+        /// Fee taken by the node to forward the payment
+        /// to the next hop.
+        pub fee_msat: u64,
+        pub cltv_expiry_delta: u32,
+        /// Probability that the channel is able to forward the amount,
+        /// `None` when the channel is unknown to the scorer.
+        pub probability: Option<f64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct RoutePath {
+        pub hops: Vec<RouteHop>,
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        pub cltv_expiry_delta: u32,
+        pub probability: f64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Route {
+        pub paths: Vec<RoutePath>,
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        /// Probability of success estimated by the scorer.
+        pub probability: f64,
+    }
+}
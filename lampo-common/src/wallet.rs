@@ -1,11 +1,29 @@
 use std::sync::Arc;
 
-use crate::bitcoin::{ScriptBuf, Transaction};
+use crate::bitcoin::hashes::Hash;
+use crate::bitcoin::psbt::PartiallySignedTransaction;
+use crate::bitcoin::{OutPoint, ScriptBuf, Transaction, TxOut, WPubkeyHash};
 use crate::conf::LampoConf;
 use crate::error;
 use crate::keys::LampoKeys;
+use crate::ldk::events::bump_transaction;
 use crate::model::response::{NewAddress, Utxo};
 
+/// Build the UTXO used by LDK to bump the fees, `None` is
+/// returned when the output is not a P2WPKH.
+pub fn p2wpkh_utxo(outpoint: OutPoint, output: &TxOut) -> Option<bump_transaction::Utxo> {
+    if !output.script_pubkey.is_v0_p2wpkh() {
+        return None;
+    }
+    // the script is `OP_0 <20 bytes pubkey hash>`
+    let pubkey_hash = WPubkeyHash::from_slice(&output.script_pubkey.as_bytes()[2..]).ok()?;
+    Some(bump_transaction::Utxo::new_v0_p2wpkh(
+        outpoint,
+        output.value,
+        &pubkey_hash,
+    ))
+}
+
 /// Wallet manager trait that define a generic interface
 /// over Wallet implementation!
 pub trait WalletManager: Send + Sync {
@@ -30,16 +48,63 @@ pub trait WalletManager: Send + Sync {
 
     /// Create the transaction from a script and return the transaction
     /// to propagate to the network.
+    ///
+    /// When `inputs` is not empty the transaction spends only the
+    /// specified UTXOs, otherwise the wallet selects the UTXOs with at
+    /// least `minconf` confirmations.
     fn create_transaction(
         &self,
         script: ScriptBuf,
         amount_sat: u64,
         fee_rate: u32,
+        inputs: &[OutPoint],
+        minconf: Option<u32>,
+    ) -> error::Result<Transaction> {
+        self.create_batch_transaction(&[(script, amount_sat)], fee_rate, inputs, minconf)
+    }
+
+    /// Create a transaction that pays more outputs at once, e.g. to
+    /// fund more channels with the same transaction.
+    fn create_batch_transaction(
+        &self,
+        outputs: &[(ScriptBuf, u64)],
+        fee_rate: u32,
+        inputs: &[OutPoint],
+        minconf: Option<u32>,
+    ) -> error::Result<Transaction>;
+
+    /// Send `amount_sat` to the script, or all the confirmed funds of
+    /// the wallet when it is `None`, and return the signed transaction
+    /// to propagate to the network.
+    ///
+    /// When `inputs` is not empty the transaction spends only the
+    /// specified UTXOs, the `reserved` UTXOs (e.g. the ones selected to
+    /// fund a channel) are never spent.
+    fn withdraw(
+        &self,
+        script: ScriptBuf,
+        amount_sat: Option<u64>,
+        fee_rate: u32,
+        inputs: &[OutPoint],
+        reserved: &[OutPoint],
+        rbf: bool,
     ) -> error::Result<Transaction>;
 
     /// Return the list of transaction stored inside the wallet
     fn list_transactions(&self) -> error::Result<Vec<Utxo>>;
 
+    /// Return the confirmed UTXOs that can be used to bump
+    /// the fee of a transaction (e.g. with the anchor outputs).
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<bump_transaction::Utxo>>;
+
+    /// Return a script where to send the change of a transaction.
+    fn get_change_script(&self) -> error::Result<ScriptBuf>;
+
+    /// Sign the inputs of the psbt owned by the wallet, and return the
+    /// transaction. The inputs that are not owned by the wallet are left
+    /// unsigned.
+    fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> error::Result<Transaction>;
+
     /// Sync the wallet.
     fn sync(&self) -> error::Result<()>;
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::ops::Not;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use bdk::bitcoin::Amount;
@@ -19,14 +20,17 @@ use bitcoincore_rpc::{Auth, Client, RpcApi};
 use crate::bitcoin::PrivateKey;
 
 use lampo_common::bitcoin;
+use lampo_common::bitcoin::consensus::encode::serialize_hex;
 use lampo_common::bitcoin::consensus::Decodable;
+use lampo_common::bitcoin::psbt::PartiallySignedTransaction;
 use lampo_common::conf::{LampoConf, Network};
 use lampo_common::error;
 use lampo_common::json;
 use lampo_common::json::Deserialize;
 use lampo_common::keys::LampoKeys;
+use lampo_common::ldk::events::bump_transaction;
 use lampo_common::model::response::{NewAddress, Utxo};
-use lampo_common::wallet::WalletManager;
+use lampo_common::wallet::{p2wpkh_utxo, WalletManager};
 
 pub struct CoreWalletManager {
     rpc: Client,
@@ -93,6 +97,37 @@ impl CoreWalletManager {
         Ok((wallet, ldk_keys))
     }
 
+    /// Fund the transaction with the wallet and sign it.
+    fn fund_transaction(
+        &self,
+        inputs: &[bitcoin::OutPoint],
+        outputs: json::Value,
+        options: json::Value,
+    ) -> error::Result<bitcoin::Transaction> {
+        let inputs = inputs
+            .iter()
+            .map(|input| json::json!({ "txid": input.txid.to_string(), "vout": input.vout }))
+            .collect::<Vec<_>>();
+
+        let hex: String = self.rpc.call(
+            "createrawtransaction",
+            &[json::json!(inputs), outputs, json::json!(0)],
+        )?;
+
+        let tx: Tx = self.rpc.call(
+            "fundrawtransaction",
+            &[json::json!(hex), json::json!(options)],
+        )?;
+
+        let hex: Tx = self
+            .rpc
+            .call("signrawtransactionwithwallet", &[json::json!(tx.hex)])?;
+        let hex = hex.hex.unwrap();
+        let mut reader = HexIterator::new(&hex)?;
+        let object = Decodable::consensus_decode(&mut reader)?;
+        Ok(object)
+    }
+
     fn configure_bitcoin_wallet(
         rpc: &Client,
         conf: Arc<LampoConf>,
@@ -223,26 +258,33 @@ impl WalletManager for CoreWalletManager {
         ))
     }
 
-    fn create_transaction(
+    fn create_batch_transaction(
         &self,
-        script: bitcoin::ScriptBuf,
-        amount_sat: u64,
+        outputs: &[(bitcoin::ScriptBuf, u64)],
         fee_rate: u32,
+        inputs: &[bitcoin::OutPoint],
+        minconf: Option<u32>,
     ) -> error::Result<bitcoin::Transaction> {
-        let addr = bitcoin_bech32::WitnessProgram::from_scriptpubkey(
-            &script.as_bytes(),
-            match self.network {
-                Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
-                Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
-                Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
-                Network::Signet => bitcoin_bech32::constants::Network::Signet,
-                _ => error::bail!("network `{}` not supported", self.network),
-            },
-        )?
-        .to_address();
+        let network = match self.network {
+            Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
+            Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
+            Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
+            Network::Signet => bitcoin_bech32::constants::Network::Signet,
+            _ => error::bail!("network `{}` not supported", self.network),
+        };
         let mut map = HashMap::new();
-        map.insert(addr, Amount::from_sat(amount_sat).to_btc());
-        let options = json::json!({
+        for (script, amount_sat) in outputs {
+            let addr =
+                bitcoin_bech32::WitnessProgram::from_scriptpubkey(script.as_bytes(), network)?
+                    .to_address();
+            if map
+                .insert(addr.clone(), Amount::from_sat(*amount_sat).to_btc())
+                .is_some()
+            {
+                error::bail!("address `{addr}` is paid more than once");
+            }
+        }
+        let mut options = json::json!({
             // LDK gives us feerates in satoshis per KW but Bitcoin Core here expects fees
             // denominated in satoshis per vB. First we need to multiply by 4 to convert weight
             // units to virtual bytes, then divide by 1000 to convert KvB to vB.
@@ -253,28 +295,73 @@ impl WalletManager for CoreWalletManager {
             // LDK-based applications should enable RBF bumping and RBF bump either to a local
             // change address or to a new channel output negotiated with the same node.
             "replaceable": false,
-            "include_unsafe": true,
+            "include_unsafe": minconf.is_none(),
             "includeWatching": true,
-            "add_inputs": true,
+            // the user selected the UTXOs, so do not add others.
+            "add_inputs": inputs.is_empty(),
         });
+        if let Some(minconf) = minconf {
+            options["minconf"] = json::json!(minconf);
+        }
+        self.fund_transaction(inputs, json::json!(&map), options)
+    }
 
-        let hex: String = self.rpc.call(
-            "createrawtransaction",
-            &[json::json!([]), json::json!(&map), json::json!(0)],
-        )?;
-
-        let tx: Tx = self.rpc.call(
-            "fundrawtransaction",
-            &[json::json!(hex), json::json!(options)],
-        )?;
-
-        let hex: Tx = self
-            .rpc
-            .call("signrawtransactionwithwallet", &[json::json!(tx.hex)])?;
-        let hex = hex.hex.unwrap();
-        let mut reader = HexIterator::new(&hex)?;
-        let object = Decodable::consensus_decode(&mut reader)?;
-        Ok(object)
+    fn withdraw(
+        &self,
+        script: bitcoin::ScriptBuf,
+        amount_sat: Option<u64>,
+        fee_rate: u32,
+        inputs: &[bitcoin::OutPoint],
+        reserved: &[bitcoin::OutPoint],
+        rbf: bool,
+    ) -> error::Result<bitcoin::Transaction> {
+        let address = bitcoin::Address::from_script(&script, self.network)?;
+        let mut options = json::json!({
+            // see `create_batch_transaction` for the conversion of the feerate.
+            "fee_rate": fee_rate as f64 / 250.0,
+            "replaceable": rbf,
+            "add_inputs": inputs.is_empty(),
+        });
+        let (inputs, amount_sat) = match amount_sat {
+            Some(amount_sat) => (inputs.to_vec(), amount_sat),
+            None => {
+                // send all the funds, the fee is paid by the output.
+                let utxos = self
+                    .rpc
+                    .list_unspent(Some(1), None, None, None, None)?
+                    .into_iter()
+                    .filter(|utxo| utxo.spendable)
+                    .map(|utxo| (bitcoin::OutPoint::new(utxo.txid, utxo.vout), utxo.amount))
+                    .filter(|(outpoint, _)| !reserved.contains(outpoint))
+                    .filter(|(outpoint, _)| inputs.is_empty() || inputs.contains(outpoint))
+                    .collect::<Vec<_>>();
+                if utxos.is_empty() {
+                    error::bail!("no confirmed funds to withdraw");
+                }
+                if !inputs.is_empty() && utxos.len() != inputs.len() {
+                    error::bail!(
+                        "some of the utxos are not confirmed, reserved, or they are not owned by the wallet"
+                    );
+                }
+                options["add_inputs"] = json::json!(false);
+                options["subtractFeeFromOutputs"] = json::json!([0]);
+                let amount_sat = utxos.iter().map(|(_, amount)| amount.to_sat()).sum();
+                let inputs = utxos.into_iter().map(|(outpoint, _)| outpoint).collect();
+                (inputs, amount_sat)
+            }
+        };
+        let outputs = json::json!({
+            address.to_string(): Amount::from_sat(amount_sat).to_btc(),
+        });
+        if !inputs.is_empty() || reserved.is_empty() {
+            return self.fund_transaction(&inputs, outputs, options);
+        }
+        // Nb. `fundrawtransaction` does not select the locked UTXOs, so
+        // we lock the reserved ones while the wallet selects the inputs.
+        self.rpc.lock_unspent(reserved)?;
+        let tx = self.fund_transaction(&inputs, outputs, options);
+        self.rpc.unlock_unspent(reserved)?;
+        tx
     }
 
     fn get_onchain_address(&self) -> error::Result<NewAddress> {
@@ -308,6 +395,45 @@ impl WalletManager for CoreWalletManager {
         Ok(unspend)
     }
 
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<bump_transaction::Utxo>> {
+        let utxos = self
+            .rpc
+            .list_unspent(Some(1), None, None, Some(false), None)?
+            .into_iter()
+            // the wallet is using a BIP 84 descriptor, but the user
+            // may have imported others
+            .filter_map(|utxo| {
+                let output = bitcoin::TxOut {
+                    value: utxo.amount.to_sat(),
+                    script_pubkey: utxo.script_pub_key,
+                };
+                p2wpkh_utxo(bitcoin::OutPoint::new(utxo.txid, utxo.vout), &output)
+            })
+            .collect::<Vec<_>>();
+        Ok(utxos)
+    }
+
+    fn get_change_script(&self) -> error::Result<bitcoin::ScriptBuf> {
+        let addr: String = self.rpc.call("getrawchangeaddress", &["bech32".into()])?;
+        let addr = bitcoin::Address::from_str(&addr)?.require_network(self.network)?;
+        Ok(addr.script_pubkey())
+    }
+
+    fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> error::Result<bitcoin::Transaction> {
+        // Nb. bitcoin core signs only the inputs that it knows, the
+        // others are signed by the caller.
+        let tx: Tx = self.rpc.call(
+            "signrawtransactionwithwallet",
+            &[json::json!(serialize_hex(&psbt.unsigned_tx))],
+        )?;
+        let hex = tx.hex.ok_or(error::anyhow!(
+            "bitcoin core did not return the signed transaction"
+        ))?;
+        let mut reader = HexIterator::new(&hex)?;
+        let tx = Decodable::consensus_decode(&mut reader)?;
+        Ok(tx)
+    }
+
     fn restore(conf: Arc<LampoConf>, mnemonic_words: &str) -> error::Result<Self>
     where
         Self: Sized,
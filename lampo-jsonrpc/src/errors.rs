@@ -61,6 +61,17 @@ impl error::Error for Error {
     }
 }
 
+/// Invalid JSON was received by the server.
+pub const PARSE_ERROR: i32 = -32700;
+/// The JSON sent is not a valid Request object.
+pub const INVALID_REQUEST: i32 = -32600;
+/// The method does not exist / is not available.
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// Internal JSON-RPC error, e.g. the method panicked.
+pub const INTERNAL_ERROR: i32 = -32603;
+/// The client did not provide valid credentials.
+pub const UNAUTHORIZED: i32 = -32001;
+
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 /// A JSONRPCv2.0 spec compilant error object
@@ -0,0 +1,254 @@
+//! Incremental framing of the JSON RPC 2.0 stream.
+//!
+//! A unix socket is a byte stream, so a single request can be
+//! split across several reads, and several requests can arrive
+//! with the same read. The `Decoder` keeps the bytes of a single
+//! connection and returns every JSON value as soon as it is
+//! complete.
+use std::fmt;
+
+use serde_json::{Deserializer, Value};
+
+/// Max size of a single value, to avoid buffering forever
+/// a client that never terminates the request.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The value is not a valid JSON.
+    Json(serde_json::Error),
+    /// The value is bigger than the max size of the decoder.
+    TooLarge(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(err) => write!(f, "{err}"),
+            Error::TooLarge(size) => write!(f, "request bigger than {size} bytes"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    max_size: usize,
+    /// Bytes of the buffer already scanned looking for the
+    /// end of the first value, so a value split across many
+    /// reads is not scanned again from the start.
+    scanned: usize,
+    /// The closing brackets of the objects and arrays still open.
+    closing: Vec<u8>,
+    in_string: bool,
+    escape: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::with_max_size(MAX_FRAME_SIZE)
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_size(max_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_size,
+            scanned: 0,
+            closing: Vec::new(),
+            in_string: false,
+            escape: false,
+        }
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /// Append the bytes read from the stream to the buffer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Return true if the buffer is over the max size, so the
+    /// caller should stop reading until the buffer is decoded.
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() > self.max_size
+    }
+
+    /// Decode the next complete JSON value from the buffer.
+    ///
+    /// Return `None` when the buffer does not contain a complete
+    /// value yet, so the caller should wait for more bytes. When
+    /// the buffer contains invalid JSON, or a value bigger than the
+    /// max size, the error is returned and the buffer is cleared
+    /// because there is no way to find where the next value starts.
+    pub fn decode(&mut self) -> Option<Result<Value, Error>> {
+        if self.scanned == 0 {
+            // drop the whitespace between the values.
+            let start = self
+                .buffer
+                .iter()
+                .position(|byte| !byte.is_ascii_whitespace())
+                .unwrap_or(self.buffer.len());
+            self.buffer.drain(..start);
+        }
+        let value = match self.buffer.first() {
+            None => return None,
+            Some(b'{') | Some(b'[') => {
+                let end = match self.scan() {
+                    Some(end) => end,
+                    None if self.buffer.len() > self.max_size => {
+                        self.clear();
+                        return Some(Err(Error::TooLarge(self.max_size)));
+                    }
+                    None => return None,
+                };
+                let value = serde_json::from_slice::<Value>(&self.buffer[..end]);
+                self.buffer.drain(..end);
+                self.reset();
+                value
+            }
+            // this is not a request, so serde will report the error.
+            Some(_) => {
+                let mut stream = Deserializer::from_slice(&self.buffer).into_iter::<Value>();
+                match stream.next()? {
+                    Ok(value) => {
+                        let offset = stream.byte_offset();
+                        self.buffer.drain(..offset);
+                        Ok(value)
+                    }
+                    Err(err) if err.is_eof() && self.buffer.len() <= self.max_size => return None,
+                    Err(err) => Err(err),
+                }
+            }
+        };
+        if value.is_err() {
+            self.clear();
+        }
+        Some(value.map_err(Error::Json))
+    }
+
+    /// Scan the new bytes of the buffer, and return the end of the first
+    /// value if it is complete. A mismatched bracket also ends the value,
+    /// so the error is reported by the JSON parser.
+    fn scan(&mut self) -> Option<usize> {
+        while self.scanned < self.buffer.len() {
+            let byte = self.buffer[self.scanned];
+            self.scanned += 1;
+            if self.in_string {
+                match byte {
+                    _ if self.escape => self.escape = false,
+                    b'\\' => self.escape = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' => self.closing.push(b'}'),
+                b'[' => self.closing.push(b']'),
+                b'}' | b']' => {
+                    let expected = self.closing.pop();
+                    if expected != Some(byte) || self.closing.is_empty() {
+                        return Some(self.scanned);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        self.scanned = 0;
+        self.closing.clear();
+        self.in_string = false;
+        self.escape = false;
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.reset();
+    }
+
+    /// Return true if there is no partial value inside the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.iter().all(u8::is_ascii_whitespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Decoder, Error};
+
+    #[test]
+    fn decode_split_value() {
+        let mut decoder = Decoder::new();
+        let value = json!({ "method": "offer", "params": { "description": "a".repeat(4096) } });
+        let buff = serde_json::to_vec(&value).unwrap();
+        let (first, second) = buff.split_at(1064);
+        decoder.feed(first);
+        assert!(decoder.decode().is_none());
+        assert!(!decoder.is_empty());
+        decoder.feed(second);
+        assert_eq!(decoder.decode().unwrap().unwrap(), value);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_pipelined_values() {
+        let mut decoder = Decoder::new();
+        decoder.feed(br#"{"id": 1} {"id": 2}"#);
+        decoder.feed(b"\n{\"id\":");
+        assert_eq!(decoder.decode().unwrap().unwrap(), json!({ "id": 1 }));
+        assert_eq!(decoder.decode().unwrap().unwrap(), json!({ "id": 2 }));
+        assert!(decoder.decode().is_none());
+        decoder.feed(b" 3}\n");
+        assert_eq!(decoder.decode().unwrap().unwrap(), json!({ "id": 3 }));
+        assert!(decoder.decode().is_none());
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_value_over_max_size() {
+        let mut decoder = Decoder::with_max_size(16);
+        decoder.feed(br#"{"id": 1} {"method": "#);
+        assert_eq!(decoder.decode().unwrap().unwrap(), json!({ "id": 1 }));
+        assert!(decoder.decode().is_none());
+        assert!(!decoder.is_full());
+        decoder.feed(br#""getinfo", "params": ["#);
+        assert!(decoder.is_full());
+        assert!(matches!(decoder.decode(), Some(Err(Error::TooLarge(16)))));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_mismatched_brackets() {
+        let mut decoder = Decoder::new();
+        decoder.feed(br#"{"params": ["}", "\"]"], "id": 1}"#);
+        assert_eq!(
+            decoder.decode().unwrap().unwrap(),
+            json!({ "params": ["}", "\"]"], "id": 1 })
+        );
+        decoder.feed(br#"{"params": [1}"#);
+        assert!(matches!(decoder.decode(), Some(Err(Error::Json(_)))));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_invalid_value() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"{\"id\": 1]");
+        assert!(decoder.decode().unwrap().is_err());
+        assert!(decoder.is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+//! Minimal HTTP/1.1 support, enough to receive the JSON RPC 2.0
+//! requests with a `POST` and send back the responses.
+use std::fmt::Write;
+
+/// Max size of the request head, to avoid buffering forever
+/// a client that never terminates the headers.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// Max size of the request body, the same of a JSON value
+/// received on the other transports.
+const MAX_BODY_SIZE: usize = crate::framing::MAX_FRAME_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status(pub u16, pub &'static str);
+
+pub const OK: Status = Status(200, "OK");
+pub const NO_CONTENT: Status = Status(204, "No Content");
+pub const BAD_REQUEST: Status = Status(400, "Bad Request");
+pub const UNAUTHORIZED: Status = Status(401, "Unauthorized");
+pub const METHOD_NOT_ALLOWED: Status = Status(405, "Method Not Allowed");
+pub const LENGTH_REQUIRED: Status = Status(411, "Length Required");
+pub const PAYLOAD_TOO_LARGE: Status = Status(413, "Payload Too Large");
+
+/// The head of a request.
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// A part of a request decoded from the stream.
+#[derive(Debug)]
+pub enum Part {
+    /// The head of a request, returned as soon as the headers are
+    /// received, so the request can be refused before its body.
+    Head(Request),
+    /// The body of the last request.
+    Body(Vec<u8>),
+}
+
+impl Request {
+    /// Return the value of the header, the name is case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    /// The length of the body of the last head decoded.
+    body: Option<usize>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Return true if the buffer is over the size of the biggest
+    /// request, so the caller should stop reading until it is decoded.
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() > MAX_HEAD_SIZE + MAX_BODY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty() && self.body.is_none()
+    }
+
+    /// Decode the next part of the request from the buffer, `None` is
+    /// returned when the part is not fully received yet.
+    ///
+    /// The head of a request is always followed by its body, that is
+    /// returned by the next calls.
+    ///
+    /// When the request is invalid, the buffer is cleared because
+    /// there is no way to find where the next request starts.
+    pub fn decode(&mut self) -> Option<Result<Part, Status>> {
+        if let Some(length) = self.body {
+            if self.buffer.len() < length {
+                return None;
+            }
+            self.body = None;
+            let body = self.buffer.drain(..length).collect();
+            return Some(Ok(Part::Body(body)));
+        }
+        let request = self.decode_head();
+        if let Some(Err(_)) = request {
+            self.buffer.clear();
+        }
+        request
+    }
+
+    fn decode_head(&mut self) -> Option<Result<Part, Status>> {
+        let Some(end) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.buffer.len() > MAX_HEAD_SIZE {
+                return Some(Err(BAD_REQUEST));
+            }
+            return None;
+        };
+        let Ok(head) = std::str::from_utf8(&self.buffer[..end]) else {
+            return Some(Err(BAD_REQUEST));
+        };
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let (Some(method), Some(_), Some(_)) = (
+            request_line.next(),
+            request_line.next(),
+            request_line.next(),
+        ) else {
+            return Some(Err(BAD_REQUEST));
+        };
+        let mut headers = Vec::new();
+        for line in lines {
+            let Some((key, value)) = line.split_once(':') else {
+                return Some(Err(BAD_REQUEST));
+            };
+            headers.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+        let request = Request {
+            method: method.to_owned(),
+            headers,
+        };
+        if request.header("Transfer-Encoding").is_some() {
+            return Some(Err(LENGTH_REQUIRED));
+        }
+        let length = match request.header("Content-Length").map(str::parse::<usize>) {
+            Some(Ok(length)) => length,
+            Some(Err(_)) => return Some(Err(BAD_REQUEST)),
+            None => 0,
+        };
+        if length > MAX_BODY_SIZE {
+            return Some(Err(PAYLOAD_TOO_LARGE));
+        }
+        self.buffer.drain(..end + 4);
+        self.body = Some(length);
+        Some(Ok(Part::Head(request)))
+    }
+}
+
+/// Encode a HTTP response, the connection is always closed
+/// after all the requests are answered.
+pub fn response(status: Status, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status.0, status.1);
+    for (key, value) in headers {
+        // SAFETY: write on a string never fails.
+        write!(head, "{key}: {value}\r\n").unwrap();
+    }
+    write!(head, "Content-Length: {}\r\n", body.len()).unwrap();
+    head.push_str("Connection: close\r\n\r\n");
+    let mut buff = head.into_bytes();
+    buff.extend_from_slice(body);
+    buff
+}
+
+/// Encode the bytes in base64 with the standard alphabet and
+/// padding, as used by the HTTP basic authentication.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let buff = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (buff[0] as u32) << 16 | (buff[1] as u32) << 8 | buff[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, Decoder, Part, BAD_REQUEST, MAX_BODY_SIZE, PAYLOAD_TOO_LARGE};
+
+    #[test]
+    fn decode_post_request() {
+        let mut decoder = Decoder::new();
+        let body = r#"{"jsonrpc": "2.0", "method": "getinfo", "id": 1}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (head, tail) = request.split_at(40);
+        decoder.feed(head.as_bytes());
+        assert!(decoder.decode().is_none());
+        decoder.feed(tail.as_bytes());
+        let Part::Head(request) = decoder.decode().unwrap().unwrap() else {
+            panic!("the head of the request is expected");
+        };
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.header("Host"), Some("localhost"));
+        let Part::Body(content) = decoder.decode().unwrap().unwrap() else {
+            panic!("the body of the request is expected");
+        };
+        assert_eq!(content, body.as_bytes());
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_head_before_body() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n{}");
+        assert!(matches!(decoder.decode(), Some(Ok(Part::Head(_)))));
+        assert!(decoder.decode().is_none());
+        assert!(!decoder.is_empty());
+        decoder.feed(b"  ");
+        assert!(matches!(decoder.decode(), Some(Ok(Part::Body(body))) if body == b"{}  "));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_body_too_large() {
+        let mut decoder = Decoder::new();
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        decoder.feed(request.as_bytes());
+        assert_eq!(decoder.decode().unwrap().unwrap_err(), PAYLOAD_TOO_LARGE);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn decode_invalid_request() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"POST\r\n\r\n");
+        assert_eq!(decoder.decode().unwrap().unwrap_err(), BAD_REQUEST);
+    }
+
+    #[test]
+    fn encode_base64() {
+        assert_eq!(base64(b"lampo:lampo"), "bGFtcG86bGFtcG8=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert_eq!(base64(b""), "");
+    }
+}
@@ -1,4 +1,5 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Number;
 
 use crate::errors::{Error, RpcError};
 
@@ -6,7 +7,12 @@ use crate::errors::{Error, RpcError};
 #[serde(untagged)]
 pub enum Id {
     Str(String),
-    Int(u16),
+    /// A numeric id, kept as a json number so any
+    /// integer (negative or bigger than `u32`) is accepted.
+    Int(Number),
+    /// The `null` id, used when the id of the request can
+    /// not be determined, e.g. with a parse error.
+    Null,
 }
 
 impl From<&str> for Id {
@@ -17,7 +23,7 @@ impl From<&str> for Id {
 
 impl From<u64> for Id {
     fn from(value: u64) -> Self {
-        Id::Str(format!("{value}"))
+        Id::Int(value.into())
     }
 }
 
@@ -28,14 +34,27 @@ pub struct Request<T: Serialize> {
     /// The name of the RPC method call
     pub method: String,
     /// Parameters to the RPC method call
+    #[serde(default)]
     pub params: T,
-    /// Identifier for this Request, which should appear in the response
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Identifier for this Request, which should appear in the response.
+    /// A request without the id is a notification, so the server will not
+    /// reply to it.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_id"
+    )]
     pub id: Option<Id>,
     /// jsonrpc field, MUST be "2.0"
     pub jsonrpc: String,
 }
 
+/// Deserialize the id of a request, so a `null` id is
+/// not confused with a missing one.
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Id>, D::Error> {
+    Id::deserialize(deserializer).map(Some)
+}
+
 impl<T: Serialize> Request<T> {
     pub fn new(method: &str, args: T) -> Self {
         Request {
@@ -52,8 +71,10 @@ impl<T: Serialize> Request<T> {
 /// A standard JSONRPC response object
 pub struct Response<T> {
     /// A result if there is one, or null
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<T>,
     /// An error if there is one, or null
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<RpcError>,
     /// Identifier for this Request, which should match that of the request
     pub id: Id,
@@ -62,6 +83,24 @@ pub struct Response<T> {
 }
 
 impl<T> Response<T> {
+    pub fn success(id: Id, result: T) -> Self {
+        Response {
+            result: Some(result),
+            error: None,
+            id,
+            jsonrpc: "2.0".to_owned(),
+        }
+    }
+
+    pub fn error(id: Id, error: RpcError) -> Self {
+        Response {
+            result: None,
+            error: Some(error),
+            id,
+            jsonrpc: "2.0".to_owned(),
+        }
+    }
+
     /// Extract the result from a response, consuming the response
     pub fn into_result(self) -> Result<T, Error> {
         if let Some(e) = self.error {
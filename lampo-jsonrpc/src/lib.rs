@@ -1,116 +1,447 @@
 //! Full feature async JSON RPC 2.0 Server/client with a
 //! minimal dependencies footprint.
-use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::io::ErrorKind;
 use std::io::{Read, Write};
-use std::os::fd::AsRawFd;
+use std::mem;
+use std::net::TcpListener;
+use std::os::fd::{AsRawFd, RawFd};
 use std::os::unix::net::UnixListener;
-use std::os::unix::net::UnixStream;
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock};
 use std::thread::JoinHandle;
 
 // FIXME: use mio for a better platform support.
-use popol::{Event, Sources, Timeout};
+use popol::{Sources, Timeout, Waker};
 use serde_json::Value;
 
 pub mod command;
 pub mod errors;
+pub mod framing;
+mod http;
 pub mod json_rpc2;
+mod subscription;
+mod transport;
+mod worker;
 
 use command::Context;
 
 use crate::errors::Error;
-use crate::json_rpc2::{Request, Response};
+use crate::json_rpc2::{Id, Request, Response};
+use crate::transport::{Codec, Incoming, Listener, Stream};
+use crate::worker::{Done, Job, Output, WorkerPool};
 
-#[derive(Debug, Clone, PartialEq)]
+pub use crate::subscription::Subscriber;
+pub use crate::transport::{Auth, Protocol};
+
+/// Number of threads where the RPC callbacks are executed.
+pub const DEFAULT_WORKERS: usize = 8;
+
+/// Max size of the notifications waiting to be written to a client,
+/// a subscriber that does not read them is disconnected.
+const MAX_QUEUED_NOTIFICATIONS: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RPCEvent {
-    Accept,
-    Connect,
+    Accept(RawFd),
+    Connect(RawFd),
+    Wake,
+}
+
+/// A client connection, with the requests that are
+/// partially read and the responses that are waiting
+/// to be written.
+struct Connection {
+    id: u64,
+    stream: Stream,
+    codec: Codec,
+    outbound: Vec<u8>,
+    /// Sequence number of the next request read from the stream.
+    next_request: u64,
+    /// Sequence number of the next response to write, the responses
+    /// completed before this one are kept inside `ready`.
+    next_response: u64,
+    ready: BTreeMap<u64, Vec<u8>>,
+    /// Notifications of the subscriptions that are waiting for
+    /// the response of the subscription request to be written.
+    notifications: BTreeMap<u64, Vec<u8>>,
+    /// At least one subscription is running on this connection.
+    subscribed: bool,
+    /// Tell the subscriptions that the connection is closed.
+    closed: Arc<AtomicBool>,
+    eof: bool,
+}
+
+impl Connection {
+    fn new(id: u64, stream: Stream, codec: Codec) -> Self {
+        Self {
+            id,
+            stream,
+            codec,
+            outbound: Vec::new(),
+            next_request: 0,
+            next_response: 0,
+            ready: BTreeMap::new(),
+            notifications: BTreeMap::new(),
+            subscribed: false,
+            closed: Arc::new(AtomicBool::new(false)),
+            eof: false,
+        }
+    }
+
+    /// Store the encoded response of the request `seq`, and queue all
+    /// the responses that are now in order for writing.
+    fn push_frame(&mut self, seq: u64, frame: Vec<u8>) {
+        self.ready.insert(seq, frame);
+        while let Some(frame) = self.ready.remove(&self.next_response) {
+            self.next_response += 1;
+            self.outbound.extend(frame);
+        }
+        let waiting = self.notifications.split_off(&self.next_response);
+        for (_, frame) in mem::replace(&mut self.notifications, waiting) {
+            self.outbound.extend(frame);
+        }
+    }
+
+    fn push_response(&mut self, seq: u64, response: Option<Value>) {
+        let frame = self.codec.encode(response);
+        self.push_frame(seq, frame);
+    }
+
+    /// Queue a notification of the subscription started by the
+    /// request `seq`, after the response of the request itself.
+    fn push_notification(&mut self, seq: u64, notification: Value) {
+        let frame = self.codec.encode(Some(notification));
+        if seq < self.next_response {
+            self.outbound.extend(frame);
+        } else {
+            self.notifications.entry(seq).or_default().extend(frame);
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.next_response < self.next_request
+    }
+
+    /// Size of the frames waiting to be written to the client.
+    fn queued(&self) -> usize {
+        self.outbound.len() + self.notifications.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// The connection is kept open until the client closes it, and
+    /// then it is closed when all the requests received are answered
+    /// in order, so a client can send a request and shutdown the write
+    /// side while it waits for the response.
+    ///
+    /// When the client closes the stream, a partial request will
+    /// never be completed, so it is dropped.
+    ///
+    /// A connection with a subscription is closed as soon as the
+    /// client closes it, while the HTTP connections are closed after
+    /// the responses, as the `Connection: close` header says.
+    fn is_done(&self) -> bool {
+        if self.subscribed {
+            return self.eof;
+        }
+        let answered = self.outbound.is_empty() && !self.is_pending();
+        if !self.codec.is_streaming() {
+            return answered && (self.eof || (self.codec.is_empty() && self.next_request > 0));
+        }
+        answered && self.eof
+    }
 }
 
 pub struct JSONRPCv2<T: Send + Sync + 'static> {
     socket_path: String,
     sources: Sources<RPCEvent>,
-    open_streams: HashMap<i32, UnixStream>,
-    response_queue: HashMap<i32, Response<Value>>,
-    socket: UnixListener,
+    connections: HashMap<RawFd, Connection>,
+    next_connection: u64,
+    listeners: HashMap<RawFd, Listener>,
     handler: Arc<Handler<T>>,
+    workers: WorkerPool,
+    done: Receiver<Done>,
 }
 
+type RPCMethod<T> = dyn Fn(&T, &Value) -> Result<Value, errors::Error> + Send + Sync + 'static;
+type SubscriptionMethod<T> =
+    dyn Fn(&T, &Value, Subscriber) -> Result<Value, errors::Error> + Send + Sync + 'static;
+
 pub struct Handler<T: Send + Sync + 'static> {
-    stop: Cell<bool>,
-    rpc_method:
-        RefCell<HashMap<String, Arc<dyn Fn(&T, &Value) -> Result<Value, errors::Error> + 'static>>>,
+    stop: AtomicBool,
+    rpc_method: RwLock<HashMap<String, Arc<RPCMethod<T>>>>,
+    subscriptions: RwLock<HashMap<String, Arc<SubscriptionMethod<T>>>>,
     ctx: Arc<dyn Context<Ctx = T>>,
+    waker: Arc<Waker>,
 }
 
-unsafe impl<T: Send + Sync> Sync for Handler<T> {}
-unsafe impl<T: Send + Sync> Send for Handler<T> {}
-
 impl<T: Send + Sync + 'static> Handler<T> {
-    pub fn new(ctx: Arc<dyn Context<Ctx = T>>) -> Self {
+    pub fn new(ctx: Arc<dyn Context<Ctx = T>>, waker: Arc<Waker>) -> Self {
         Handler::<T> {
-            stop: Cell::new(false),
-            rpc_method: RefCell::new(HashMap::new()),
+            stop: AtomicBool::new(false),
+            rpc_method: RwLock::new(HashMap::new()),
+            subscriptions: RwLock::new(HashMap::new()),
             ctx,
+            waker,
         }
     }
 
     pub fn add_method<F>(&self, method: &str, callback: F)
     where
-        F: Fn(&T, &Value) -> Result<Value, errors::Error> + 'static,
+        F: Fn(&T, &Value) -> Result<Value, errors::Error> + Send + Sync + 'static,
     {
+        // SAFETY: the lock is never poisoned because we do not
+        // run any callback while we hold it.
         self.rpc_method
-            .borrow_mut()
+            .write()
+            .unwrap()
+            .insert(method.to_owned(), Arc::new(callback));
+    }
+
+    pub fn add_subscription<F>(&self, method: &str, callback: F)
+    where
+        F: Fn(&T, &Value, Subscriber) -> Result<Value, errors::Error> + Send + Sync + 'static,
+    {
+        self.subscriptions
+            .write()
+            .unwrap()
             .insert(method.to_owned(), Arc::new(callback));
     }
 
     pub fn run_callback(&self, req: &Request<Value>) -> Option<Result<Value, errors::Error>> {
-        let binding = self.rpc_method.borrow();
-        let Some(callback) = binding.get(&req.method) else {
+        // Nb. the callback is cloned, so the lock is released before
+        // running it.
+        let callback = self.rpc_method.read().unwrap().get(&req.method).cloned();
+        let Some(callback) = callback else {
             return Some(Err(errors::RpcError {
                 message: format!("method `{}` not found", req.method),
-                code: -1,
+                code: errors::METHOD_NOT_FOUND,
                 data: None,
             }
             .into()));
         };
-        let resp = callback(self.ctx(), &req.params);
+        // Nb. a panic is reported to the client, otherwise it
+        // will wait for a response that is never sent.
+        let resp = panic::catch_unwind(AssertUnwindSafe(|| callback(self.ctx(), &req.params)))
+            .unwrap_or_else(|_| {
+                log::error!(target: "jsonrpc", "the rpc method `{}` panicked", req.method);
+                Err(errors::RpcError {
+                    message: format!("internal error: the method `{}` panicked", req.method),
+                    code: errors::INTERNAL_ERROR,
+                    data: None,
+                }
+                .into())
+            });
         Some(resp)
     }
 
+    /// Handle a JSON RPC 2.0 message, that can be a single request
+    /// or a batch of requests, and return the response that should be
+    /// sent back to the client. Notifications do not have a response,
+    /// so `None` is returned when there is nothing to send back.
+    pub fn handle(&self, message: Value) -> Option<Value> {
+        let response = match message {
+            Value::Array(batch) if batch.is_empty() => {
+                let error = errors::RpcError {
+                    code: errors::INVALID_REQUEST,
+                    message: "invalid request: empty batch".to_owned(),
+                    data: None,
+                };
+                serde_json::to_value(Response::<Value>::error(Id::Null, error))
+            }
+            Value::Array(batch) => {
+                let responses = batch
+                    .into_iter()
+                    .filter_map(|request| self.handle_request(request))
+                    .collect::<Vec<_>>();
+                if responses.is_empty() {
+                    return None;
+                }
+                serde_json::to_value(responses)
+            }
+            request => serde_json::to_value(self.handle_request(request)?),
+        };
+        // SAFETY: the response should be a valid json.
+        Some(response.unwrap())
+    }
+
+    fn handle_request(&self, request: Value) -> Option<Response<Value>> {
+        let requ = match serde_json::from_value::<Request<Value>>(request.clone()) {
+            Ok(requ) if requ.jsonrpc == "2.0" => requ,
+            Ok(requ) => {
+                let error = errors::RpcError {
+                    code: errors::INVALID_REQUEST,
+                    message: format!("invalid request: unsupported version `{}`", requ.jsonrpc),
+                    data: None,
+                };
+                return Some(Response::error(requ.id.unwrap_or(Id::Null), error));
+            }
+            Err(err) => {
+                log::warn!(target: "jsonrpc", "invalid request received: `{err}`");
+                // if the id is invalid too, we reply with a `null` id.
+                let id = request
+                    .get("id")
+                    .and_then(|id| serde_json::from_value::<Id>(id.clone()).ok())
+                    .unwrap_or(Id::Null);
+                let error = errors::RpcError {
+                    code: errors::INVALID_REQUEST,
+                    message: format!("invalid request: {err}"),
+                    data: None,
+                };
+                return Some(Response::error(id, error));
+            }
+        };
+        log::trace!(target: "jsonrpc", "request {:?}", requ);
+        let resp = self.run_callback(&requ)?;
+        // A request without the id is a notification, so
+        // the client is not waiting for any response.
+        let Some(id) = requ.id else {
+            if let Err(err) = resp {
+                log::warn!(target: "jsonrpc", "notification `{}` fails: {err}", requ.method);
+            }
+            return None;
+        };
+        let response = match resp {
+            Ok(result) => Response::success(id, result),
+            Err(err) => Response::error(id, err.into()),
+        };
+        Some(response)
+    }
+
+    /// Handle the message if it is a subscription request, `None` is
+    /// returned for any other message. The `subscriber` is `None` when
+    /// the connection can not stream the notifications (e.g. HTTP).
+    pub(crate) fn subscribe(
+        &self,
+        message: &Value,
+        subscriber: Option<Subscriber>,
+    ) -> Option<Output> {
+        let method = message.get("method").and_then(Value::as_str)?;
+        let callback = self.subscriptions.read().unwrap().get(method).cloned()?;
+        // an invalid request is reported by `handle`.
+        let requ = serde_json::from_value::<Request<Value>>(message.clone()).ok()?;
+        if requ.jsonrpc != "2.0" {
+            return None;
+        }
+        let Some(id) = requ.id else {
+            log::warn!(target: "jsonrpc", "subscription `{method}` without id, ignoring it");
+            return Some(Output::Response(None));
+        };
+        let Some(subscriber) = subscriber else {
+            let error = errors::RpcError {
+                code: errors::INVALID_REQUEST,
+                message: format!("subscription `{method}` is not supported on this transport"),
+                data: None,
+            };
+            return Some(Output::Response(Some(
+                serde_json::to_value(Response::<Value>::error(id, error)).unwrap(),
+            )));
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            callback(self.ctx(), &requ.params, subscriber)
+        }))
+        .unwrap_or_else(|_| {
+            log::error!(target: "jsonrpc", "the subscription `{method}` panicked");
+            Err(errors::RpcError {
+                message: format!("internal error: the subscription `{method}` panicked"),
+                code: errors::INTERNAL_ERROR,
+                data: None,
+            }
+            .into())
+        });
+        let output = match result {
+            Ok(result) => {
+                Output::Subscribed(serde_json::to_value(Response::success(id, result)).unwrap())
+            }
+            Err(err) => Output::Response(Some(
+                serde_json::to_value(Response::<Value>::error(id, err.into())).unwrap(),
+            )),
+        };
+        Some(output)
+    }
+
     pub fn has_rpc(&self, method: &str) -> bool {
-        self.rpc_method.borrow().contains_key(method)
+        self.rpc_method.read().unwrap().contains_key(method)
+            || self.subscriptions.read().unwrap().contains_key(method)
     }
 
     fn ctx(&self) -> &T {
         self.ctx.ctx()
     }
 
+    /// Stop the server, the requests that are already running
+    /// are not interrupted.
     pub fn stop(&self) {
-        self.stop.set(true);
+        self.stop.store(true, Ordering::SeqCst);
+        if let Err(err) = self.waker.wake() {
+            log::error!(target: "jsonrpc", "impossible wake up the server: {err}");
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
     }
 }
 
 impl<T: Send + Sync + 'static> JSONRPCv2<T> {
     pub fn new(ctx: Arc<dyn Context<Ctx = T>>, path: &str) -> Result<Self, Error> {
+        Self::with_workers(ctx, path, DEFAULT_WORKERS)
+    }
+
+    /// Create a new server that runs the RPC callbacks on `workers` threads.
+    pub fn with_workers(
+        ctx: Arc<dyn Context<Ctx = T>>,
+        path: &str,
+        workers: usize,
+    ) -> Result<Self, Error> {
         let listnet = UnixListener::bind(path)?;
-        let sources = Sources::<RPCEvent>::new();
-        Ok(Self {
+        let mut sources = Sources::<RPCEvent>::new();
+        let waker = Arc::new(Waker::new(&mut sources, RPCEvent::Wake)?);
+        let handler = Arc::new(Handler::new(ctx, waker.clone()));
+        let (workers, done) = WorkerPool::new(workers, handler.clone(), waker)?;
+        let mut server = Self {
             sources,
-            socket: listnet,
-            handler: Arc::new(Handler::new(ctx)),
+            listeners: HashMap::new(),
+            handler,
             socket_path: path.to_owned(),
-            open_streams: HashMap::new(),
-            response_queue: HashMap::new(),
-        })
+            connections: HashMap::new(),
+            next_connection: 0,
+            workers,
+            done,
+        };
+        server.add_listener(Listener::Unix(listnet))?;
+        Ok(server)
+    }
+
+    /// Listen for the JSON RPC 2.0 requests on a TCP socket too, so the
+    /// server can be reached from another host. When `auth` is not `None`
+    /// every client must provide the credentials, with the `Authorization`
+    /// header over HTTP, or with the `auth` request over plain TCP.
+    pub fn add_tcp_listener(
+        &mut self,
+        addr: &str,
+        protocol: Protocol,
+        auth: Option<Auth>,
+    ) -> Result<(), Error> {
+        let listener = TcpListener::bind(addr)?;
+        log::info!(target: "jsonrpc", "listening for {:?} connections on {addr}", protocol);
+        self.add_listener(Listener::Tcp(listener, protocol, auth))
+    }
+
+    fn add_listener(&mut self, listener: Listener) -> Result<(), Error> {
+        listener.set_nonblocking(true)?;
+        let fd = listener.as_raw_fd();
+        self.sources
+            .register(RPCEvent::Accept(fd), &listener, popol::interest::READ);
+        self.listeners.insert(fd, listener);
+        Ok(())
     }
 
     pub fn add_rpc<F>(&self, name: &str, callback: F) -> Result<(), ()>
     where
-        F: Fn(&T, &Value) -> Result<Value, errors::Error> + 'static,
+        F: Fn(&T, &Value) -> Result<Value, errors::Error> + Send + Sync + 'static,
     {
         if self.handler.has_rpc(name) {
             return Err(());
@@ -119,169 +450,227 @@ impl<T: Send + Sync + 'static> JSONRPCv2<T> {
         Ok(())
     }
 
+    /// Register a method that starts a subscription, the callback
+    /// replies like any other method and keeps the `Subscriber` to
+    /// send the notifications until the client closes the connection.
+    ///
+    /// Subscriptions are available on the unix socket and on the
+    /// TCP listeners that do not speak HTTP.
+    pub fn add_subscription<F>(&self, name: &str, callback: F) -> Result<(), ()>
+    where
+        F: Fn(&T, &Value, Subscriber) -> Result<Value, errors::Error> + Send + Sync + 'static,
+    {
+        if self.handler.has_rpc(name) {
+            return Err(());
+        }
+        self.handler.add_subscription(name, callback);
+        Ok(())
+    }
+
     #[allow(dead_code)]
     fn ctx(&self) -> &T {
         self.handler.ctx()
     }
 
-    fn read(&mut self, event: &mut Event<RPCEvent>) -> io::Result<()> {
+    fn accept(&mut self, fd: RawFd) -> io::Result<()> {
+        // SAFETY: the listener is inserted when it is registered.
+        let listener = self.listeners.get(&fd).unwrap();
+        loop {
+            let (stream, codec) = match listener.accept() {
+                Ok(accept) => accept,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    log::trace!("accepting the connection is blocking");
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            };
+            log::info!("Accepting connection: `{:?}`", stream);
+            stream.set_nonblocking(true)?;
+            let fd = stream.as_raw_fd();
+            self.sources
+                .register(RPCEvent::Connect(fd), &stream, popol::interest::READ);
+            let id = self.next_connection;
+            self.next_connection += 1;
+            self.connections
+                .insert(fd, Connection::new(id, stream, codec));
+        }
+    }
+
+    fn read(&mut self, fd: RawFd) -> io::Result<()> {
         log::trace!("read from connection");
-        let fd = event.as_raw_fd();
-        // SAFETY: in this case the socket stream should be created
-        let stream = self.open_streams.get_mut(&fd.clone()).unwrap();
-        log::trace!("start reading");
-        // Nb. Since `poll`, which this reactor is based on, is *level-triggered*,
-        // we will be notified again if there is still data to be read on the socket.
-        // Hence, there is no use in putting this socket read in a loop, as the second
-        // invocation would likely block.
-        let mut buff = vec![0; 1064];
-        let resp = loop {
-            match stream.read(&mut buff) {
+        // SAFETY: the connection is inserted when it is accepted.
+        let conn = self.connections.get_mut(&fd).unwrap();
+        // Nb. `poll` is level-triggered, but the stream is non blocking
+        // so we can drain it here and decode all the requests that are
+        // already arrived.
+        let mut buff = [0; 4096];
+        loop {
+            match conn.stream.read(&mut buff) {
+                Ok(0) => {
+                    log::trace!("the client closed the connection");
+                    conn.eof = true;
+                    self.sources
+                        .unset(&RPCEvent::Connect(fd), popol::interest::READ);
+                    break;
+                }
                 Ok(count) => {
-                    buff.truncate(count);
-                    if count > 0 {
-                        log::info!(target: "jsonrpc", "buffer read {}", String::from_utf8(buff.to_vec()).unwrap());
-                        // Put this inside the unfinish queue
-                        let Ok(requ) = serde_json::from_slice::<Request<Value>>(&buff) else {
-                            log::warn!(target: "jsonrpc", "looks like that the json is not fully read ` {}`", String::from_utf8(buff.to_vec()).unwrap());
-                            // Usually this mean that we was too fast in reading and the sender too low
-                            continue;
-                        };
-                        log::trace!(target: "jsonrpc", "request {:?}", requ);
-                        let Some(resp) = self.handler.run_callback(&requ) else {
-                            log::error!(target: "jsonrpc", "`{}` not found!", requ.method);
-                            return Ok(());
-                        };
-                        // FIXME; the id in the JSON RPC can be null!
-                        let response = match resp {
-                            Ok(result) => Response {
-                                id: requ.id.clone().unwrap(),
-                                jsonrpc: requ.jsonrpc.to_owned(),
-                                result: Some(result),
-                                error: None,
-                            },
-                            Err(err) => Response {
-                                result: None,
-                                error: Some(err.into()),
-                                id: requ.id.unwrap().clone(),
-                                jsonrpc: requ.jsonrpc.clone(),
-                            },
-                        };
-                        break response;
-                    } else {
-                        log::info!("Reading is not finished, so keep reading");
-                        event.source.unset(popol::interest::READ);
-                        return Ok(());
+                    conn.codec.feed(&buff[..count]);
+                    // the rest of the stream is read at the next
+                    // `poll`, after the buffer is decoded.
+                    if conn.codec.is_full() {
+                        break;
                     }
                 }
-                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
-                    log::trace!("reading is blocking");
-                    // This shouldn't normally happen, since this function is only called
-                    // when there's data on the socket. We leave it here in case external
-                    // conditions change.
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        while let Some(incoming) = conn.codec.decode() {
+            let seq = conn.next_request;
+            conn.next_request += 1;
+            match incoming {
+                Incoming::Message(message) => self.workers.execute(Job {
+                    fd,
+                    conn: conn.id,
+                    seq,
+                    message,
+                    closed: conn.codec.is_streaming().then(|| conn.closed.clone()),
+                }),
+                Incoming::Reply(frame) => conn.push_frame(seq, frame),
+                Incoming::Reject(frame) => {
+                    conn.push_frame(seq, frame);
+                    // we do not read anymore from this connection.
+                    conn.eof = true;
+                    self.sources
+                        .unset(&RPCEvent::Connect(fd), popol::interest::READ);
+                    break;
+                }
+            }
+        }
+
+        if !conn.outbound.is_empty() {
+            self.sources
+                .set(&RPCEvent::Connect(fd), popol::interest::WRITE);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, fd: RawFd) -> io::Result<()> {
+        log::trace!("write to connection");
+        // SAFETY: the connection is inserted when it is accepted.
+        let conn = self.connections.get_mut(&fd).unwrap();
+        while !conn.outbound.is_empty() {
+            match conn.stream.write(&conn.outbound) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(count) => {
+                    conn.outbound.drain(..count);
+                }
+                // In this case, the write couldn't complete. We keep our
+                // interest in `WRITE` to be notified when the socket is
+                // ready to write again.
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    log::trace!("writing is blocking");
                     return Ok(());
                 }
-                Err(err) => {
-                    log::error!("{:?}", err);
-                    self.sources.unregister(&event.key);
-                    return Err(err);
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        // In this case, we've written all the data, we
+        // are no longer interested in writing to this
+        // socket.
+        log::trace!("writing ended");
+        self.sources
+            .unset(&RPCEvent::Connect(fd), popol::interest::WRITE);
+        Ok(())
+    }
+
+    /// Collect the responses of the jobs that are completed.
+    fn complete(&mut self) {
+        while let Ok(done) = self.done.try_recv() {
+            let Some(conn) = self.connections.get_mut(&done.fd) else {
+                log::debug!(target: "jsonrpc", "connection closed before the response");
+                continue;
+            };
+            if conn.id != done.conn {
+                log::debug!(target: "jsonrpc", "connection closed before the response");
+                continue;
+            }
+            match done.output {
+                Output::Response(response) => conn.push_response(done.seq, response),
+                Output::Subscribed(response) => {
+                    conn.subscribed = true;
+                    conn.push_response(done.seq, Some(response));
+                }
+                Output::Notification(notification) => {
+                    conn.push_notification(done.seq, notification)
                 }
             }
-        };
+            if conn.queued() > MAX_QUEUED_NOTIFICATIONS {
+                log::warn!(target: "jsonrpc", "the client is not reading the notifications, closing the connection");
+                self.close(done.fd);
+                continue;
+            }
+            if !conn.outbound.is_empty() {
+                self.sources
+                    .set(&RPCEvent::Connect(done.fd), popol::interest::WRITE);
+            } else if conn.is_done() {
+                self.close(done.fd);
+            }
+        }
+    }
 
-        log::trace!(target: "jsonrpc", "send response: `{:?}`", resp);
-        self.response_queue.insert(fd, resp);
-        event.source.set(popol::interest::WRITE);
-        Ok(())
+    fn close(&mut self, fd: RawFd) {
+        log::trace!("closing connection");
+        self.sources.unregister(&RPCEvent::Connect(fd));
+        if let Some(conn) = self.connections.remove(&fd) {
+            conn.closed.store(true, Ordering::SeqCst);
+        }
     }
 
     pub fn listen(mut self) -> io::Result<()> {
-        self.socket.set_nonblocking(true)?;
-        self.sources
-            .register(RPCEvent::Accept, &self.socket, popol::interest::READ);
         log::info!(target: "jsonrpc", "starting server on {}", self.socket_path);
         let mut events = vec![];
-        while !self.handler.stop.get() {
+        while !self.handler.is_stopped() {
             // Blocking while we are waiting new events!
             self.sources.poll(&mut events, Timeout::Never)?;
-            for mut event in events.drain(..) {
-                match &event.key {
-                    RPCEvent::Accept => loop {
-                        let accept = self.socket.accept();
-                        if let Err(err) = accept {
-                            if err.kind() == ErrorKind::WouldBlock {
-                                log::trace!("accepting the connection is blocking");
-                                break;
-                            }
-                            return Err(err);
-                        }
-                        log::info!("Accepting connection: `{:?}`", accept);
-                        let stream = accept?.0;
-                        self.sources.register(
-                            RPCEvent::Connect,
-                            &stream,
-                            popol::interest::READ | popol::interest::WRITE,
-                        );
-                        self.open_streams.insert(stream.as_raw_fd(), stream);
-                        break;
-                    },
-                    RPCEvent::Connect if event.is_readable() => {
-                        self.read(&mut event)?;
+            for event in events.drain(..) {
+                match event.key {
+                    RPCEvent::Accept(fd) => self.accept(fd)?,
+                    RPCEvent::Wake => {
+                        Waker::reset(event.as_raw_fd())?;
+                        self.complete();
                     }
-                    // FIXME: convert all the code inside a `self.write`
-                    RPCEvent::Connect if event.is_writable() => {
-                        let fd = event.as_raw_fd();
-                        // SAFETY: we must have the response for this fd.
-                        let resp = self.response_queue.remove(&fd).unwrap();
-                        // SAFETY: we much have a stream for this fd.
-                        let mut stream = self.open_streams.remove(&event.as_raw_fd()).unwrap();
-                        // SAFETY: the resp should be a valid json.
-                        let buff = serde_json::to_string(&resp).unwrap();
-                        log::debug!("writing the response `{buff}`");
-                        if let Err(err) = stream.write_all(buff.as_bytes()) {
-                            if err.kind() != ErrorKind::WouldBlock {
-                                return Err(err);
-                            }
-                            log::info!("writing is blocking");
+                    RPCEvent::Connect(fd) => {
+                        if event.is_invalid() {
+                            log::warn!(target: "jsonrpc", "event invalid: {:?}", event);
+                            self.close(fd);
                             continue;
                         }
-                        match stream.flush() {
-                            // In this case, we've written all the data, we
-                            // are no longer interested in writing to this
-                            // socket.
-                            Ok(()) => {
-                                log::trace!("writing ended");
-                                event.source.unset(popol::interest::WRITE);
-                                event.source.set(popol::interest::READ);
-                                self.sources.unregister(&event.key);
+                        if event.is_readable() {
+                            if let Err(err) = self.read(fd) {
+                                log::error!(target: "jsonrpc", "error while reading: {:?}", err);
+                                self.close(fd);
                                 continue;
                             }
-                            // In this case, the write couldn't complete. Set
-                            // our interest to `WRITE` to be notified when the
-                            // socket is ready to write again.
-                            Err(err)
-                                if [io::ErrorKind::WouldBlock, io::ErrorKind::WriteZero]
-                                    .contains(&err.kind()) =>
-                            {
-                                log::info!("writing return an error: {:?}", err);
-                                event.source.set(popol::interest::READ);
-                                break;
-                            }
-                            Err(err) => {
-                                return Err(err);
+                        }
+                        if event.is_writable() {
+                            if let Err(err) = self.write(fd) {
+                                log::error!(target: "jsonrpc", "error while writing: {:?}", err);
+                                self.close(fd);
+                                continue;
                             }
                         }
-                    }
-                    RPCEvent::Connect => {
                         if event.is_hangup() || event.is_error() {
-                            log::error!(target: "jsonrpc", "an error occurs: {:?}", event);
+                            log::debug!(target: "jsonrpc", "connection closed: {:?}", event);
+                            self.close(fd);
                             continue;
                         }
-
-                        if event.is_invalid() {
-                            log::warn!(target: "jsonrpc", "event invalid: {:?}", event);
-                            self.sources.unregister(&event.key);
-                            continue;
+                        // SAFETY: the connection is removed only by `close`.
+                        if self.connections.get(&fd).unwrap().is_done() {
+                            self.close(fd);
                         }
                     }
                 }
@@ -309,7 +698,13 @@ impl<T: Send + Sync + 'static> Drop for JSONRPCv2<T> {
 #[cfg(test)]
 mod tests {
     use std::{
-        io::Write, os::unix::net::UnixStream, path::Path, str::FromStr, sync::Arc, time::Duration,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        os::unix::net::UnixStream,
+        path::Path,
+        str::FromStr,
+        sync::Arc,
+        time::Duration,
     };
 
     use lampo_common::logger;
@@ -318,8 +713,9 @@ mod tests {
 
     use crate::{
         command::Context,
+        errors,
         json_rpc2::{Id, Request, Response},
-        JSONRPCv2,
+        Auth, JSONRPCv2, Protocol,
     };
 
     struct DummyCtx;
@@ -335,7 +731,7 @@ mod tests {
     #[test]
     #[timeout(9000)]
     fn register_rpc() {
-        logger::init("debug", None).unwrap();
+        let _ = logger::init("debug", None);
         let path = "/tmp/tmp.sock";
         let _ = std::fs::remove_file(path);
         let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
@@ -367,9 +763,17 @@ mod tests {
             let _ = stream.flush().unwrap();
             log::info!(target: "client", "waiting for server response");
             log::info!(target: "client", "read answer from server");
-            let resp: Response<Value> = serde_json::from_reader(stream).unwrap();
+            let mut responses = serde_json::Deserializer::from_reader(stream.try_clone().unwrap())
+                .into_iter::<Response<Value>>();
+            let resp = responses.next().unwrap().unwrap();
             log::info!(target: "client", "msg received: {:?}", resp);
-            assert_eq!(resp.id, request.id.unwrap());
+            assert_eq!(resp.id, request.id.clone().unwrap());
+
+            // the connection is still open for the next request.
+            stream.write_all(buff.as_bytes()).unwrap();
+            stream.flush().unwrap();
+            let second = responses.next().unwrap().unwrap();
+            assert_eq!(second.id, request.id.unwrap());
             resp
         });
 
@@ -391,6 +795,7 @@ mod tests {
             let _ = stream.write_all(buff.as_bytes()).unwrap();
             let _ = stream.flush().unwrap();
             log::info!(target: "client", "waiting for server response");
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
             log::info!(target: "client", "read answer from server");
             let resp: Response<Value> = serde_json::from_reader(stream).unwrap();
             log::info!(target: "client", "msg received: {:?}", resp);
@@ -398,9 +803,375 @@ mod tests {
         });
 
         let resp = client_worker.join().unwrap();
-        assert_eq!(Id::Str("0".to_owned()), resp.id);
+        assert_eq!(Id::from(0), resp.id);
         let resp = client_worker2.join().unwrap();
-        assert_eq!(Id::Str("1".to_owned()), resp.id);
+        assert_eq!(Id::from(1), resp.id);
+        handler.stop();
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn large_and_pipelined_requests() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-framing.sock";
+        let _ = std::fs::remove_file(path);
+        let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let _ = server.add_rpc("echo", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        let handler = server.handler();
+        let _worker = server.spawn();
+
+        let large =
+            Request::<Value>::new("echo", serde_json::json!({ "offer": "lno".repeat(4096) }));
+        let mut buff = serde_json::to_vec(&large).unwrap();
+        for id in 0..3u64 {
+            let mut request = Request::<Value>::new("echo", serde_json::json!([id]));
+            request.id = Some(id.into());
+            buff.extend(serde_json::to_vec(&request).unwrap());
+        }
+
+        let mut stream = UnixStream::connect(Path::new(path)).unwrap();
+        // write the requests in small chunks to split them across reads.
+        for chunk in buff.chunks(512) {
+            stream.write_all(chunk).unwrap();
+            stream.flush().unwrap();
+        }
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let reader = BufReader::new(stream);
+        let responses = reader
+            .lines()
+            .map(|line| serde_json::from_str::<Response<Value>>(&line.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses.len(), 4, "{:?}", responses);
+        assert_eq!(responses[0].result, Some(large.params));
+        for (id, resp) in responses.iter().skip(1).enumerate() {
+            assert_eq!(resp.id, Id::from(id as u64));
+            assert_eq!(resp.result, Some(serde_json::json!([id])));
+        }
+        handler.stop();
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn batch_and_notifications() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-batch.sock";
+        let _ = std::fs::remove_file(path);
+        let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let _ = server.add_rpc("echo", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        let _ = server.add_rpc("panic", |_: &DummyCtx, _| -> Result<Value, errors::Error> {
+            panic!("the callback panicked")
+        });
+        let handler = server.handler();
+        let _worker = server.spawn();
+
+        let call = |buff: &str| -> Vec<Value> {
+            let mut stream = UnixStream::connect(Path::new(path)).unwrap();
+            stream.write_all(buff.as_bytes()).unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            BufReader::new(stream)
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(&line.unwrap()).unwrap())
+                .collect()
+        };
+
+        let resp = call(
+            r#"[
+                {"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1},
+                {"jsonrpc": "2.0", "method": "echo", "params": [2]},
+                {"jsonrpc": "2.0", "method": "unknown", "id": "2"},
+                {"jsonrpc": "2.0", "method": "echo", "id": null},
+                1
+            ]"#,
+        );
+        assert_eq!(
+            resp,
+            vec![serde_json::json!([
+                {"jsonrpc": "2.0", "result": [1], "id": 1},
+                {"jsonrpc": "2.0", "error": {"code": -32601, "message": "method `unknown` not found", "data": null}, "id": "2"},
+                {"jsonrpc": "2.0", "result": null, "id": null},
+                {"jsonrpc": "2.0", "error": resp[0][3]["error"], "id": null},
+            ])]
+        );
+        assert_eq!(resp[0][3]["error"]["code"], -32600);
+
+        // any integer is a valid id, not only the small ones.
+        let resp = call(
+            r#"[
+                {"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 18446744073709551615},
+                {"jsonrpc": "2.0", "method": "echo", "params": [2], "id": -1}
+            ]"#,
+        );
+        assert_eq!(
+            resp,
+            vec![serde_json::json!([
+                {"jsonrpc": "2.0", "result": [1], "id": u64::MAX},
+                {"jsonrpc": "2.0", "result": [2], "id": -1},
+            ])]
+        );
+
+        // a panic in the callback is an internal error.
+        let resp = call(r#"{"jsonrpc": "2.0", "method": "panic", "id": 7}"#);
+        assert_eq!(resp[0]["error"]["code"], -32603);
+        assert_eq!(resp[0]["id"], 7);
+
+        // a batch of notifications does not have any response.
+        let resp = call(r#"[{"jsonrpc": "2.0", "method": "echo", "params": [1]}]"#);
+        assert!(resp.is_empty(), "{:?}", resp);
+
+        let resp = call("[]");
+        assert_eq!(resp[0]["error"]["code"], -32600);
+
+        let resp = call(r#"{"jsonrpc": "2.0", "method": "echo", "params": [1]"#);
+        assert_eq!(resp.len(), 0);
+
+        let resp = call(r#"{"jsonrpc": "2.0", "method": "echo", "params": [1}"#);
+        assert_eq!(resp[0]["error"]["code"], -32700);
+        assert_eq!(resp[0]["id"], Value::Null);
+        handler.stop();
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn slow_callback_does_not_block() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-workers.sock";
+        let _ = std::fs::remove_file(path);
+        let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let _ = server.add_rpc("slow", |_: &DummyCtx, request| {
+            std::thread::sleep(Duration::from_secs(3));
+            Ok(serde_json::json!(request))
+        });
+        let _ = server.add_rpc("fast", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        let handler = server.handler();
+        let worker = server.spawn();
+
+        let call = |buff: String| -> Vec<Value> {
+            let mut stream = UnixStream::connect(Path::new(path)).unwrap();
+            stream.write_all(buff.as_bytes()).unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            BufReader::new(stream)
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(&line.unwrap()).unwrap())
+                .collect()
+        };
+
+        let slow = std::thread::spawn(move || {
+            // the slow response must be written before the fast one.
+            call(
+                r#"{"jsonrpc": "2.0", "method": "slow", "params": [0], "id": 0}
+                {"jsonrpc": "2.0", "method": "fast", "params": [1], "id": 1}"#
+                    .to_owned(),
+            )
+        });
+        std::thread::sleep(Duration::from_millis(500));
+        let started = std::time::Instant::now();
+        let resp =
+            call(r#"{"jsonrpc": "2.0", "method": "fast", "params": [2], "id": 2}"#.to_owned());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(resp[0]["result"], serde_json::json!([2]));
+
+        let resp = slow.join().unwrap();
+        assert_eq!(resp.len(), 2, "{:?}", resp);
+        assert_eq!(resp[0]["id"], 0);
+        assert_eq!(resp[1]["id"], 1);
+
+        handler.stop();
+        assert!(worker.join().unwrap().is_ok());
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn tcp_listeners_with_auth() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-tcp.sock";
+        let _ = std::fs::remove_file(path);
+        let mut server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let auth = Auth::Basic {
+            user: "lampo".to_owned(),
+            password: "lampo".to_owned(),
+        };
+        server
+            .add_tcp_listener("127.0.0.1:19998", Protocol::Http, Some(auth.clone()))
+            .unwrap();
+        server
+            .add_tcp_listener("127.0.0.1:19999", Protocol::Tcp, Some(auth))
+            .unwrap();
+        let _ = server.add_rpc("echo", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        let handler = server.handler();
+        let _worker = server.spawn();
+
+        let call = |addr: &str, buff: &str| -> String {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(buff.as_bytes()).unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut resp = String::new();
+            stream.read_to_string(&mut resp).unwrap();
+            resp
+        };
+
+        let body = r#"{"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1}"#;
+        let resp = call(
+            "127.0.0.1:19998",
+            &format!(
+                "POST / HTTP/1.1\r\nAuthorization: Basic bGFtcG86bGFtcG8=\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        let (_, content) = resp.split_once("\r\n\r\n").unwrap();
+        let content: Value = serde_json::from_str(content).unwrap();
+        assert_eq!(content["result"], serde_json::json!([1]));
+
+        let resp = call(
+            "127.0.0.1:19998",
+            &format!(
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        assert!(resp.starts_with("HTTP/1.1 401 Unauthorized\r\n"), "{resp}");
+
+        // the credentials are checked before the body is received.
+        let resp = call(
+            "127.0.0.1:19998",
+            "POST / HTTP/1.1\r\nAuthorization: basic bGFtcG86bGFtcG9=\r\nContent-Length: 1000000\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 401 Unauthorized\r\n"), "{resp}");
+
+        let resp = call(
+            "127.0.0.1:19999",
+            r#"{"jsonrpc": "2.0", "method": "auth", "params": {"user": "lampo", "password": "lampo"}, "id": 0}
+            {"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1}"#,
+        );
+        let resp = resp
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(resp[0]["result"], serde_json::json!({}));
+        assert_eq!(resp[1]["result"], serde_json::json!([1]));
+
+        let resp = call("127.0.0.1:19999", body);
+        let resp: Value = serde_json::from_str(&resp).unwrap();
+        assert_eq!(resp["error"]["code"], -32001);
+
+        // a connection that is not authenticated can not send a large request,
+        // so the server closes it without reading the rest of the request.
+        let mut stream = TcpStream::connect("127.0.0.1:19999").unwrap();
+        let request = format!(
+            r#"{{"jsonrpc": "2.0", "method": "auth", "params": {{"token": "{}"#,
+            "a".repeat(32 * 1024)
+        );
+        let _ = stream.write_all(request.as_bytes());
+        let mut resp = String::new();
+        BufReader::new(stream).read_line(&mut resp).unwrap();
+        let resp: Value = serde_json::from_str(&resp).unwrap();
+        assert_eq!(resp["error"]["code"], -32600);
+        handler.stop();
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn subscription_streams_notifications() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-subscribe.sock";
+        let _ = std::fs::remove_file(path);
+        let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let (closed, is_closed) = std::sync::mpsc::channel();
+        let _ = server.add_subscription("subscribe", move |_: &DummyCtx, request, subscriber| {
+            let closed = closed.clone();
+            // the notifications can be sent before the response is written.
+            for event in 0..3 {
+                assert!(subscriber.notify("event", serde_json::json!([event])));
+            }
+            std::thread::spawn(move || {
+                while !subscriber.is_closed() {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                assert!(!subscriber.notify("event", serde_json::json!([3])));
+                closed.send(()).unwrap();
+            });
+            Ok(serde_json::json!(request))
+        });
+        let _ = server.add_rpc("echo", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        let res = server.add_rpc("subscribe", |_: &DummyCtx, request| {
+            Ok(serde_json::json!(request))
+        });
+        assert!(res.is_err());
+        let handler = server.handler();
+        let _worker = server.spawn();
+
+        let mut stream = UnixStream::connect(Path::new(path)).unwrap();
+        stream
+            .write_all(
+                br#"{"jsonrpc": "2.0", "method": "subscribe", "params": ["channels"], "id": 0}"#,
+            )
+            .unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut next = || serde_json::from_str::<Value>(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(next()["result"], serde_json::json!(["channels"]));
+        for event in 0..3 {
+            let notification = next();
+            assert_eq!(notification["method"], "event");
+            assert_eq!(notification["params"], serde_json::json!([event]));
+            assert!(notification.get("id").is_none());
+        }
+        // the connection is still open for the other requests.
+        stream
+            .write_all(br#"{"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1}"#)
+            .unwrap();
+        assert_eq!(next()["result"], serde_json::json!([1]));
+        stream.shutdown(std::net::Shutdown::Both).unwrap();
+        is_closed.recv_timeout(Duration::from_secs(3)).unwrap();
+        handler.stop();
+    }
+
+    #[test]
+    #[timeout(9000)]
+    fn slow_subscriber_is_disconnected() {
+        let _ = logger::init("debug", None);
+        let path = "/tmp/tmp-slow-subscriber.sock";
+        let _ = std::fs::remove_file(path);
+        let server = JSONRPCv2::new(Arc::new(DummyCtx), path).unwrap();
+        let (sent, notified) = std::sync::mpsc::channel();
+        let _ = server.add_subscription("subscribe", move |_: &DummyCtx, request, subscriber| {
+            let sent = sent.clone();
+            std::thread::spawn(move || {
+                let event = serde_json::json!(["event".repeat(1024)]);
+                let mut count = 0;
+                while subscriber.notify("event", event.clone()) {
+                    count += 1;
+                    // give the reactor the time to collect the notifications.
+                    if count % 64 == 0 {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                sent.send(count).unwrap();
+            });
+            Ok(serde_json::json!(request))
+        });
+        let handler = server.handler();
+        let _worker = server.spawn();
+
+        // the client never reads the notifications.
+        let mut stream = UnixStream::connect(Path::new(path)).unwrap();
+        stream
+            .write_all(br#"{"jsonrpc": "2.0", "method": "subscribe", "params": [], "id": 0}"#)
+            .unwrap();
+        let count = notified.recv_timeout(Duration::from_secs(8)).unwrap();
+        assert!(
+            count * 5 * 1024 > super::MAX_QUEUED_NOTIFICATIONS,
+            "{count}"
+        );
         handler.stop();
     }
 }
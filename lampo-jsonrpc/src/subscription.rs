@@ -0,0 +1,63 @@
+//! Subscriptions that keep the connection open and stream
+//! notifications to the client.
+//!
+//! A subscription method answers like any other method, then the
+//! callback keeps the `Subscriber` and sends the notifications with it
+//! until the client closes the connection.
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use popol::Waker;
+use serde_json::{json, Value};
+
+use crate::worker::{Done, Output};
+
+/// Send the notifications of a subscription to the client.
+#[derive(Clone)]
+pub struct Subscriber {
+    pub(crate) fd: RawFd,
+    pub(crate) conn: u64,
+    /// Position of the subscription request inside the connection
+    /// stream, the notifications are written after its response.
+    pub(crate) seq: u64,
+    pub(crate) closed: Arc<AtomicBool>,
+    pub(crate) results: Sender<Done>,
+    pub(crate) waker: Arc<Waker>,
+}
+
+impl Subscriber {
+    /// Send a JSON RPC 2.0 notification to the client, false is
+    /// returned when the client is gone and the subscription should
+    /// be dropped.
+    pub fn notify(&self, method: &str, params: Value) -> bool {
+        if self.is_closed() {
+            return false;
+        }
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        let done = Done {
+            fd: self.fd,
+            conn: self.conn,
+            seq: self.seq,
+            output: Output::Notification(notification),
+        };
+        // the server is stopped.
+        if self.results.send(done).is_err() {
+            return false;
+        }
+        if let Err(err) = self.waker.wake() {
+            log::error!(target: "jsonrpc", "impossible wake up the reactor: {err}");
+        }
+        true
+    }
+
+    /// Return true when the client closed the connection.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,417 @@
+//! Transports supported by the JSON RPC 2.0 server.
+//!
+//! The unix socket is always available, while a TCP listener
+//! can be added to reach the server from another host, with the
+//! same stream of JSON values or with HTTP.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use serde_json::{json, Value};
+
+use crate::errors::{self, RpcError};
+use crate::framing;
+use crate::http;
+use crate::json_rpc2::{Id, Response};
+
+/// Max size of the first request of a TCP connection with auth,
+/// so a client that is not authenticated yet can not make the
+/// server buffer a large request.
+const MAX_AUTH_SIZE: usize = 16 * 1024;
+
+/// The protocol spoken on a TCP listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// The same stream of JSON values used on the unix socket.
+    Tcp,
+    /// A JSON RPC 2.0 message inside the body of each HTTP `POST`.
+    Http,
+}
+
+/// The credentials required by a TCP listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+    /// `Authorization: Basic <base64(user:password)>`
+    Basic { user: String, password: String },
+}
+
+impl Auth {
+    /// Return the scheme and the credentials of the `Authorization` header.
+    fn credentials(&self) -> (&'static str, String) {
+        match self {
+            Auth::Bearer(token) => ("Bearer", token.to_owned()),
+            Auth::Basic { user, password } => (
+                "Basic",
+                http::base64(format!("{user}:{password}").as_bytes()),
+            ),
+        }
+    }
+
+    fn challenge(&self) -> &'static str {
+        match self {
+            Auth::Bearer(_) => "Bearer realm=\"lampo\"",
+            Auth::Basic { .. } => "Basic realm=\"lampo\"",
+        }
+    }
+
+    /// Check the value of the `Authorization` header, the
+    /// scheme is case insensitive.
+    pub fn check(&self, authorization: &str) -> bool {
+        let (scheme, expected) = self.credentials();
+        let Some((received_scheme, credentials)) = authorization.trim().split_once(' ') else {
+            return false;
+        };
+        let credentials = credentials.trim_start().as_bytes();
+        // compare all the bytes, to not leak how many of them are correct.
+        received_scheme.eq_ignore_ascii_case(scheme)
+            && expected.len() == credentials.len()
+            && expected
+                .as_bytes()
+                .iter()
+                .zip(credentials)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
+    /// Check the params of the `auth` request, that must be the first
+    /// request sent on a TCP connection. The params are `{ "token": ... }`
+    /// or `{ "user": ..., "password": ... }`.
+    fn check_params(&self, params: &Value) -> bool {
+        let credentials = match (
+            params.get("token"),
+            params.get("user"),
+            params.get("password"),
+        ) {
+            (Some(Value::String(token)), _, _) => Auth::Bearer(token.to_owned()),
+            (_, Some(Value::String(user)), Some(Value::String(password))) => Auth::Basic {
+                user: user.to_owned(),
+                password: password.to_owned(),
+            },
+            _ => return false,
+        };
+        let (scheme, credentials) = credentials.credentials();
+        self.check(&format!("{scheme} {credentials}"))
+    }
+}
+
+pub(crate) enum Listener {
+    Unix(UnixListener),
+    Tcp(TcpListener, Protocol, Option<Auth>),
+}
+
+impl Listener {
+    /// Accept a new connection, with the codec used to decode
+    /// the requests and encode the responses.
+    pub fn accept(&self) -> io::Result<(Stream, Codec)> {
+        match self {
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Stream::Unix(stream), Codec::json(None)))
+            }
+            Listener::Tcp(listener, protocol, auth) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                let codec = match protocol {
+                    Protocol::Tcp => Codec::json(auth.clone()),
+                    Protocol::Http => Codec::http(auth.clone()),
+                };
+                Ok((Stream::Tcp(stream), codec))
+            }
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Unix(listener) => listener.set_nonblocking(nonblocking),
+            Listener::Tcp(listener, ..) => listener.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Unix(listener) => listener.as_raw_fd(),
+            Listener::Tcp(listener, ..) => listener.as_raw_fd(),
+        }
+    }
+}
+
+pub(crate) enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.read(buf),
+            Stream::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.write(buf),
+            Stream::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(stream) => stream.flush(),
+            Stream::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Stream::Unix(stream) => stream.as_raw_fd(),
+            Stream::Tcp(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Unix(stream) => write!(f, "{:?}", stream),
+            Stream::Tcp(stream) => write!(f, "{:?}", stream),
+        }
+    }
+}
+
+/// A message decoded from a connection.
+pub(crate) enum Incoming {
+    /// A JSON RPC 2.0 message that should be handled by the workers.
+    Message(Value),
+    /// A reply already encoded, e.g. for a parse error.
+    Reply(Vec<u8>),
+    /// A reply already encoded, after which the connection is closed.
+    Reject(Vec<u8>),
+}
+
+/// Decode the requests and encode the responses of a connection.
+pub(crate) enum Codec {
+    /// A stream of JSON values, each response is followed by a new line.
+    ///
+    /// When the `auth` is not `None`, the connection is not authenticated
+    /// yet, so the first message must be the `auth` request.
+    Json {
+        decoder: framing::Decoder,
+        auth: Option<Auth>,
+    },
+    /// HTTP requests, each one with a JSON RPC 2.0 message in the body.
+    Http {
+        decoder: http::Decoder,
+        auth: Option<Auth>,
+    },
+}
+
+impl Codec {
+    fn json(auth: Option<Auth>) -> Self {
+        let decoder = match auth {
+            Some(_) => framing::Decoder::with_max_size(MAX_AUTH_SIZE),
+            None => framing::Decoder::new(),
+        };
+        Codec::Json { decoder, auth }
+    }
+
+    fn http(auth: Option<Auth>) -> Self {
+        Codec::Http {
+            decoder: http::Decoder::new(),
+            auth,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        match self {
+            Codec::Json { decoder, .. } => decoder.feed(bytes),
+            Codec::Http { decoder, .. } => decoder.feed(bytes),
+        }
+    }
+
+    /// Return true if the buffer is over the max size, so
+    /// the connection should not be read until it is decoded.
+    pub fn is_full(&self) -> bool {
+        match self {
+            Codec::Json { decoder, .. } => decoder.is_full(),
+            Codec::Http { decoder, .. } => decoder.is_full(),
+        }
+    }
+
+    /// Return true if there is no partial request inside the buffer.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Codec::Json { decoder, .. } => decoder.is_empty(),
+            Codec::Http { decoder, .. } => decoder.is_empty(),
+        }
+    }
+
+    pub fn decode(&mut self) -> Option<Incoming> {
+        match self {
+            Codec::Json { decoder, auth } => {
+                let message = match decoder.decode()? {
+                    Ok(message) => message,
+                    Err(framing::Error::Json(err)) => {
+                        return Some(Incoming::Reply(json_frame(Some(parse_error(err)))))
+                    }
+                    Err(err @ framing::Error::TooLarge(_)) => {
+                        log::warn!(target: "jsonrpc", "closing the connection: {err}");
+                        let error = RpcError {
+                            code: errors::INVALID_REQUEST,
+                            message: format!("{err}"),
+                            data: None,
+                        };
+                        let response = Response::<Value>::error(Id::Null, error);
+                        return Some(Incoming::Reject(json_frame(Some(json!(response)))));
+                    }
+                };
+                let Some(credentials) = auth else {
+                    return Some(Incoming::Message(message));
+                };
+                let id = message
+                    .get("id")
+                    .and_then(|id| serde_json::from_value::<Id>(id.clone()).ok())
+                    .unwrap_or(Id::Null);
+                let is_auth = message.get("method").and_then(Value::as_str) == Some("auth");
+                let params = message.get("params").unwrap_or(&Value::Null);
+                if is_auth && credentials.check_params(params) {
+                    *auth = None;
+                    decoder.set_max_size(framing::MAX_FRAME_SIZE);
+                    let response = Response::success(id, json!({}));
+                    return Some(Incoming::Reply(json_frame(Some(json!(response)))));
+                }
+                log::warn!(target: "jsonrpc", "unauthorized tcp connection");
+                let error = RpcError {
+                    code: errors::UNAUTHORIZED,
+                    message: "unauthorized".to_owned(),
+                    data: None,
+                };
+                let response = Response::<Value>::error(id, error);
+                Some(Incoming::Reject(json_frame(Some(json!(response)))))
+            }
+            Codec::Http { decoder, auth } => loop {
+                let request = match decoder.decode()? {
+                    Ok(http::Part::Head(request)) => request,
+                    Ok(http::Part::Body(body)) => {
+                        return match serde_json::from_slice::<Value>(&body) {
+                            Ok(message) => Some(Incoming::Message(message)),
+                            Err(err) => Some(Incoming::Reply(http_frame(Some(parse_error(err))))),
+                        };
+                    }
+                    Err(status) => return Some(Incoming::Reject(http::response(status, &[], &[]))),
+                };
+                // Nb. the head is checked before the body is received.
+                if request.method != "POST" {
+                    let allow = [("Allow", "POST")];
+                    let response = http::response(http::METHOD_NOT_ALLOWED, &allow, &[]);
+                    return Some(Incoming::Reject(response));
+                }
+                if let Some(credentials) = auth {
+                    let authorized = request
+                        .header("Authorization")
+                        .map(|authorization| credentials.check(authorization))
+                        .unwrap_or_default();
+                    if !authorized {
+                        log::warn!(target: "jsonrpc", "unauthorized http request");
+                        let challenge = [("WWW-Authenticate", credentials.challenge())];
+                        let response = http::response(http::UNAUTHORIZED, &challenge, &[]);
+                        return Some(Incoming::Reject(response));
+                    }
+                }
+            },
+        }
+    }
+
+    /// Return true if the connection can stream the
+    /// notifications of a subscription.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, Codec::Json { .. })
+    }
+
+    /// Encode the response of a message, `None` if the
+    /// message was a notification.
+    pub fn encode(&self, response: Option<Value>) -> Vec<u8> {
+        match self {
+            Codec::Json { .. } => json_frame(response),
+            Codec::Http { .. } => http_frame(response),
+        }
+    }
+}
+
+fn parse_error(err: serde_json::Error) -> Value {
+    log::warn!(target: "jsonrpc", "invalid json received: `{err}`");
+    let error = RpcError {
+        code: errors::PARSE_ERROR,
+        message: format!("parse error: {err}"),
+        data: None,
+    };
+    json!(Response::<Value>::error(Id::Null, error))
+}
+
+fn json_frame(response: Option<Value>) -> Vec<u8> {
+    let Some(response) = response else {
+        return Vec::new();
+    };
+    log::trace!(target: "jsonrpc", "send response: `{:?}`", response);
+    // SAFETY: the response should be a valid json.
+    let mut buff = serde_json::to_vec(&response).unwrap();
+    buff.push(b'\n');
+    buff
+}
+
+fn http_frame(response: Option<Value>) -> Vec<u8> {
+    let Some(response) = response else {
+        return http::response(http::NO_CONTENT, &[], &[]);
+    };
+    log::trace!(target: "jsonrpc", "send response: `{:?}`", response);
+    // SAFETY: the response should be a valid json.
+    let body = serde_json::to_vec(&response).unwrap();
+    http::response(http::OK, &[("Content-Type", "application/json")], &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Auth;
+
+    #[test]
+    fn check_authorization() {
+        let auth = Auth::Basic {
+            user: "lampo".to_owned(),
+            password: "lampo".to_owned(),
+        };
+        assert!(auth.check("Basic bGFtcG86bGFtcG8="));
+        assert!(auth.check("basic bGFtcG86bGFtcG8="));
+        assert!(auth.check("BASIC  bGFtcG86bGFtcG8="));
+        assert!(!auth.check("Basic bGFtcG86bGFtcG9="));
+        assert!(!auth.check("Bearer bGFtcG86bGFtcG8="));
+        assert!(!auth.check("bGFtcG86bGFtcG8="));
+        assert!(auth.check_params(&json!({ "user": "lampo", "password": "lampo" })));
+        assert!(!auth.check_params(&json!({ "token": "lampo" })));
+
+        let auth = Auth::Bearer("secret".to_owned());
+        assert!(auth.check("Bearer secret"));
+        assert!(!auth.check("Bearer secre"));
+        assert!(auth.check_params(&json!({ "token": "secret" })));
+    }
+}
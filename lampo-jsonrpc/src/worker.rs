@@ -0,0 +1,120 @@
+//! Pool of threads where the RPC callbacks are executed.
+//!
+//! The reactor only reads and writes on the sockets, so a slow
+//! callback (e.g. a `pay` that waits for the payment result) does not
+//! block the other clients. The result of each job is sent back to
+//! the reactor, that is woken up by the `Waker`.
+use std::io;
+use std::os::fd::RawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use popol::Waker;
+use serde_json::Value;
+
+use crate::subscription::Subscriber;
+use crate::Handler;
+
+/// A message read from a connection.
+pub(crate) struct Job {
+    pub fd: RawFd,
+    /// Identifier of the connection, used to discard the responses
+    /// when the file descriptor is reused by a new connection.
+    pub conn: u64,
+    /// Position of the message inside the connection stream.
+    pub seq: u64,
+    pub message: Value,
+    /// Set when the connection is closed, `None` when the
+    /// connection does not support subscriptions.
+    pub closed: Option<Arc<AtomicBool>>,
+}
+
+/// The output of a job, or a notification of a subscription.
+pub(crate) struct Done {
+    pub fd: RawFd,
+    pub conn: u64,
+    pub seq: u64,
+    pub output: Output,
+}
+
+pub(crate) enum Output {
+    /// The response of a message, if any.
+    Response(Option<Value>),
+    /// The response of a subscription, the connection is kept
+    /// open to stream the notifications.
+    Subscribed(Value),
+    /// A notification of the subscription started by the request `seq`.
+    Notification(Value),
+}
+
+/// The workers stop when the pool is dropped, because
+/// the jobs channel is closed.
+pub(crate) struct WorkerPool {
+    jobs: Sender<Job>,
+}
+
+impl WorkerPool {
+    pub fn new<T: Send + Sync + 'static>(
+        size: usize,
+        handler: Arc<Handler<T>>,
+        waker: Arc<Waker>,
+    ) -> io::Result<(Self, Receiver<Done>)> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let (results, done) = mpsc::channel::<Done>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for id in 0..size {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            let results = results.clone();
+            let waker = waker.clone();
+            std::thread::Builder::new()
+                .name(format!("jsonrpc-worker-{id}"))
+                .spawn(move || loop {
+                    // SAFETY: the lock is poisoned only if a worker panics
+                    // while is waiting for a job, that should never happens.
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        // the server is stopped.
+                        Err(_) => return,
+                    };
+                    let subscriber = job.closed.map(|closed| Subscriber {
+                        fd: job.fd,
+                        conn: job.conn,
+                        seq: job.seq,
+                        closed,
+                        results: results.clone(),
+                        waker: waker.clone(),
+                    });
+                    let output = panic::catch_unwind(AssertUnwindSafe(|| {
+                        handler
+                            .subscribe(&job.message, subscriber)
+                            .unwrap_or_else(|| Output::Response(handler.handle(job.message)))
+                    }))
+                    .unwrap_or_else(|_| {
+                        log::error!(target: "jsonrpc", "the rpc callback panicked");
+                        Output::Response(None)
+                    });
+                    let done = Done {
+                        fd: job.fd,
+                        conn: job.conn,
+                        seq: job.seq,
+                        output,
+                    };
+                    if results.send(done).is_err() {
+                        return;
+                    }
+                    if let Err(err) = waker.wake() {
+                        log::error!(target: "jsonrpc", "impossible wake up the reactor: {err}");
+                    }
+                })?;
+        }
+        Ok((Self { jobs }, done))
+    }
+
+    pub fn execute(&self, job: Job) {
+        // SAFETY: the workers never stop while the pool is alive.
+        self.jobs.send(job).unwrap();
+    }
+}
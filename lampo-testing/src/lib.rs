@@ -17,6 +17,8 @@ use lampo_common::model::response;
 use lampo_common::model::response::NewAddress;
 use lampod::jsonrpc::channels::json_close_channel;
 use lampod::jsonrpc::offchain::json_keysend;
+use lampod::jsonrpc::offchain::json_list_forwards;
+use lampod::jsonrpc::offchain::{json_list_invoices, json_list_payments, json_list_sendpays};
 use tempfile::TempDir;
 
 use lampo_bitcoind::BitcoinCore;
@@ -27,15 +29,24 @@ use lampo_jsonrpc::JSONRPCv2;
 use lampod::actions::handler::LampoHandler;
 use lampod::chain::WalletManager;
 use lampod::jsonrpc::channels::json_list_channels;
+use lampod::jsonrpc::channels::json_set_channel_fee;
+use lampod::jsonrpc::events::json_subscribe;
+use lampod::jsonrpc::gossip::{json_graph_stats, json_list_gossip_channels, json_list_nodes};
 use lampod::jsonrpc::inventory::get_info;
 use lampod::jsonrpc::offchain::json_decode_invoice;
+use lampod::jsonrpc::offchain::json_get_route;
 use lampod::jsonrpc::offchain::json_invoice;
 use lampod::jsonrpc::offchain::json_offer;
 use lampod::jsonrpc::offchain::json_pay;
+use lampod::jsonrpc::offchain::{json_pay_status, json_wait_send_pay};
 use lampod::jsonrpc::onchain::json_funds;
 use lampod::jsonrpc::onchain::json_new_addr;
-use lampod::jsonrpc::open_channel::json_open_channel;
+use lampod::jsonrpc::onchain::json_withdraw;
+use lampod::jsonrpc::open_channel::{
+    json_fund_channel_complete, json_fund_channel_start, json_multi_fund_channel, json_open_channel,
+};
 use lampod::jsonrpc::peer_control::json_connect;
+use lampod::jsonrpc::peer_control::{json_disconnect, json_list_peers};
 use lampod::jsonrpc::CommandHandler;
 use lampod::LampoDaemon;
 
@@ -109,10 +120,22 @@ impl LampoTesting {
         let server = JSONRPCv2::new(lampo.clone(), &socket_path)?;
         server.add_rpc("getinfo", get_info).unwrap();
         server.add_rpc("connect", json_connect).unwrap();
+        server.add_rpc("listpeers", json_list_peers).unwrap();
+        server.add_rpc("disconnect", json_disconnect).unwrap();
         server.add_rpc("fundchannel", json_open_channel).unwrap();
+        server
+            .add_rpc("multifundchannel", json_multi_fund_channel)
+            .unwrap();
+        server
+            .add_rpc("fundchannel_start", json_fund_channel_start)
+            .unwrap();
+        server
+            .add_rpc("fundchannel_complete", json_fund_channel_complete)
+            .unwrap();
         server.add_rpc("newaddr", json_new_addr).unwrap();
         server.add_rpc("channels", json_list_channels).unwrap();
         server.add_rpc("funds", json_funds).unwrap();
+        server.add_rpc("withdraw", json_withdraw).unwrap();
         server.add_rpc("invoice", json_invoice).unwrap();
         server.add_rpc("offer", json_offer).unwrap();
         server
@@ -121,7 +144,25 @@ impl LampoTesting {
 
         server.add_rpc("pay", json_pay).unwrap();
         server.add_rpc("keysend", json_keysend).unwrap();
+        server.add_rpc("listinvoices", json_list_invoices).unwrap();
+        server.add_rpc("listpayments", json_list_payments).unwrap();
+        server.add_rpc("listsendpays", json_list_sendpays).unwrap();
+        server.add_rpc("listforwards", json_list_forwards).unwrap();
+        server.add_rpc("paystatus", json_pay_status).unwrap();
+        server.add_rpc("waitsendpay", json_wait_send_pay).unwrap();
+        server.add_rpc("getroute", json_get_route).unwrap();
+        server.add_rpc("listnodes", json_list_nodes).unwrap();
+        server
+            .add_rpc("listgossipchannels", json_list_gossip_channels)
+            .unwrap();
+        server.add_rpc("graphstats", json_graph_stats).unwrap();
+        server
+            .add_rpc("setchannelfee", json_set_channel_fee)
+            .unwrap();
         server.add_rpc("close", json_close_channel).unwrap();
+        server
+            .add_subscription("subscribe", json_subscribe)
+            .unwrap();
         let handler = server.handler();
         let rpc_handler = Arc::new(CommandHandler::new(&lampo_conf)?);
         rpc_handler.set_handler(handler);
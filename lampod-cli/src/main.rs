@@ -3,6 +3,7 @@ mod args;
 
 use std::env;
 use std::io;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -16,22 +17,35 @@ use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::logger;
 use lampo_core_wallet::CoreWalletManager;
+use lampo_jsonrpc::Auth;
 use lampo_jsonrpc::Handler;
 use lampo_jsonrpc::JSONRPCv2;
+use lampo_jsonrpc::Protocol;
 use lampod::chain::WalletManager;
 use lampod::jsonrpc::channels::json_close_channel;
 use lampod::jsonrpc::channels::json_list_channels;
+use lampod::jsonrpc::channels::json_set_channel_fee;
+use lampod::jsonrpc::events::json_subscribe;
+use lampod::jsonrpc::gossip::{json_graph_stats, json_list_gossip_channels, json_list_nodes};
 use lampod::jsonrpc::inventory::get_info;
 use lampod::jsonrpc::offchain::json_decode_invoice;
+use lampod::jsonrpc::offchain::json_get_route;
 use lampod::jsonrpc::offchain::json_invoice;
 use lampod::jsonrpc::offchain::json_keysend;
+use lampod::jsonrpc::offchain::json_list_forwards;
 use lampod::jsonrpc::offchain::json_offer;
 use lampod::jsonrpc::offchain::json_pay;
+use lampod::jsonrpc::offchain::{json_list_invoices, json_list_payments, json_list_sendpays};
+use lampod::jsonrpc::offchain::{json_pay_status, json_wait_send_pay};
 use lampod::jsonrpc::onchain::json_estimate_fees;
 use lampod::jsonrpc::onchain::json_funds;
 use lampod::jsonrpc::onchain::json_new_addr;
-use lampod::jsonrpc::open_channel::json_open_channel;
+use lampod::jsonrpc::onchain::json_withdraw;
+use lampod::jsonrpc::open_channel::{
+    json_fund_channel_complete, json_fund_channel_start, json_multi_fund_channel, json_open_channel,
+};
 use lampod::jsonrpc::peer_control::json_connect;
+use lampod::jsonrpc::peer_control::{json_disconnect, json_list_peers};
 use lampod::jsonrpc::CommandHandler;
 use lampod::LampoDaemon;
 
@@ -171,13 +185,45 @@ fn run_jsonrpc(
     // that it is running.
     let _ = std::fs::remove_file(socket_path.clone());
     env::set_var("LAMPO_UNIX", socket_path.clone());
-    let server = JSONRPCv2::new(lampod, &socket_path)?;
+    let conf = lampod.conf().clone();
+    let mut server = JSONRPCv2::new(lampod, &socket_path)?;
+    let auth = rpc_auth(&conf)?;
+    for (addr, protocol) in [
+        (&conf.rpc_http_bind, Protocol::Http),
+        (&conf.rpc_tcp_bind, Protocol::Tcp),
+    ] {
+        let Some(addr) = addr else {
+            continue;
+        };
+        let is_loopback = addr
+            .parse::<SocketAddr>()
+            .map(|addr| addr.ip().is_loopback())
+            .unwrap_or(false);
+        if auth.is_none() && !is_loopback {
+            error::bail!(
+                "the rpc listener on `{addr}` requires `rpc-token` or `rpc-user` and `rpc-pass`"
+            );
+        }
+        server.add_tcp_listener(addr, protocol, auth.clone())?;
+    }
     server.add_rpc("getinfo", get_info).unwrap();
     server.add_rpc("connect", json_connect).unwrap();
+    server.add_rpc("listpeers", json_list_peers).unwrap();
+    server.add_rpc("disconnect", json_disconnect).unwrap();
     server.add_rpc("fundchannel", json_open_channel).unwrap();
+    server
+        .add_rpc("multifundchannel", json_multi_fund_channel)
+        .unwrap();
+    server
+        .add_rpc("fundchannel_start", json_fund_channel_start)
+        .unwrap();
+    server
+        .add_rpc("fundchannel_complete", json_fund_channel_complete)
+        .unwrap();
     server.add_rpc("newaddr", json_new_addr).unwrap();
     server.add_rpc("channels", json_list_channels).unwrap();
     server.add_rpc("funds", json_funds).unwrap();
+    server.add_rpc("withdraw", json_withdraw).unwrap();
     server.add_rpc("invoice", json_invoice).unwrap();
     server.add_rpc("offer", json_offer).unwrap();
     server
@@ -185,8 +231,42 @@ fn run_jsonrpc(
         .unwrap();
     server.add_rpc("pay", json_pay).unwrap();
     server.add_rpc("keysend", json_keysend).unwrap();
+    server.add_rpc("listinvoices", json_list_invoices).unwrap();
+    server.add_rpc("listpayments", json_list_payments).unwrap();
+    server.add_rpc("listsendpays", json_list_sendpays).unwrap();
+    server.add_rpc("listforwards", json_list_forwards).unwrap();
+    server.add_rpc("paystatus", json_pay_status).unwrap();
+    server.add_rpc("waitsendpay", json_wait_send_pay).unwrap();
+    server.add_rpc("getroute", json_get_route).unwrap();
+    server.add_rpc("listnodes", json_list_nodes).unwrap();
+    server
+        .add_rpc("listgossipchannels", json_list_gossip_channels)
+        .unwrap();
+    server.add_rpc("graphstats", json_graph_stats).unwrap();
+    server
+        .add_rpc("setchannelfee", json_set_channel_fee)
+        .unwrap();
     server.add_rpc("fees", json_estimate_fees).unwrap();
     server.add_rpc("close", json_close_channel).unwrap();
+    server
+        .add_subscription("subscribe", json_subscribe)
+        .unwrap();
     let handler = server.handler();
     Ok((server.spawn(), handler))
 }
+
+/// Credentials required by the TCP and HTTP listeners of the JSON RPC server.
+fn rpc_auth(conf: &LampoConf) -> error::Result<Option<Auth>> {
+    let auth = match (&conf.rpc_token, &conf.rpc_user, &conf.rpc_pass) {
+        (Some(token), None, None) => Some(Auth::Bearer(token.clone())),
+        (None, Some(user), Some(pass)) => Some(Auth::Basic {
+            user: user.clone(),
+            password: pass.clone(),
+        }),
+        (None, None, None) => None,
+        _ => {
+            error::bail!("use `rpc-token` or `rpc-user` and `rpc-pass` for the rpc authentication")
+        }
+    };
+    Ok(auth)
+}
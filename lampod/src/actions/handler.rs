@@ -1,6 +1,5 @@
 //! Handler module implementation that
-use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use lampo_common::chan;
 use lampo_common::error;
@@ -12,14 +11,19 @@ use lampo_common::json;
 use lampo_common::ldk;
 use lampo_common::model::response::PaymentHop;
 use lampo_common::model::response::PaymentState;
-use lampo_common::types::ChannelState;
+use lampo_common::model::response::PaymentFailure;
+use lampo_common::model::response::{ForwardRecord, ForwardStatus};
+use lampo_common::model::response::{PaymentStatus, SendPayRecord};
+use lampo_common::types::{ChannelId, ChannelState, NodeId};
 use lampo_jsonrpc::json_rpc2::Request;
 
-use crate::chain::{LampoChainManager, WalletManager};
+use crate::chain::{LampoBumpHandler, LampoSweeper, WalletManager};
 use crate::command::Command;
 use crate::handler::external_handler::ExternalHandler;
 use crate::ln::events::PeerEvents;
 use crate::ln::{LampoChannelManager, LampoInventoryManager, LampoPeerManager};
+use crate::persistence::payments::now;
+use crate::persistence::PaymentStore;
 use crate::{async_run, LampoDaemon};
 
 use super::{Handler, InventoryHandler};
@@ -29,8 +33,10 @@ pub struct LampoHandler {
     peer_manager: Arc<LampoPeerManager>,
     inventory_manager: Arc<LampoInventoryManager>,
     wallet_manager: Arc<dyn WalletManager>,
-    chain_manager: Arc<LampoChainManager>,
-    external_handlers: RefCell<Vec<Arc<dyn ExternalHandler>>>,
+    payments: Arc<PaymentStore>,
+    sweeper: Arc<LampoSweeper>,
+    bump_handler: Arc<LampoBumpHandler>,
+    external_handlers: RwLock<Vec<Arc<dyn ExternalHandler>>>,
     #[allow(dead_code)]
     emitter: Emitter<Event>,
     subscriber: Subscriber<Event>,
@@ -48,19 +54,33 @@ impl LampoHandler {
             peer_manager: lampod.peer_manager(),
             inventory_manager: lampod.inventory_manager(),
             wallet_manager: lampod.wallet_manager(),
-            chain_manager: lampod.onchain_manager(),
-            external_handlers: RefCell::new(Vec::new()),
+            payments: lampod.offchain_manager().payments(),
+            sweeper: lampod.sweeper(),
+            bump_handler: lampod.bump_handler(),
+            external_handlers: RwLock::new(Vec::new()),
             emitter,
             subscriber,
         }
     }
 
     pub fn add_external_handler(&self, handler: Arc<dyn ExternalHandler>) -> error::Result<()> {
-        let mut vect = self.external_handlers.borrow_mut();
+        let mut vect = self.external_handlers.write().unwrap();
         vect.push(handler);
         Ok(())
     }
 
+    /// Drop a channel that we are not able to fund, the `ChannelClosed`
+    /// event then cleans up the state kept for it.
+    fn abandon_channel(&self, temporary_channel_id: &ChannelId, counterparty_node_id: &NodeId) {
+        if let Err(err) = self
+            .channel_manager
+            .manager()
+            .force_close_without_broadcasting_txn(temporary_channel_id, counterparty_node_id)
+        {
+            log::error!("impossible abandon the channel `{temporary_channel_id}`: {:?}", err);
+        }
+    }
+
     /// Call any method supported by the lampod configuration. This includes
     /// a lot of handler code. This function serves as a broker pattern in some ways,
     /// but it may also function as a chain of responsibility pattern in certain cases.
@@ -112,9 +132,9 @@ impl Handler for LampoHandler {
             Command::ExternalCommand(req, chan) => {
                 log::info!(
                     "external handler size {}",
-                    self.external_handlers.borrow().len()
+                    self.external_handlers.read().unwrap().len()
                 );
-                for handler in self.external_handlers.borrow().iter() {
+                for handler in self.external_handlers.read().unwrap().iter() {
                     if let Some(resp) = handler.handle(&req)? {
                         chan.send(resp)?;
                         return Ok(());
@@ -135,7 +155,46 @@ impl Handler for LampoHandler {
                 push_msat,
                 channel_type,
             } => {
-                Err(error::anyhow!("Request for open a channel received, unfortunatly we do not support this feature yet."))
+                let policy = &self.channel_manager.conf.inbound_policy;
+                let manager = self.channel_manager.manager();
+                let pending = manager
+                    .list_channels()
+                    .into_iter()
+                    .filter(|channel| !channel.is_outbound && !channel.is_channel_ready)
+                    .collect::<Vec<_>>();
+                let pending_channels = pending
+                    .iter()
+                    .filter(|channel| channel.counterparty.node_id == counterparty_node_id)
+                    .count();
+                let check = policy.check(&counterparty_node_id, funding_satoshis, pending_channels, pending.len()).and_then(|_| {
+                    if !channel_type.supports_anchors_zero_fee_htlc_tx() {
+                        return Ok(());
+                    }
+                    // the fee of the commitment transaction is bumped with our funds.
+                    self.channel_manager.check_anchor_reserve(0).map_err(|err| err.to_string())
+                });
+                if let Err(reason) = check {
+                    log::warn!("rejecting channel `{temporary_channel_id}` from `{counterparty_node_id}`: {reason}");
+                    manager
+                        .force_close_without_broadcasting_txn(&temporary_channel_id, &counterparty_node_id)
+                        .map_err(|err| error::anyhow!("{:?}", err))?;
+                    self.emit(Event::Lightning(LightningEvent::ChannelRejected {
+                        counterparty_node_id,
+                        temporary_channel_id,
+                        channel_value_satoshis: funding_satoshis,
+                        reason,
+                    }));
+                    return Ok(());
+                }
+                let result = if policy.is_zero_conf(&counterparty_node_id) {
+                    log::info!("accepting zero-conf channel `{temporary_channel_id}` from `{counterparty_node_id}`");
+                    manager.accept_inbound_channel_from_trusted_peer_0conf(&temporary_channel_id, &counterparty_node_id, 0)
+                } else {
+                    log::info!("accepting channel `{temporary_channel_id}` from `{counterparty_node_id}`");
+                    manager.accept_inbound_channel(&temporary_channel_id, &counterparty_node_id, 0)
+                };
+                result.map_err(|err| error::anyhow!("{:?}", err))?;
+                Ok(())
             }
             ldk::events::Event::ChannelReady {
                 channel_id,
@@ -164,6 +223,13 @@ impl Handler for LampoHandler {
                 }
                 let node_id = counterparty_node_id.map(|id| id.to_string());
                 let txo = channel_funding_txo.map(|txo| txo.to_string());
+                // the channel was closed before it was funded.
+                self.channel_manager.take_funding_options(user_channel_id);
+                self.channel_manager.cancel_external_funding(user_channel_id);
+                self.channel_manager.cancel_batch(user_channel_id, &format!("channel `{channel_id}` closed with reason: `{reason}`"));
+                if let Err(err) = self.channel_manager.remove_close_to(user_channel_id) {
+                    log::error!("impossible remove the `close_to` of the channel `{channel_id}`: {err}");
+                }
                 self.emit(Event::Lightning(LightningEvent::CloseChannelEvent { channel_id: channel_id.to_string(), message: reason.to_string(), counterparty_node_id : node_id, funding_utxo : txo}));
                 log::info!("channel `{user_channel_id}` closed with reason: `{reason}`");
                 Ok(())
@@ -173,27 +239,51 @@ impl Handler for LampoHandler {
                 counterparty_node_id,
                 channel_value_satoshis,
                 output_script,
-                ..
+                user_channel_id,
             } => {
+                // Nb. the funding output is stored before emitting the event, because `fundchannel_start` waits for it.
+                let external = self.channel_manager.external_funding_ready(user_channel_id, temporary_channel_id, counterparty_node_id, output_script.clone(), channel_value_satoshis);
                 self.emit(Event::Lightning(LightningEvent::FundingChannelStart {
                     counterparty_node_id,
                     temporary_channel_id,
                     channel_value_satoshis,
                 }));
+                // the funding transaction is built outside of the node, see `fundchannel_complete`.
+                if external {
+                    return Ok(());
+                }
 
+                // the channel is funded by the same transaction of the other channels of the batch.
+                if self.channel_manager.fund_batch(user_channel_id, temporary_channel_id, output_script.clone(), channel_value_satoshis)? {
+                    return Ok(());
+                }
                 log::info!("propagate funding transaction for open a channel with `{counterparty_node_id}`");
-                // FIXME: estimate the fee rate with a callback
-                let fee = self.chain_manager.backend.fee_rate_estimation(6).map_err(|err| {
-                    let msg = format!("Channel Opening Error: {err}");
-                    self.emit(Event::Lightning(LightningEvent::ChannelEvent { state: ChannelState::OpeningError, message : msg}));
-                    err
-                })?;
+                // the options chosen by the user when opening the channel, if any.
+                let options = self.channel_manager.take_funding_options(user_channel_id).unwrap_or_default();
+                let fee = match options.feerate {
+                    Some(fee) => fee,
+                    // FIXME: estimate the fee rate with a callback
+                    None => self.channel_manager.estimate_funding_feerate(6).map_err(|err| {
+                        let msg = format!("Channel Opening Error: {err}");
+                        self.emit(Event::Lightning(LightningEvent::ChannelEvent { state: ChannelState::OpeningError, message : msg}));
+                        self.abandon_channel(&temporary_channel_id, &counterparty_node_id);
+                        err
+                    })?,
+                };
                 log::info!("fee estimated {:?} sats", fee);
                 let transaction = self.wallet_manager.create_transaction(
                     output_script,
                     channel_value_satoshis,
                     fee,
-                )?;
+                    &options.utxos,
+                    options.minconf,
+                ).map_err(|err| {
+                    // the rpc does not wait the funding anymore, so report the error with an event.
+                    let msg = format!("Channel Opening Error: {err}");
+                    self.emit(Event::Lightning(LightningEvent::ChannelEvent { state: ChannelState::OpeningError, message : msg}));
+                    self.abandon_channel(&temporary_channel_id, &counterparty_node_id);
+                    err
+                })?;
                 log::info!("funding transaction created `{}`", transaction.txid());
                 log::info!(
                     "transaction hex `{}`",
@@ -274,20 +364,138 @@ impl Handler for LampoHandler {
                     ldk::events::PaymentPurpose::Bolt12RefundPayment { payment_preimage, payment_secret, .. } => (payment_preimage, Some(payment_secret)),
                     ldk::events::PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None),
                 };
-                log::warn!("please note the payments are not make persistent for the moment");
-                // FIXME: make peristant these information
+                log::info!("payment `{payment_hash}` claimed for {amount_msat} msat");
+                self.payments.invoice_paid(&payment_hash, amount_msat, payment_preimage)?;
                 Ok(())
             }
-            ldk::events::Event::PaymentSent { .. } => {
+            ldk::events::Event::PaymentSent { ref payment_id, ref payment_preimage, fee_paid_msat, .. } => {
                 log::info!("payment sent: `{:?}`", event);
+                let Some(payment_id) = payment_id else {
+                    return Ok(());
+                };
+                self.payments.payment_sent(payment_id, payment_preimage, fee_paid_msat)?;
+                // Nb. this terminates the payment, also when it is split in more parts,
+                // the paths are reported later by `PaymentPathSuccessful`.
+                let payment_hash = self.payments.payment(payment_id)?.and_then(|payment| payment.payment_hash);
+                let hop = LightningEvent::PaymentEvent { payment_id: hex::encode(payment_id.0), state: PaymentState::Success, payment_hash, path: Vec::new(), payment_preimage: Some(hex::encode(payment_preimage.0)), fee_msat: fee_paid_msat, failure: None };
+                self.emit(Event::Lightning(hop));
+                Ok(())
+            },
+            ldk::events::Event::PaymentFailed { payment_id, reason, .. } => {
+                let reason = reason.map(|reason| format!("{:?}", reason)).unwrap_or("unknown".to_owned());
+                log::warn!("payment `{}` failed: {reason}", hex::encode(payment_id.0));
+                self.payments.payment_failed(&payment_id, reason.clone())?;
+                let payment_hash = self.payments.payment(&payment_id)?.and_then(|payment| payment.payment_hash);
+                // the last attempt tells us where the payment got stuck.
+                let attempt = self.payments.last_failed_sendpay(&payment_id)?;
+                let failing_hop = attempt.as_ref().and_then(|attempt| attempt.path.iter().find(|hop| Some(hop.short_channel_id) == attempt.short_channel_id).cloned());
+                let failure = PaymentFailure {
+                    reason,
+                    last_error: attempt.as_ref().and_then(|attempt| attempt.failure.clone()),
+                    short_channel_id: attempt.as_ref().and_then(|attempt| attempt.short_channel_id),
+                    failing_hop,
+                };
+                let hop = LightningEvent::PaymentEvent { payment_id: hex::encode(payment_id.0), state: PaymentState::Faulure, payment_hash, path: attempt.map(|attempt| attempt.path).unwrap_or_default(), payment_preimage: None, fee_msat: None, failure: Some(failure) };
+                self.emit(Event::Lightning(hop));
                 Ok(())
             },
-            ldk::events::Event::PaymentPathSuccessful { payment_hash, path, .. } => {
-                let path = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
-                let hop = LightningEvent::PaymentEvent { state: PaymentState::Success, payment_hash: payment_hash.map(|hash| hash.to_string()), path };
+            ldk::events::Event::PaymentPathSuccessful { payment_id, payment_hash, path, .. } => {
+                let hops = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
+                self.payments.add_sendpay(SendPayRecord {
+                    payment_id: hex::encode(payment_id.0),
+                    payment_hash: payment_hash.map(|hash| hex::encode(hash.0)),
+                    status: PaymentStatus::Complete,
+                    amount_msat: path.final_value_msat(),
+                    fee_msat: path.fee_msat(),
+                    path: hops.clone(),
+                    failure: None,
+                    short_channel_id: None,
+                    created_at: now(),
+                })?;
+                let hop = LightningEvent::PaymentPathSuccessful {
+                    payment_id: hex::encode(payment_id.0),
+                    payment_hash: payment_hash.map(|hash| hash.to_string()),
+                    path: hops,
+                };
                 self.emit(Event::Lightning(hop));
                 Ok(())
             },
+            ldk::events::Event::PaymentPathFailed { payment_id, payment_hash, ref failure, ref path, short_channel_id, .. } => {
+                let Some(payment_id) = payment_id else {
+                    log::warn!("payment path failed for `{payment_hash}`: {:?}", failure);
+                    return Ok(());
+                };
+                self.payments.add_sendpay(SendPayRecord {
+                    payment_id: hex::encode(payment_id.0),
+                    payment_hash: Some(hex::encode(payment_hash.0)),
+                    status: PaymentStatus::Failed,
+                    amount_msat: path.final_value_msat(),
+                    fee_msat: path.fee_msat(),
+                    path: path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect(),
+                    failure: Some(format!("{:?}", failure)),
+                    short_channel_id,
+                    created_at: now(),
+                })?;
+                Ok(())
+            },
+            ldk::events::Event::PaymentForwarded { prev_channel_id, next_channel_id, total_fee_earned_msat, claim_from_onchain_tx, outbound_amount_forwarded_msat, .. } => {
+                log::info!("payment forwarded from `{:?}` to `{:?}` earning `{:?}` msat", prev_channel_id, next_channel_id, total_fee_earned_msat);
+                let in_msat = outbound_amount_forwarded_msat.zip(total_fee_earned_msat).map(|(out, fee)| out + fee);
+                self.payments.add_forward(ForwardRecord {
+                    in_channel_id: prev_channel_id.map(|id| id.to_string()),
+                    out_channel_id: next_channel_id.map(|id| id.to_string()),
+                    in_msat,
+                    out_msat: outbound_amount_forwarded_msat,
+                    fee_msat: total_fee_earned_msat,
+                    status: ForwardStatus::Settled,
+                    failure: None,
+                    claim_from_onchain_tx,
+                    resolved_at: now(),
+                })?;
+                Ok(())
+            },
+            ldk::events::Event::HTLCHandlingFailed { prev_channel_id, failed_next_destination } => {
+                // a payment to us that failed, so it is not a forward.
+                if let ldk::events::HTLCDestination::FailedPayment { payment_hash } = &failed_next_destination {
+                    log::warn!("failed to receive the payment `{payment_hash}`");
+                    return Ok(());
+                }
+                log::warn!("failed to forward the HTLC from `{prev_channel_id}`: {:?}", failed_next_destination);
+                let out_channel_id = match &failed_next_destination {
+                    ldk::events::HTLCDestination::NextHopChannel { channel_id, .. } => Some(channel_id.to_string()),
+                    _ => None,
+                };
+                self.payments.add_forward(ForwardRecord {
+                    in_channel_id: Some(prev_channel_id.to_string()),
+                    out_channel_id,
+                    in_msat: None,
+                    out_msat: None,
+                    fee_msat: None,
+                    status: ForwardStatus::Failed,
+                    failure: Some(format!("{:?}", failed_next_destination)),
+                    claim_from_onchain_tx: false,
+                    resolved_at: now(),
+                })?;
+                Ok(())
+            },
+            ldk::events::Event::BumpTransaction(event) => {
+                log::info!("bump transaction event: {:?}", event);
+                self.bump_handler.handle_event(&event);
+                Ok(())
+            },
+            ldk::events::Event::SpendableOutputs { outputs, channel_id } => {
+                log::info!("received {} spendable outputs from channel `{:?}`", outputs.len(), channel_id);
+                self.sweeper.track(outputs, channel_id.map(|id| id.to_string()))?;
+                Ok(())
+            },
+            ldk::events::Event::ProbeSuccessful { payment_hash, .. } => {
+                log::debug!("probe `{payment_hash}` successful");
+                Ok(())
+            },
+            ldk::events::Event::ProbeFailed { payment_hash, short_channel_id, .. } => {
+                log::debug!("probe `{payment_hash}` failed at channel `{:?}`", short_channel_id);
+                Ok(())
+            },
             _ => Err(error::anyhow!("unexpected ldk event: {:?}", event)),
         }
     }
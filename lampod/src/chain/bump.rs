@@ -0,0 +1,55 @@
+//! Fee bumping implementation for the anchor channels.
+//!
+//! With the anchor outputs the commitment and the HTLC transactions
+//! pay a low fee, and LDK asks us with the `BumpTransaction` event
+//! to bump it (CPFP) with the UTXOs of the on chain wallet.
+use std::sync::Arc;
+
+use lampo_common::bitcoin::psbt::PartiallySignedTransaction;
+use lampo_common::bitcoin::{ScriptBuf, Transaction};
+use lampo_common::ldk::events::bump_transaction::{
+    BumpTransactionEventHandler, Utxo, Wallet, WalletSource,
+};
+use lampo_common::ldk::sign::KeysManager;
+use lampo_common::wallet::WalletManager;
+
+use crate::chain::LampoChainManager;
+use crate::utils::logger::LampoLogger;
+
+pub type LampoBumpHandler = BumpTransactionEventHandler<
+    Arc<LampoChainManager>,
+    Arc<Wallet<Arc<LampoWalletSource>, Arc<LampoLogger>>>,
+    Arc<KeysManager>,
+    Arc<LampoLogger>,
+>;
+
+/// Expose the wallet manager to the LDK coin selection.
+pub struct LampoWalletSource {
+    wallet_manager: Arc<dyn WalletManager>,
+}
+
+impl LampoWalletSource {
+    pub fn new(wallet_manager: Arc<dyn WalletManager>) -> Self {
+        Self { wallet_manager }
+    }
+}
+
+impl WalletSource for LampoWalletSource {
+    fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+        self.wallet_manager.list_confirmed_utxos().map_err(|err| {
+            log::error!(target: "bump", "impossible list the confirmed utxos: {err}");
+        })
+    }
+
+    fn get_change_script(&self) -> Result<ScriptBuf, ()> {
+        self.wallet_manager.get_change_script().map_err(|err| {
+            log::error!(target: "bump", "impossible get the change script: {err}");
+        })
+    }
+
+    fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> Result<Transaction, ()> {
+        self.wallet_manager.sign_psbt(psbt).map_err(|err| {
+            log::error!(target: "bump", "impossible sign the psbt: {err}");
+        })
+    }
+}
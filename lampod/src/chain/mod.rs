@@ -1,7 +1,11 @@
 //! Chain module implementation that contains all the code related to the blockchain communication.
 mod blockchain;
+mod bump;
+mod sweeper;
 
 pub use lampo_common::bitcoin::Network;
 pub use lampo_common::wallet::WalletManager;
 
 pub use blockchain::LampoChainManager;
+pub use bump::{LampoBumpHandler, LampoWalletSource};
+pub use sweeper::LampoSweeper;
@@ -0,0 +1,419 @@
+//! Sweeper implementation.
+//!
+//! When a channel is closed, LDK gives us the outputs that we can
+//! spend with the `SpendableOutputs` event, and it is up to us to move
+//! them to the on chain wallet. The sweeper persists the descriptors
+//! as soon as they are received, so they survive a restart, and
+//! rebuild the sweeping transaction when the feerate goes up, until
+//! it is confirmed deep enough to be safe from a reorg.
+use std::io::{Cursor, ErrorKind};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use lampo_common::backend::TxResult;
+use lampo_common::bitcoin::consensus::{deserialize, serialize};
+use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::{Address, ScriptBuf, Transaction, Txid};
+use lampo_common::chan;
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::json;
+use lampo_common::json::prelude::serde::{Deserialize, Serialize};
+use lampo_common::ldk::chain::chaininterface::{
+    BroadcasterInterface, ConfirmationTarget, FeeEstimator,
+};
+use lampo_common::ldk::chain::channelmonitor::ANTI_REORG_DELAY;
+use lampo_common::ldk::sign::SpendableOutputDescriptor;
+use lampo_common::ldk::util::persist::KVStore;
+use lampo_common::ldk::util::ser::{Readable, Writeable};
+use lampo_common::secp256k1::Secp256k1;
+use lampo_common::wallet::WalletManager;
+
+use crate::chain::LampoChainManager;
+use crate::persistence::payments::now;
+use crate::persistence::LampoPersistence;
+
+const NAMESPACE: &str = "sweeper";
+const OUTPUTS: &str = "outputs";
+/// How much (in sats per 1000 weight) the feerate must go up to
+/// replace the sweeping transaction, a replacement must pay at
+/// least the incremental relay fee of 1 sat/vB (BIP 125).
+const INCREMENTAL_RELAY_FEE: u32 = 253;
+/// How many replaced transactions we keep looking at, the older
+/// ones are very unlikely to be confirmed.
+const MAX_REPLACED: usize = 10;
+
+/// The outputs received with a single `SpendableOutputs` event,
+/// and the transaction that sweeps them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "lampo_common::json::prelude::serde")]
+struct SweepRecord {
+    /// The descriptors encoded by LDK, in hex.
+    descriptors: Vec<String>,
+    channel_id: Option<String>,
+    /// The script (in hex) where the outputs are sent, it does not
+    /// change so a transaction is rebuilt only for a new feerate.
+    #[serde(default)]
+    destination: Option<String>,
+    /// The last sweeping transaction broadcasted, in hex.
+    tx: Option<String>,
+    txid: Option<String>,
+    /// The feerate (in sats per 1000 weight) of the last transaction.
+    #[serde(default)]
+    feerate: Option<u32>,
+    /// The sweeping transactions replaced by the last one, any
+    /// of them can still be confirmed.
+    #[serde(default)]
+    replaced: Vec<String>,
+    /// The sweeping transaction is confirmed deep enough, so
+    /// we stop looking at it.
+    confirmed: bool,
+    created_at: u64,
+}
+
+impl SweepRecord {
+    /// Store the new sweeping transaction, and return false
+    /// if it is the same one already broadcasted.
+    fn replace(&mut self, tx: &Transaction, feerate: u32) -> bool {
+        let txid = tx.txid().to_string();
+        if self.txid.as_ref() == Some(&txid) {
+            return false;
+        }
+        if let Some(previous) = self.txid.take() {
+            self.replaced.push(previous);
+        }
+        if self.replaced.len() > MAX_REPLACED {
+            self.replaced.remove(0);
+        }
+        self.tx = Some(hex::encode(serialize(tx)));
+        self.txid = Some(txid);
+        self.feerate = Some(feerate);
+        true
+    }
+
+    /// Return true if the sweeping transaction should be rebuilt
+    /// with the `feerate`, otherwise the last one is rebroadcasted.
+    fn should_bump(&self, feerate: u32) -> bool {
+        match (self.tx.as_ref(), self.feerate) {
+            (Some(_), Some(last)) => feerate >= last.saturating_add(INCREMENTAL_RELAY_FEE),
+            _ => true,
+        }
+    }
+
+    /// All the sweeping transactions broadcasted, the last one first.
+    fn txids(&self) -> Vec<String> {
+        self.txid
+            .iter()
+            .chain(self.replaced.iter().rev())
+            .cloned()
+            .collect()
+    }
+}
+
+pub struct LampoSweeper {
+    store: Arc<LampoPersistence>,
+    onchain: Arc<LampoChainManager>,
+    wallet_manager: Arc<dyn WalletManager>,
+    lock: Mutex<()>,
+}
+
+impl LampoSweeper {
+    pub fn new(
+        store: Arc<LampoPersistence>,
+        onchain: Arc<LampoChainManager>,
+        wallet_manager: Arc<dyn WalletManager>,
+    ) -> Self {
+        Self {
+            store,
+            onchain,
+            wallet_manager,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Persist the outputs and sweep them to the on chain wallet.
+    pub fn track(
+        &self,
+        outputs: Vec<SpendableOutputDescriptor>,
+        channel_id: Option<String>,
+    ) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let descriptors = outputs
+            .iter()
+            .map(|output| hex::encode(output.encode()))
+            .collect::<Vec<_>>();
+        let key = Self::key(&descriptors);
+        if self.read(&key)?.is_some() {
+            log::debug!(target: "sweeper", "outputs `{key}` are already tracked");
+            return Ok(());
+        }
+        // Nb. the outputs must be stored also when the wallet does not give
+        // us an address, the destination is chosen again at the next sweep.
+        let destination = self
+            .new_destination()
+            .map_err(|err| {
+                log::warn!(target: "sweeper", "impossible get the destination of the outputs `{key}`: {err}")
+            })
+            .ok();
+        let mut record = SweepRecord {
+            descriptors,
+            channel_id,
+            destination,
+            tx: None,
+            txid: None,
+            feerate: None,
+            replaced: Vec::new(),
+            confirmed: false,
+            created_at: now(),
+        };
+        // Nb. store the outputs before sweeping them, if we crash
+        // in between we can sweep them at the next block.
+        self.write(&key, &record)?;
+        self.sweep(&key, &mut record)
+    }
+
+    /// Check the sweeping transactions that are not confirmed yet,
+    /// replacing them when the feerate goes up.
+    ///
+    /// Nb. a failure with one of the outputs does not stop the others.
+    pub fn check_sweeps(&self) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        for key in self.keys()? {
+            let mut record = match self.read(&key) {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::error!(target: "sweeper", "impossible read the outputs `{key}`: {err}");
+                    continue;
+                }
+            };
+            if record.confirmed {
+                continue;
+            }
+            match self.confirmations(&record) {
+                Ok(Some(confirmations)) if confirmations >= ANTI_REORG_DELAY => {
+                    log::info!(target: "sweeper", "sweeping transaction of the outputs `{key}` confirmed");
+                    record.confirmed = true;
+                    if let Err(err) = self.write(&key, &record) {
+                        log::error!(target: "sweeper", "impossible store the outputs `{key}`: {err}");
+                    }
+                    continue;
+                }
+                // a reorg can still remove it, so we keep checking.
+                Ok(Some(_)) => continue,
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!(target: "sweeper", "impossible check the sweep of the outputs `{key}`: {err}");
+                    continue;
+                }
+            }
+            // the sweep failed before, we crashed before sweeping, or the
+            // transaction is still waiting, so we bump or rebroadcast it.
+            if let Err(err) = self.sweep(&key, &mut record) {
+                log::error!(target: "sweeper", "impossible sweep the outputs `{key}`: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the confirmations of the sweeping transaction that is
+    /// confirmed, if any.
+    fn confirmations(&self, record: &SweepRecord) -> error::Result<Option<u32>> {
+        for txid in record.txids() {
+            let Ok(txid) = Txid::from_str(&txid) else {
+                log::warn!(target: "sweeper", "invalid sweeping transaction id `{txid}`");
+                continue;
+            };
+            if let TxResult::Confirmed((_, _, _, height)) =
+                self.onchain.backend.get_transaction(&txid)?
+            {
+                let (_, best_height) = self.onchain.backend.get_best_block()?;
+                let confirmations = best_height
+                    .map(|best| best.saturating_sub(height.to_consensus_u32()) + 1)
+                    .unwrap_or(1);
+                return Ok(Some(confirmations));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Listen for new blocks and check the sweeping transactions.
+    pub fn listen(self: Arc<Self>, events: chan::Receiver<Event>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            log::info!(target: "sweeper", "listening on chain event on the sweeper");
+            // sweep the outputs left behind by the last run.
+            if let Err(err) = self.check_sweeps() {
+                log::error!(target: "sweeper", "{err}");
+            }
+            loop {
+                let Ok(event) = events.recv() else {
+                    break;
+                };
+                let Event::OnChain(OnChainEvent::NewBestBlock(_)) = event else {
+                    continue;
+                };
+                if let Err(err) = self.check_sweeps() {
+                    log::error!(target: "sweeper", "{err}");
+                }
+            }
+        })
+    }
+
+    fn sweep(&self, key: &str, record: &mut SweepRecord) -> error::Result<()> {
+        let feerate = self
+            .onchain
+            .get_est_sat_per_1000_weight(ConfirmationTarget::OutputSpendingFee);
+        if !record.should_bump(feerate) {
+            // SAFETY: there is a transaction to rebroadcast, otherwise we bump it.
+            let tx: Transaction = deserialize(&hex::decode(record.tx.as_ref().unwrap())?)?;
+            log::debug!(target: "sweeper", "rebroadcast the sweeping transaction `{}`", tx.txid());
+            self.onchain.broadcast_transactions(&[&tx]);
+            return Ok(());
+        }
+        let descriptors = record
+            .descriptors
+            .iter()
+            .map(|descriptor| {
+                let buff = hex::decode(descriptor)?;
+                SpendableOutputDescriptor::read(&mut Cursor::new(buff))
+                    .map_err(|err| error::anyhow!("invalid output descriptor: {:?}", err))
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        let script = match record.destination.as_ref() {
+            Some(destination) => ScriptBuf::from_bytes(hex::decode(destination)?),
+            None => {
+                let script = self.new_destination()?;
+                record.destination = Some(hex::encode(script.as_bytes()));
+                script
+            }
+        };
+        let tx = self
+            .wallet_manager
+            .ldk_keys()
+            .keys_manager
+            .spend_spendable_outputs(
+                &descriptors.iter().collect::<Vec<_>>(),
+                Vec::new(),
+                script,
+                feerate,
+                None,
+                &Secp256k1::new(),
+            )
+            .map_err(|_| error::anyhow!("impossible build the sweeping transaction"))?;
+        if record.replace(&tx, feerate) {
+            log::info!(target: "sweeper", "sweeping the outputs `{key}` with transaction `{}`", tx.txid());
+        }
+        self.write(key, record)?;
+        self.onchain.broadcast_transactions(&[&tx]);
+        Ok(())
+    }
+
+    /// A new address of the on chain wallet where the outputs are sent.
+    fn new_destination(&self) -> error::Result<ScriptBuf> {
+        let address = self.wallet_manager.get_onchain_address()?;
+        Ok(Address::from_str(&address.address)?
+            .assume_checked()
+            .script_pubkey())
+    }
+
+    /// The outputs do not have an id, so we use the hash of
+    /// their descriptors.
+    fn key(descriptors: &[String]) -> String {
+        let hash = Sha256::hash(descriptors.concat().as_bytes());
+        hex::encode(hash.to_byte_array())
+    }
+
+    fn read(&self, key: &str) -> error::Result<Option<SweepRecord>> {
+        match self.store.read(NAMESPACE, OUTPUTS, key) {
+            Ok(buff) => Ok(Some(json::from_slice(&buff)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, key: &str, record: &SweepRecord) -> error::Result<()> {
+        let buff = json::to_vec(record)?;
+        self.store.write(NAMESPACE, OUTPUTS, key, &buff)?;
+        Ok(())
+    }
+
+    fn keys(&self) -> error::Result<Vec<String>> {
+        match self.store.list(NAMESPACE, OUTPUTS) {
+            Ok(keys) => Ok(keys),
+            // nothing was stored yet.
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// SAFETY: the shared state is behind a lock.
+unsafe impl Send for LampoSweeper {}
+unsafe impl Sync for LampoSweeper {}
+
+#[cfg(test)]
+mod tests {
+    use lampo_common::bitcoin::absolute::LockTime;
+    use lampo_common::bitcoin::{ScriptBuf, Transaction, TxOut};
+    use lampo_common::json;
+
+    use super::SweepRecord;
+
+    fn sweep_tx(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![TxOut {
+                value,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn replace_the_sweeping_transaction() {
+        let mut record: SweepRecord = json::from_value(json::json!({
+            "descriptors": [],
+            "channel_id": null,
+            "tx": null,
+            "txid": null,
+            "confirmed": false,
+            "created_at": 0,
+        }))
+        .unwrap();
+        assert!(record.txids().is_empty());
+
+        assert!(record.should_bump(253));
+
+        let first = sweep_tx(1000);
+        assert!(record.replace(&first, 253));
+        // the same transaction is only rebroadcasted.
+        assert!(!record.replace(&first, 253));
+        assert_eq!(record.txids(), vec![first.txid().to_string()]);
+        // a replacement must pay more than the last transaction.
+        assert!(!record.should_bump(253));
+        assert!(!record.should_bump(505));
+        assert!(record.should_bump(506));
+
+        // with an higher feerate the output is smaller.
+        let second = sweep_tx(900);
+        assert!(record.replace(&second, 506));
+        assert_eq!(record.txid, Some(second.txid().to_string()));
+        assert_eq!(record.feerate, Some(506));
+        assert_eq!(
+            record.txids(),
+            vec![second.txid().to_string(), first.txid().to_string()]
+        );
+
+        // only the last replaced transactions are kept.
+        for value in 0..20 {
+            record.replace(&sweep_tx(value), 1000 + value as u32);
+        }
+        assert_eq!(record.replaced.len(), super::MAX_REPLACED);
+        assert_eq!(record.txid, Some(sweep_tx(19).txid().to_string()));
+    }
+}
@@ -1,18 +1,21 @@
 //! JSON RPC 2.0 implementation
 pub mod channels;
+pub mod events;
+pub mod gossip;
 pub mod inventory;
 pub mod offchain;
 pub mod onchain;
 pub mod open_channel;
 pub mod peer_control;
 
-use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::json;
 use lampo_jsonrpc::command::Context;
+use lampo_jsonrpc::errors::{Error, METHOD_NOT_FOUND};
 use lampo_jsonrpc::json_rpc2;
 use lampo_jsonrpc::Handler;
 
@@ -29,9 +32,25 @@ macro_rules! rpc_error {
     }};
 }
 
+/// The longest time a method waits for an event (e.g. the result of a
+/// payment), the RPC methods run on a fixed pool of workers so a
+/// client should not be able to keep one busy forever.
+pub(crate) const MAX_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The filters of the list methods are optional, so the
+/// request can be also empty.
+pub(crate) fn list_request<T: json::DeserializeOwned + Default>(
+    request: &json::Value,
+) -> Result<T, Error> {
+    match request {
+        json::Value::Object(_) => Ok(json::from_value(request.clone())?),
+        _ => Ok(T::default()),
+    }
+}
+
 /// JSON RPC 2.0 Command handler!
 pub struct CommandHandler {
-    pub handler: RefCell<Option<Arc<Handler<LampoDaemon>>>>,
+    pub handler: RwLock<Option<Arc<Handler<LampoDaemon>>>>,
     pub conf: LampoConf,
 }
 
@@ -41,21 +60,23 @@ unsafe impl Sync for CommandHandler {}
 impl CommandHandler {
     pub fn new(lampo_conf: &LampoConf) -> error::Result<Self> {
         let handler = CommandHandler {
-            handler: RefCell::new(None),
+            handler: RwLock::new(None),
             conf: lampo_conf.clone(),
         };
         Ok(handler)
     }
 
     pub fn set_handler(&self, handler: Arc<Handler<LampoDaemon>>) {
-        self.handler.replace(Some(handler));
+        *self.handler.write().unwrap() = Some(handler);
     }
 }
 
 impl ExternalHandler for CommandHandler {
     fn handle(&self, req: &json_rpc2::Request<json::Value>) -> error::Result<Option<json::Value>> {
-        let handler = self.handler.borrow();
-        let Some(handler) = handler.as_ref() else {
+        // Nb. the handler is cloned, so the lock is not held
+        // while the callback is running.
+        let handler = self.handler.read().unwrap().clone();
+        let Some(handler) = handler else {
             log::info!("skipping the handling because it is not defined");
             return Ok(None);
         };
@@ -65,10 +86,13 @@ impl ExternalHandler for CommandHandler {
             log::info!("callback `{}` not found, skipping handler", req.method);
             return Ok(None);
         };
-        // FIXME: we should manage the handler when we try to handle
-        // a method that it is not supported by this handler
-        //
-        // Like we should look at the error code, and return None.
+        // The method is not supported by this handler, so we
+        // give the possibility to the next handler to handle it.
+        if let Err(Error::Rpc(ref err)) = resp {
+            if err.code == METHOD_NOT_FOUND {
+                return Ok(None);
+            }
+        }
         Ok(Some(resp?))
     }
 }
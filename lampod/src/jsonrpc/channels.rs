@@ -1,3 +1,6 @@
+use std::time::{Duration, Instant};
+
+use lampo_common::chan;
 use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::Event;
 use lampo_common::handler::Handler;
@@ -9,17 +12,51 @@ use lampo_jsonrpc::errors::RpcError;
 
 use crate::ln::events::ChannelEvents;
 
+use crate::jsonrpc::MAX_WAIT_TIMEOUT;
+use crate::rpc_error;
 use crate::LampoDaemon;
 
+/// How long `close` waits for the channel to be closed, after
+/// the unilateral timeout when it is specified.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub fn json_list_channels(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `list_channels` with request {:?}", request);
     let resp = ctx.channel_manager().list_channel();
     Ok(json::to_value(resp)?)
 }
 
+pub fn json_set_channel_fee(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `setchannelfee` with request {:?}", request);
+    let request: request::SetChannelFee = json::from_value(request.clone())?;
+    let channels = ctx
+        .channel_manager()
+        .set_channel_fee(&request)
+        .map_err(|err| {
+            Error::Rpc(RpcError {
+                code: -1,
+                message: format!("{err}"),
+                data: None,
+            })
+        })?;
+    Ok(json::to_value(channels)?)
+}
+
 pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `closechannel` with request {:?}", request);
     let mut request: request::CloseChannel = json::from_value(request.clone())?;
+    if request
+        .unilateral_timeout
+        .is_some_and(|timeout| timeout > MAX_WAIT_TIMEOUT.as_secs())
+    {
+        return Err(rpc_error!(
+            "`unilateral_timeout` can not be bigger than {} seconds",
+            MAX_WAIT_TIMEOUT.as_secs()
+        ));
+    }
     let events = ctx.handler().events();
     // This gives all the channels with associated peer
     let channels: response::Channels = ctx
@@ -63,7 +100,14 @@ pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<js
             data: None,
         }));
     };
-    match ctx.channel_manager().close_channel(res) {
+    // When the cooperative close does not complete before the
+    // unilateral deadline, we force close the channel, and we give
+    // up waiting for the close at the `deadline`.
+    let mut unilateral_deadline = res
+        .unilateral_timeout
+        .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let deadline = unilateral_deadline.unwrap_or_else(Instant::now) + CLOSE_TIMEOUT;
+    match ctx.channel_manager().close_channel(res.clone()) {
         Err(err) => {
             return Err(Error::Rpc(RpcError {
                 code: -1,
@@ -73,16 +117,29 @@ pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<js
         }
         Ok(_) => {}
     };
+    let closing_channel_id = res.channel_id.clone().unwrap_or_default();
     let (message, channel_id, node_id, funding_utxo) = loop {
-        let event = events
-            .recv_timeout(std::time::Duration::from_secs(30))
-            .map_err(|err| {
-                Error::Rpc(RpcError {
-                    code: -1,
-                    message: format!("{err}"),
-                    data: None,
-                })
-            })?;
+        let event = match events.recv_deadline(unilateral_deadline.unwrap_or(deadline)) {
+            Ok(event) => event,
+            Err(chan::RecvTimeoutError::Timeout) if unilateral_deadline.is_some() => {
+                log::warn!(
+                    "cooperative close of `{closing_channel_id}` timed out, force closing it"
+                );
+                unilateral_deadline = None;
+                let request = request::CloseChannel {
+                    force: Some(true),
+                    destination: None,
+                    feerate: None,
+                    unilateral_timeout: None,
+                    ..res.clone()
+                };
+                ctx.channel_manager()
+                    .close_channel(request)
+                    .map_err(|err| rpc_error!("{err}"))?;
+                continue;
+            }
+            Err(err) => return Err(rpc_error!("{err}")),
+        };
         if let Event::Lightning(LightningEvent::CloseChannelEvent {
             message,
             channel_id,
@@ -90,6 +147,9 @@ pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<js
             funding_utxo,
         }) = event
         {
+            if !channel_id.eq_ignore_ascii_case(&closing_channel_id) {
+                continue;
+            }
             break (message, channel_id, counterparty_node_id, funding_utxo);
         }
     };
@@ -0,0 +1,300 @@
+//! Event subscription JSON RPC Interface!
+//!
+//! The `subscribe` method keeps the connection open and streams
+//! the events of the node as `event` notifications, so the clients
+//! do not need to poll the node to know when something changes.
+use std::time::Duration;
+
+use lampo_common::chan;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_jsonrpc::errors::{Error, RpcError};
+use lampo_jsonrpc::Subscriber;
+
+use crate::rpc_error;
+use crate::LampoDaemon;
+
+/// Categories of events that a client can subscribe to.
+pub const CATEGORIES: [&str; 4] = ["payments", "channels", "chain", "peers"];
+
+/// How often the subscription checks if the client is gone
+/// while there are no events.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn json_subscribe(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+    subscriber: Subscriber,
+) -> Result<json::Value, Error> {
+    log::info!("call for `subscribe` with request `{:?}`", request);
+    let categories = match request.get("categories") {
+        None | Some(json::Value::Null) => CATEGORIES.to_vec(),
+        Some(json::Value::Array(categories)) => {
+            let mut filter = Vec::new();
+            for category in categories {
+                let Some(category) = CATEGORIES
+                    .iter()
+                    .find(|name| category.as_str() == Some(**name))
+                else {
+                    return Err(rpc_error!(
+                        "unknown category `{category}`, expected one of {:?}",
+                        CATEGORIES
+                    ));
+                };
+                filter.push(*category);
+            }
+            filter
+        }
+        Some(_) => return Err(rpc_error!("`categories` must be a list of strings")),
+    };
+    let events = ctx.handler().events();
+    let filter = categories.clone();
+    std::thread::Builder::new()
+        .name("lampo-subscription".to_owned())
+        .spawn(move || forward_events(events, filter, subscriber))
+        .map_err(|err| rpc_error!("{err}"))?;
+    Ok(json::json!({
+        "categories": categories,
+    }))
+}
+
+/// Forward the events to the client until it closes the connection.
+fn forward_events(
+    events: chan::Receiver<Event>,
+    categories: Vec<&'static str>,
+    subscriber: Subscriber,
+) {
+    loop {
+        let event = match events.recv_timeout(IDLE_TIMEOUT) {
+            Ok(event) => event,
+            Err(chan::RecvTimeoutError::Timeout) if !subscriber.is_closed() => continue,
+            Err(_) => break,
+        };
+        let Some((category, event)) = event_to_json(&event) else {
+            continue;
+        };
+        if !categories.contains(&category) {
+            continue;
+        }
+        let params = json::json!({
+            "category": category,
+            "event": event,
+        });
+        if !subscriber.notify("event", params) {
+            break;
+        }
+    }
+    // Nb. dropping the receiver removes the subscription
+    // from the emitter at the next event.
+    log::debug!("subscription closed");
+}
+
+/// Map the event to its category and its JSON representation,
+/// `None` if the event is not exposed to the clients.
+pub fn event_to_json(event: &Event) -> Option<(&'static str, json::Value)> {
+    let event = match event {
+        Event::Lightning(event) => match event {
+            LightningEvent::PeerConnect {
+                counterparty_node_id,
+            } => (
+                "peers",
+                json::json!({
+                    "type": "peer_connect",
+                    "peer_id": counterparty_node_id.to_string(),
+                }),
+            ),
+            LightningEvent::PeerDisconnect {
+                counterparty_node_id,
+            } => (
+                "peers",
+                json::json!({
+                    "type": "peer_disconnect",
+                    "peer_id": counterparty_node_id.to_string(),
+                }),
+            ),
+            LightningEvent::ChannelPending {
+                counterparty_node_id,
+                funding_transaction,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "channel_pending",
+                    "peer_id": counterparty_node_id.to_string(),
+                    "funding_utxo": funding_transaction.to_string(),
+                }),
+            ),
+            LightningEvent::ChannelReady {
+                counterparty_node_id,
+                channel_id,
+                channel_type,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "channel_ready",
+                    "peer_id": counterparty_node_id.to_string(),
+                    "channel_id": channel_id.to_string(),
+                    "channel_type": channel_type.to_string(),
+                }),
+            ),
+            LightningEvent::ChannelRejected {
+                counterparty_node_id,
+                temporary_channel_id,
+                channel_value_satoshis,
+                reason,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "channel_rejected",
+                    "peer_id": counterparty_node_id.to_string(),
+                    "temporary_channel_id": temporary_channel_id.to_string(),
+                    "amount_sat": channel_value_satoshis,
+                    "reason": reason,
+                }),
+            ),
+            LightningEvent::FundingChannelStart {
+                counterparty_node_id,
+                temporary_channel_id,
+                channel_value_satoshis,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "funding_start",
+                    "peer_id": counterparty_node_id.to_string(),
+                    "temporary_channel_id": temporary_channel_id.to_string(),
+                    "amount_sat": channel_value_satoshis,
+                }),
+            ),
+            LightningEvent::FundingChannelEnd {
+                counterparty_node_id,
+                temporary_channel_id,
+                channel_value_satoshis,
+                funding_transaction,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "funding_end",
+                    "peer_id": counterparty_node_id.to_string(),
+                    "temporary_channel_id": temporary_channel_id.to_string(),
+                    "amount_sat": channel_value_satoshis,
+                    "funding_txid": funding_transaction.txid().to_string(),
+                }),
+            ),
+            LightningEvent::ChannelEvent { state, message } => (
+                "channels",
+                json::json!({
+                    "type": "channel_state",
+                    "state": format!("{:?}", state),
+                    "message": message,
+                }),
+            ),
+            LightningEvent::CloseChannelEvent {
+                channel_id,
+                message,
+                counterparty_node_id,
+                funding_utxo,
+            } => (
+                "channels",
+                json::json!({
+                    "type": "channel_closed",
+                    "channel_id": channel_id,
+                    "message": message,
+                    "peer_id": counterparty_node_id,
+                    "funding_utxo": funding_utxo,
+                }),
+            ),
+            LightningEvent::PaymentEvent {
+                payment_id,
+                state,
+                payment_hash,
+                path,
+                payment_preimage,
+                fee_msat,
+                failure,
+            } => (
+                "payments",
+                json::json!({
+                    "type": "payment",
+                    "payment_id": payment_id,
+                    "state": state,
+                    "payment_hash": payment_hash,
+                    "path": path,
+                    "payment_preimage": payment_preimage,
+                    "fee_msat": fee_msat,
+                    "failure": failure,
+                }),
+            ),
+            LightningEvent::PaymentPathSuccessful {
+                payment_id,
+                payment_hash,
+                path,
+            } => (
+                "payments",
+                json::json!({
+                    "type": "payment_path_successful",
+                    "payment_id": payment_id,
+                    "payment_hash": payment_hash,
+                    "path": path,
+                }),
+            ),
+        },
+        Event::OnChain(event) => match event {
+            OnChainEvent::NewBlock(block) => (
+                "chain",
+                json::json!({
+                    "type": "new_block",
+                    "block_hash": block.block_hash().to_string(),
+                }),
+            ),
+            OnChainEvent::NewBestBlock((header, height)) => (
+                "chain",
+                json::json!({
+                    "type": "new_best_block",
+                    "block_hash": header.block_hash().to_string(),
+                    "height": height.to_consensus_u32(),
+                }),
+            ),
+            OnChainEvent::FeeEstimation(fee) => (
+                "chain",
+                json::json!({
+                    "type": "fee_estimation",
+                    "fee": fee,
+                }),
+            ),
+            OnChainEvent::SendRawTransaction(tx) => (
+                "chain",
+                json::json!({
+                    "type": "transaction_sent",
+                    "txid": tx.txid().to_string(),
+                }),
+            ),
+            OnChainEvent::ConfirmedTransaction((tx, _, header, height)) => (
+                "chain",
+                json::json!({
+                    "type": "transaction_confirmed",
+                    "txid": tx.txid().to_string(),
+                    "block_hash": header.block_hash().to_string(),
+                    "height": height.to_consensus_u32(),
+                }),
+            ),
+            OnChainEvent::DiscardedTransaction(txid) => (
+                "chain",
+                json::json!({
+                    "type": "transaction_discarded",
+                    "txid": txid.to_string(),
+                }),
+            ),
+            OnChainEvent::UnconfirmedTransaction(txid) => (
+                "chain",
+                json::json!({
+                    "type": "transaction_unconfirmed",
+                    "txid": txid.to_string(),
+                }),
+            ),
+        },
+        Event::Inventory => return None,
+    };
+    Some(event)
+}
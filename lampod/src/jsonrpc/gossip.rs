@@ -0,0 +1,32 @@
+//! Network graph JSON RPC Interface!
+use lampo_common::json;
+use lampo_common::model::request::{ListGossipChannels, ListNodes};
+use lampo_jsonrpc::errors::Error;
+
+use crate::jsonrpc::list_request;
+use crate::LampoDaemon;
+
+pub fn json_list_nodes(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listnodes` with request `{:?}`", request);
+    let request: ListNodes = list_request(request)?;
+    let nodes = ctx.channel_manager().list_nodes(request.node_id);
+    Ok(json::to_value(nodes)?)
+}
+
+pub fn json_list_gossip_channels(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `listgossipchannels` with request `{:?}`", request);
+    let request: ListGossipChannels = list_request(request)?;
+    let channels = ctx
+        .channel_manager()
+        .list_gossip_channels(request.short_channel_id, request.node_id);
+    Ok(json::to_value(channels)?)
+}
+
+pub fn json_graph_stats(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `graphstats` with request `{:?}`", request);
+    let stats = ctx.channel_manager().graph_stats();
+    Ok(json::to_value(stats)?)
+}
@@ -1,25 +1,43 @@
 //! Offchain RPC methods
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use lampo_common::chan;
 use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::Event;
 use lampo_common::handler::Handler;
 use lampo_common::ldk;
+use lampo_common::ldk::ln::channelmanager::PaymentId;
 use lampo_common::ldk::offers::offer;
 use lampo_common::model::request::GenerateInvoice;
 use lampo_common::model::request::GenerateOffer;
+use lampo_common::model::request::GetRoute;
 use lampo_common::model::request::KeySend;
 use lampo_common::model::request::Pay;
+use lampo_common::model::request::{ListForwards, ListInvoices, ListPayments, ListSendPays};
+use lampo_common::model::request::{PayStatus, WaitSendPay};
 use lampo_common::model::response;
 use lampo_common::model::response::PayResult;
+use lampo_common::model::response::{ChannelForwardFees, ForwardStatus, Forwards};
 use lampo_common::model::response::{Invoice, InvoiceInfo};
+use lampo_common::model::response::{Invoices, Payments, SendPays};
+use lampo_common::model::response::{PaymentDetails, PaymentStatus};
 use lampo_common::{json, model::request::DecodeInvoice};
 use lampo_jsonrpc::errors::{Error, RpcError};
 
+use crate::jsonrpc::list_request;
+use crate::jsonrpc::MAX_WAIT_TIMEOUT;
 use crate::rpc_error;
 use crate::LampoDaemon;
 
+/// The payment failed, the `data` of the error contains
+/// the payment with the reason of the failure.
+pub const PAY_FAILED: i32 = 210;
+/// The payment is still pending when the `waitsendpay` timeout expires.
+pub const WAIT_TIMEOUT: i32 = 200;
+/// How long `pay` waits for the payment when no `timeout` is specified.
+const PAY_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub fn json_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `invoice` with request `{:?}`", request);
     let request: GenerateInvoice = json::from_value(request.clone())?;
@@ -29,6 +47,7 @@ pub fn json_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Va
             request.amount_msat,
             &request.description,
             request.expiring_in.unwrap_or(10000),
+            request.label,
         )
         .map_err(|err| {
             Error::Rpc(RpcError {
@@ -95,41 +114,146 @@ pub fn json_decode_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<j
 pub fn json_pay(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `pay` with request `{:?}`", request);
     let request: Pay = json::from_value(request.clone())?;
+    // Nb. subscribe before sending, so we do not lose the result.
     let events = ctx.handler().events();
-    if let Ok(_) = offer::Offer::from_str(&request.invoice_str) {
+    let payment_id = if let Ok(_) = offer::Offer::from_str(&request.invoice_str) {
         ctx.offchain_manager()
-            .pay_offer(&request.invoice_str, request.amount)
-            .map_err(|err| rpc_error!("{err}"))?;
+            .pay_offer(&request.invoice_str, request.amount, &request.options)
+            .map_err(|err| rpc_error!("{err}"))?
     } else {
         ctx.offchain_manager()
-            .pay_invoice(&request.invoice_str, request.amount)
-            .map_err(|err| rpc_error!("{err}"))?;
+            .pay_invoice(&request.invoice_str, request.amount, &request.options)
+            .map_err(|err| rpc_error!("{err}"))?
+    };
+    if request.async_pay.unwrap_or(false) {
+        // the result can be tracked with `paystatus` or `waitsendpay`.
+        let details = payment_details(ctx, &payment_id)?;
+        return Ok(json::to_value(details)?);
+    }
+    let timeout = request
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(PAY_TIMEOUT)
+        .min(MAX_WAIT_TIMEOUT);
+    let deadline = Instant::now() + timeout;
+    let Some(result) = wait_payment_event(&events, &hex::encode(payment_id.0), deadline)? else {
+        // the payment is still in flight, the caller can follow up with `waitsendpay`.
+        let details = payment_details(ctx, &payment_id)?;
+        return Ok(json::to_value(details)?);
+    };
+    if let Some(failure) = result.failure.as_ref() {
+        return Err(Error::Rpc(RpcError {
+            code: PAY_FAILED,
+            message: format!("payment failed: {}", failure.reason),
+            data: Some(json::to_value(&result)?),
+        }));
+    }
+    Ok(json::to_value(result)?)
+}
+
+pub fn json_pay_status(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `paystatus` with request `{:?}`", request);
+    let request: PayStatus = json::from_value(request.clone())?;
+    let payment_id = parse_payment_id(&request.payment_id)?;
+    let details = payment_details(ctx, &payment_id)?;
+    Ok(json::to_value(details)?)
+}
+
+pub fn json_wait_send_pay(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `waitsendpay` with request `{:?}`", request);
+    let request: WaitSendPay = json::from_value(request.clone())?;
+    let payment_id = parse_payment_id(&request.payment_id)?;
+    // Nb. subscribe before looking at the store, so the result
+    // cannot be lost in between.
+    let events = ctx.handler().events();
+    let mut details = payment_details(ctx, &payment_id)?;
+    if details.payment.status == PaymentStatus::Pending {
+        let timeout = request
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(MAX_WAIT_TIMEOUT)
+            .min(MAX_WAIT_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        if wait_payment_event(&events, &hex::encode(payment_id.0), deadline)?.is_none() {
+            return Err(Error::Rpc(RpcError {
+                code: WAIT_TIMEOUT,
+                message: "timed out while waiting for the payment".to_owned(),
+                data: Some(json::to_value(&details)?),
+            }));
+        }
+        details = payment_details(ctx, &payment_id)?;
     }
-    // FIXME: this will loop when the Payment event is not generated
+    if details.payment.status == PaymentStatus::Failed {
+        return Err(Error::Rpc(RpcError {
+            code: PAY_FAILED,
+            message: format!(
+                "payment failed: {}",
+                details
+                    .payment
+                    .failure_reason
+                    .as_deref()
+                    .unwrap_or("unknown")
+            ),
+            data: Some(json::to_value(&details)?),
+        }));
+    }
+    Ok(json::to_value(details)?)
+}
+
+fn parse_payment_id(payment_id: &str) -> Result<PaymentId, Error> {
+    let bytes = hex::decode(payment_id).map_err(|err| rpc_error!("invalid `payment_id`: {err}"))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| rpc_error!("invalid `payment_id`: expected 32 bytes"))?;
+    Ok(PaymentId(bytes))
+}
+
+fn payment_details(ctx: &LampoDaemon, payment_id: &PaymentId) -> Result<PaymentDetails, Error> {
+    ctx.offchain_manager()
+        .payment_details(payment_id)
+        .map_err(|err| rpc_error!("{err}"))?
+        .ok_or_else(|| rpc_error!("payment `{}` not found", hex::encode(payment_id.0)))
+}
+
+/// Wait for the result of the payment, `None` is returned
+/// when the deadline is reached.
+///
+/// Nb. only the `PaymentEvent` terminates the payment, so a payment
+/// split in more parts is not resolved by the first path delivered.
+fn wait_payment_event(
+    events: &chan::Receiver<Event>,
+    payment_id: &str,
+    deadline: Instant,
+) -> Result<Option<PayResult>, Error> {
     loop {
-        let event = events
-            .recv_timeout(Duration::from_secs(30))
-            // FIXME: this should be avoided, the `?` should be used here
-            .map_err(|err| {
-                Error::Rpc(RpcError {
-                    code: -1,
-                    message: format!("{err}"),
-                    data: None,
-                })
-            })?;
-
-        if let Event::Lightning(LightningEvent::PaymentEvent {
+        let event = match events.recv_deadline(deadline) {
+            Ok(event) => event,
+            Err(chan::RecvTimeoutError::Timeout) => return Ok(None),
+            Err(err) => return Err(rpc_error!("node stopped while paying: {err}")),
+        };
+        let Event::Lightning(LightningEvent::PaymentEvent {
+            payment_id: id,
             payment_hash,
             path,
             state,
+            payment_preimage,
+            fee_msat,
+            failure,
         }) = event
-        {
-            return Ok(json::to_value(PayResult {
-                state,
-                path,
-                payment_hash,
-            })?);
+        else {
+            continue;
+        };
+        if id != payment_id {
+            continue;
         }
+        return Ok(Some(PayResult {
+            state,
+            path,
+            payment_hash,
+            payment_preimage,
+            fee_msat,
+            failure,
+        }));
     }
 }
 
@@ -137,7 +261,7 @@ pub fn json_keysend(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Va
     log::debug!("call for `keysend` with request `{:?}`", request);
     let request: KeySend = json::from_value(request.clone())?;
     ctx.offchain_manager()
-        .keysend(request.destination, request.amount_msat)
+        .keysend(request.destination, request.amount_msat, &request.options)
         .map_err(|err| {
             Error::Rpc(RpcError {
                 code: -1,
@@ -147,3 +271,112 @@ pub fn json_keysend(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Va
         })?;
     Ok(json::json!({}))
 }
+
+pub fn json_get_route(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `getroute` with request `{:?}`", request);
+    let request: GetRoute = json::from_value(request.clone())?;
+    let route = ctx
+        .offchain_manager()
+        .get_route(&request)
+        .map_err(|err| rpc_error!("{err}"))?;
+    Ok(json::to_value(route)?)
+}
+
+pub fn json_list_invoices(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listinvoices` with request `{:?}`", request);
+    let request: ListInvoices = list_request(request)?;
+    let invoices = ctx
+        .offchain_manager()
+        .payments()
+        .list_invoices()
+        .map_err(|err| rpc_error!("{err}"))?
+        .into_iter()
+        .filter(|invoice| request.label.is_none() || invoice.label == request.label)
+        .filter(|invoice| {
+            request.payment_hash.is_none()
+                || Some(&invoice.payment_hash) == request.payment_hash.as_ref()
+        })
+        .collect();
+    Ok(json::to_value(Invoices { invoices })?)
+}
+
+pub fn json_list_payments(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listpayments` with request `{:?}`", request);
+    let request: ListPayments = list_request(request)?;
+    let payments = ctx
+        .offchain_manager()
+        .payments()
+        .list_payments()
+        .map_err(|err| rpc_error!("{err}"))?
+        .into_iter()
+        .filter(|payment| {
+            request.payment_hash.is_none() || payment.payment_hash == request.payment_hash
+        })
+        .collect();
+    Ok(json::to_value(Payments { payments })?)
+}
+
+pub fn json_list_sendpays(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listsendpays` with request `{:?}`", request);
+    let request: ListSendPays = list_request(request)?;
+    let sendpays = ctx
+        .offchain_manager()
+        .payments()
+        .list_sendpays()
+        .map_err(|err| rpc_error!("{err}"))?
+        .into_iter()
+        .filter(|sendpay| {
+            request.payment_hash.is_none() || sendpay.payment_hash == request.payment_hash
+        })
+        .collect();
+    Ok(json::to_value(SendPays { sendpays })?)
+}
+
+pub fn json_list_forwards(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listforwards` with request `{:?}`", request);
+    let request: ListForwards = list_request(request)?;
+    let forwards = ctx
+        .offchain_manager()
+        .payments()
+        .list_forwards()
+        .map_err(|err| rpc_error!("{err}"))?
+        .into_iter()
+        .filter(|forward| {
+            request
+                .status
+                .map_or(true, |status| forward.status == status)
+        })
+        .filter(|forward| {
+            request
+                .from
+                .map_or(true, |from| forward.resolved_at >= from)
+        })
+        .filter(|forward| request.to.map_or(true, |to| forward.resolved_at <= to))
+        .collect::<Vec<_>>();
+    let mut fees: Vec<ChannelForwardFees> = Vec::new();
+    for forward in forwards
+        .iter()
+        .filter(|forward| forward.status == ForwardStatus::Settled)
+    {
+        let index = match fees
+            .iter()
+            .position(|fees| fees.channel_id == forward.out_channel_id)
+        {
+            Some(index) => index,
+            None => {
+                fees.push(ChannelForwardFees {
+                    channel_id: forward.out_channel_id.clone(),
+                    forwards: 0,
+                    out_msat: 0,
+                    fee_msat: 0,
+                });
+                fees.len() - 1
+            }
+        };
+        let channel = &mut fees[index];
+        channel.forwards += 1;
+        channel.out_msat += forward.out_msat.unwrap_or_default();
+        channel.fee_msat += forward.fee_msat.unwrap_or_default();
+    }
+    Ok(json::to_value(Forwards { forwards, fees })?)
+}
@@ -1,7 +1,14 @@
 //! On Chain RPC methods
+use std::str::FromStr;
+
+use lampo_common::bitcoin::consensus::encode::serialize_hex;
+use lampo_common::bitcoin::{Address, OutPoint};
 use lampo_common::json;
+use lampo_common::ldk::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
+use lampo_common::model::{request, response};
 use lampo_jsonrpc::errors::{Error, RpcError};
 
+use crate::rpc_error;
 use crate::LampoDaemon;
 
 pub fn json_new_addr(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
@@ -43,3 +50,79 @@ pub fn json_estimate_fees(ctx: &LampoDaemon, request: &json::Value) -> Result<js
         })),
     }
 }
+
+pub fn json_withdraw(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `withdraw` with request `{:?}`", request);
+    let request: request::Withdraw = json::from_value(request.clone())?;
+    let script = Address::from_str(&request.destination)
+        .map_err(|err| rpc_error!("invalid destination `{}`: {err}", request.destination))?
+        .require_network(ctx.channel_manager().conf.network)
+        .map_err(|err| rpc_error!("invalid destination `{}`: {err}", request.destination))?
+        .script_pubkey();
+    let amount = request.amount.sats().map_err(|err| rpc_error!("{err}"))?;
+    let inputs = request
+        .utxos
+        .unwrap_or_default()
+        .iter()
+        .map(|utxo| {
+            OutPoint::from_str(utxo).map_err(|err| rpc_error!("invalid utxo `{utxo}`: {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let reserved = ctx.channel_manager().reserved_utxos();
+    if let Some(utxo) = inputs.iter().find(|utxo| reserved.contains(utxo)) {
+        return Err(rpc_error!("utxo `{utxo}` is reserved to fund a channel"));
+    }
+    // keep the funds needed to bump the fee of the anchor channels.
+    let spent = match (amount, inputs.is_empty()) {
+        (None, false) => {
+            let utxos = ctx
+                .wallet_manager()
+                .list_confirmed_utxos()
+                .map_err(|err| rpc_error!("{err}"))?;
+            Some(
+                utxos
+                    .iter()
+                    .filter(|utxo| inputs.contains(&utxo.outpoint))
+                    .map(|utxo| utxo.output.value)
+                    .sum(),
+            )
+        }
+        (amount, _) => amount,
+    };
+    ctx.channel_manager()
+        .check_withdraw_reserve(spent)
+        .map_err(|err| rpc_error!("{err}"))?;
+    let fee_rate = match request.feerate {
+        Some(fee_rate) => fee_rate,
+        // the backend estimates the feerate in sats per 1000 vbytes,
+        // that are 4000 weight.
+        None => (ctx
+            .onchain_manager()
+            .backend
+            .fee_rate_estimation(request.conf_target.unwrap_or(6))
+            .map_err(|err| rpc_error!("{err}"))?
+            / 4)
+        .max(FEERATE_FLOOR_SATS_PER_KW),
+    };
+    let tx = ctx
+        .wallet_manager()
+        .withdraw(
+            script,
+            amount,
+            fee_rate,
+            &inputs,
+            &reserved,
+            request.rbf.unwrap_or(true),
+        )
+        .map_err(|err| rpc_error!("{err}"))?;
+    log::info!("broadcast the withdraw transaction `{}`", tx.txid());
+    ctx.onchain_manager()
+        .backend
+        .send_raw_transaction(&tx)
+        .map_err(|err| rpc_error!("impossible broadcast the transaction: {err}"))?;
+    let resp = response::Withdraw {
+        txid: tx.txid().to_string(),
+        tx: serialize_hex(&tx),
+    };
+    Ok(json::to_value(resp)?)
+}
@@ -1,16 +1,28 @@
 //! Open Channel RPC Method implementation
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use lampo_common::bitcoin::consensus::deserialize;
+use lampo_common::bitcoin::psbt::PartiallySignedTransaction;
+use lampo_common::bitcoin::{Address, Transaction};
+use lampo_common::chan;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
 use lampo_common::json;
-use lampo_common::model::request;
+use lampo_common::model::{request, response};
 use lampo_jsonrpc::errors::{Error, RpcError};
 
 use crate::ln::events::ChannelEvents;
+use crate::rpc_error;
 use crate::LampoDaemon;
 
-pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
-    log::info!("call for `openchannel` with request {:?}", request);
-    let request: request::OpenChannel = json::from_value(request.clone())?;
+/// How long `fundchannel_start` waits that the peer accepts the channel.
+const FUNDCHANNEL_START_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// Connect with the peer of the channel, if we are not
+/// already connected.
+fn connect_with_peer(ctx: &LampoDaemon, request: &request::OpenChannel) -> Result<(), Error> {
     // LDK's `create_channel()` doesn't check if you are currently connected
     // to the given peer so we need to check ourselves
     // FIXME: remove unwrap!
@@ -36,6 +48,13 @@ pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<jso
             })
         })?;
     }
+    Ok(())
+}
+
+pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `openchannel` with request {:?}", request);
+    let request: request::OpenChannel = json::from_value(request.clone())?;
+    connect_with_peer(ctx, &request)?;
 
     // FIXME: there are use case there need to be covered, like
     // - When there is an error how we return back to the user?
@@ -52,3 +71,140 @@ pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<jso
     };
     Ok(json::to_value(resp?)?)
 }
+
+pub fn json_multi_fund_channel(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `multifundchannel` with request {:?}", request);
+    let request: request::MultiFundChannel = json::from_value(request.clone())?;
+    // Nb. connect with all the peers before opening any
+    // channel, so a wrong peer does not start the batch.
+    for destination in request.destinations.iter() {
+        connect_with_peer(ctx, destination)?;
+    }
+    let resp = ctx.channel_manager().multi_open_channel(request);
+    let resp = match resp {
+        Ok(resp) => Ok(resp),
+        Err(err) => Err(Error::Rpc(RpcError {
+            code: -1,
+            message: format!("{err}"),
+            data: None,
+        })),
+    };
+    Ok(json::to_value(resp?)?)
+}
+
+/// Open a channel funded by a transaction built outside of the node,
+/// the transaction is given later with `fundchannel_complete`.
+///
+/// Nb. LDK drops an outbound channel that is not funded after
+/// about one hour, so `fundchannel_complete` has to be called before.
+pub fn json_fund_channel_start(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `fundchannel_start` with request {:?}", request);
+    let request: request::OpenChannel = json::from_value(request.clone())?;
+    connect_with_peer(ctx, &request)?;
+    let node_id = request.node_id().map_err(|err| rpc_error!("{err}"))?;
+    let events = ctx.handler().events();
+    let (user_channel_id, temporary_channel_id) = ctx
+        .channel_manager()
+        .start_external_funding(&request)
+        .map_err(|err| rpc_error!("{err}"))?;
+
+    // wait that the peer accepts the channel, so we know the funding output.
+    let deadline = Instant::now() + FUNDCHANNEL_START_TIMEOUT;
+    loop {
+        let event = match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => event,
+            Err(chan::RecvTimeoutError::Timeout) => {
+                log::warn!(
+                    "peer `{node_id}` did not accept the channel `{temporary_channel_id}` in time"
+                );
+                // the channel will be removed by the `ChannelClosed` event.
+                let _ = ctx
+                    .channel_manager()
+                    .manager()
+                    .force_close_without_broadcasting_txn(&temporary_channel_id, &node_id);
+                return Err(rpc_error!(
+                    "peer `{node_id}` did not accept the channel in time"
+                ));
+            }
+            Err(err) => return Err(rpc_error!("{err}")),
+        };
+        match event {
+            Event::Lightning(LightningEvent::FundingChannelStart {
+                temporary_channel_id: channel_id,
+                ..
+            }) if channel_id == temporary_channel_id => break,
+            Event::Lightning(LightningEvent::CloseChannelEvent {
+                channel_id,
+                message,
+                ..
+            }) if channel_id == temporary_channel_id.to_string() => {
+                return Err(rpc_error!("channel `{channel_id}` closed: {message}"));
+            }
+            _ => continue,
+        }
+    }
+    let Some(funding) = ctx.channel_manager().external_funding(user_channel_id) else {
+        return Err(rpc_error!(
+            "channel `{temporary_channel_id}` is not waiting the funding transaction"
+        ));
+    };
+    let funding_address =
+        Address::from_script(&funding.output_script, ctx.channel_manager().conf.network)
+            .map_err(|err| rpc_error!("{err}"))?;
+    let resp = response::FundChannelStart {
+        node_id: node_id.to_string(),
+        temporary_channel_id: temporary_channel_id.to_string(),
+        user_channel_id,
+        funding_address: funding_address.to_string(),
+        scriptpubkey: hex::encode(funding.output_script.as_bytes()),
+        amount: funding.amount,
+    };
+    Ok(json::to_value(resp)?)
+}
+
+pub fn json_fund_channel_complete(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `fundchannel_complete` with request {:?}", request);
+    let request: request::FundChannelComplete = json::from_value(request.clone())?;
+    let transaction: Transaction = match (request.psbt, request.tx) {
+        (Some(psbt), None) => {
+            let psbt = PartiallySignedTransaction::from_str(&psbt)
+                .map_err(|err| rpc_error!("invalid psbt: {err}"))?;
+            // Nb. the signatures are moved inside the transaction only
+            // when the psbt is finalized, e.g. with `finalizepsbt`.
+            if let Some(index) = psbt
+                .inputs
+                .iter()
+                .position(|input| input.final_script_witness.is_none())
+            {
+                return Err(rpc_error!(
+                    "the input {index} of the psbt is not finalized, the `final_script_witness` is missing"
+                ));
+            }
+            psbt.extract_tx()
+        }
+        (None, Some(tx)) => {
+            let buff = hex::decode(tx).map_err(|err| rpc_error!("invalid transaction: {err}"))?;
+            deserialize(&buff).map_err(|err| rpc_error!("invalid transaction: {err}"))?
+        }
+        _ => return Err(rpc_error!("one of `psbt` or `tx` must be specified")),
+    };
+    let txid = transaction.txid();
+    let channel_id = ctx
+        .channel_manager()
+        .complete_external_funding(&request.temporary_channel_id, transaction)
+        .map_err(|err| rpc_error!("{err}"))?;
+    let resp = response::FundChannelComplete {
+        channel_id: channel_id.to_string(),
+        txid: txid.to_string(),
+    };
+    Ok(json::to_value(resp)?)
+}
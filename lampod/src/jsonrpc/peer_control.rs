@@ -1,9 +1,13 @@
 //! Peer Control JSON RPC Interface!
 use lampo_common::json;
+use lampo_common::model::request::{Disconnect, ListPeers};
+use lampo_common::model::response;
 use lampo_common::model::Connect;
 use lampo_jsonrpc::errors::Error;
 use lampo_jsonrpc::errors::RpcError;
 
+use crate::jsonrpc::list_request;
+use crate::rpc_error;
 use crate::{ln::events::PeerEvents, LampoDaemon};
 
 pub fn json_connect(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
@@ -43,3 +47,38 @@ pub fn json_connect(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Va
     };
     result
 }
+
+pub fn json_list_peers(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listpeers` with request `{:?}`", request);
+    let request: ListPeers = list_request(request)?;
+    let peers = ctx
+        .peer_manager()
+        .list_peers(request.peer_id)
+        .map_err(|err| rpc_error!("{err}"))?;
+    Ok(json::to_value(peers)?)
+}
+
+pub fn json_disconnect(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `disconnect` with request `{:?}`", request);
+    let request: Disconnect = json::from_value(request.clone())?;
+    let node_id = request.node_id;
+    let channels = ctx
+        .channel_manager()
+        .list_channel()
+        .channels
+        .into_iter()
+        .filter(|channel| channel.peer_id == node_id.to_string())
+        .count();
+    // Nb. the channels are not closed, the peer can reconnect later.
+    if channels > 0 && !request.force.unwrap_or(false) {
+        return Err(rpc_error!(
+            "peer `{node_id}` has {channels} channels open, use `force` to disconnect it"
+        ));
+    }
+    ctx.rt
+        .block_on(ctx.peer_manager().disconnect(node_id))
+        .map_err(|err| rpc_error!("{err}"))?;
+    Ok(json::to_value(response::Disconnect {
+        node_id: node_id.to_string(),
+    })?)
+}
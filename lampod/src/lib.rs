@@ -30,7 +30,9 @@ use lampo_common::backend::Backend;
 use lampo_common::bitcoin::absolute::Height;
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
+use lampo_common::handler::Handler as EventHandler;
 use lampo_common::json;
+use lampo_common::ldk::events::bump_transaction::Wallet;
 use lampo_common::ldk::events::Event;
 use lampo_common::ldk::processor::{BackgroundProcessor, GossipSync};
 use lampo_common::ldk::routing::gossip::P2PGossipSync;
@@ -38,11 +40,12 @@ use lampo_common::wallet::WalletManager;
 
 use crate::actions::handler::LampoHandler;
 use crate::actions::Handler;
-use crate::chain::LampoChainManager;
+use crate::chain::{LampoBumpHandler, LampoChainManager, LampoSweeper, LampoWalletSource};
 use crate::handler::external_handler::ExternalHandler;
+use crate::ln::LampoRapidSync;
 use crate::ln::OffchainManager;
 use crate::ln::{LampoChannelManager, LampoInventoryManager, LampoPeerManager};
-use crate::persistence::LampoPersistence;
+use crate::persistence::{LampoPersistence, PaymentStore, PeerStore};
 use crate::utils::logger::LampoLogger;
 
 /// LampoDaemon is the main data structure that uses the facade
@@ -59,6 +62,8 @@ pub struct LampoDaemon {
     conf: LampoConf,
     peer_manager: Option<Arc<LampoPeerManager>>,
     onchain_manager: Option<Arc<LampoChainManager>>,
+    sweeper: Option<Arc<LampoSweeper>>,
+    bump_handler: Option<Arc<LampoBumpHandler>>,
     channel_manager: Option<Arc<LampoChannelManager>>,
     inventory_manager: Option<Arc<LampoInventoryManager>>,
     wallet_manager: Arc<dyn WalletManager>,
@@ -87,6 +92,8 @@ impl LampoDaemon {
             persister: Arc::new(LampoPersistence::new(root_path.into())),
             peer_manager: None,
             onchain_manager: None,
+            sweeper: None,
+            bump_handler: None,
             channel_manager: None,
             inventory_manager: None,
             wallet_manager,
@@ -116,6 +123,39 @@ impl LampoDaemon {
         self.onchain_manager.clone().unwrap()
     }
 
+    pub fn init_sweeper(&mut self) -> error::Result<()> {
+        log::debug!(target: "lampod", "init sweeper ..");
+        let sweeper = LampoSweeper::new(
+            self.persister.clone(),
+            self.onchain_manager(),
+            self.wallet_manager.clone(),
+        );
+        self.sweeper = Some(Arc::new(sweeper));
+        Ok(())
+    }
+
+    pub fn sweeper(&self) -> Arc<LampoSweeper> {
+        self.sweeper.clone().unwrap()
+    }
+
+    pub fn init_bump_handler(&mut self) -> error::Result<()> {
+        log::debug!(target: "lampod", "init bump transaction handler ..");
+        let source = LampoWalletSource::new(self.wallet_manager.clone());
+        let wallet = Wallet::new(Arc::new(source), self.logger.clone());
+        let handler = LampoBumpHandler::new(
+            self.onchain_manager(),
+            Arc::new(wallet),
+            self.wallet_manager.ldk_keys().keys_manager.clone(),
+            self.logger.clone(),
+        );
+        self.bump_handler = Some(Arc::new(handler));
+        Ok(())
+    }
+
+    pub fn bump_handler(&self) -> Arc<LampoBumpHandler> {
+        self.bump_handler.clone().unwrap()
+    }
+
     pub fn init_channeld(&mut self) -> error::Result<()> {
         log::debug!(target: "lampod", "init channeld ...");
         let mut manager = LampoChannelManager::new(
@@ -160,6 +200,7 @@ impl LampoDaemon {
             self.logger.clone(),
             Arc::new(self.conf.clone()),
             self.onchain_manager(),
+            Arc::new(PaymentStore::new(self.persister.clone())),
         )?;
         self.offchain_manager = Some(Arc::new(manager));
         Ok(())
@@ -167,7 +208,11 @@ impl LampoDaemon {
 
     pub fn init_peer_manager(&mut self) -> error::Result<()> {
         log::debug!(target: "lampo", "init peer manager ...");
-        let mut peer_manager = LampoPeerManager::new(&self.conf, self.logger.clone());
+        let mut peer_manager = LampoPeerManager::new(
+            &self.conf,
+            self.logger.clone(),
+            Arc::new(PeerStore::new(self.persister.clone())),
+        );
         peer_manager.init(
             self.onchain_manager(),
             self.wallet_manager.clone(),
@@ -217,6 +262,8 @@ impl LampoDaemon {
     pub fn init(&mut self, client: Arc<dyn Backend>) -> error::Result<()> {
         log::debug!(target: "lampod", "init lampod ...");
         self.init_onchaind(client.clone())?;
+        self.init_sweeper()?;
+        self.init_bump_handler()?;
         self.init_channeld()?;
         self.init_offchain_manager()?;
         self.init_peer_manager()?;
@@ -257,23 +304,51 @@ impl LampoDaemon {
             }
         };
 
-        let background_processor = BackgroundProcessor::start(
-            self.persister.clone(),
-            event_handler,
-            self.channel_manager().chain_monitor(),
-            self.channel_manager().manager(),
-            GossipSync::p2p(gossip_sync),
-            self.peer_manager().manager(),
-            self.logger.clone(),
-            Some(self.channel_manager().scorer()),
-        );
+        let rapid_sync = self.conf.rgs_source.clone().map(|source| {
+            Arc::new(LampoRapidSync::new(
+                self.channel_manager().graph(),
+                self.logger.clone(),
+                source,
+                self.conf.rgs_refresh_interval,
+            ))
+        });
+        let background_processor = match rapid_sync {
+            Some(rapid_sync) => {
+                log::info!(target: "lampo", "Bootstrap the network graph with rapid gossip sync");
+                let processor = BackgroundProcessor::start(
+                    self.persister.clone(),
+                    event_handler,
+                    self.channel_manager().chain_monitor(),
+                    self.channel_manager().manager(),
+                    GossipSync::rapid(rapid_sync.rapid_sync()),
+                    self.peer_manager().manager(),
+                    self.logger.clone(),
+                    Some(self.channel_manager().scorer()),
+                );
+                let _ = rapid_sync.listen();
+                processor
+            }
+            None => BackgroundProcessor::start(
+                self.persister.clone(),
+                event_handler,
+                self.channel_manager().chain_monitor(),
+                self.channel_manager().manager(),
+                GossipSync::p2p(gossip_sync),
+                self.peer_manager().manager(),
+                self.logger.clone(),
+                Some(self.channel_manager().scorer()),
+            ),
+        };
 
         log::info!(target: "lampo", "Stating onchaind");
         let _ = self.onchain_manager().backend.clone().listen();
         log::info!(target: "lampo", "Starting peer manager");
         let _ = self.peer_manager().run();
+        let _ = self.peer_manager().reconnect();
         log::info!(target: "lampo", "Starting channel manager");
         let _ = self.channel_manager().listen();
+        log::info!(target: "lampo", "Starting sweeper");
+        let _ = self.sweeper().listen(self.handler().events());
         Ok(std::thread::spawn(move || {
             let _ = background_processor.join();
             Ok(())
@@ -1,35 +1,45 @@
 //! Channel Manager Implementation
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, ErrorKind};
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
 use lampo_common::bitcoin::absolute::Height;
-use lampo_common::bitcoin::{BlockHash, Transaction};
-use lampo_common::conf::LampoConf;
+use lampo_common::bitcoin::{Address, BlockHash, OutPoint, ScriptBuf, Transaction};
+use lampo_common::conf::{LampoConf, UserConfig};
 use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::onchain::OnChainEvent;
 use lampo_common::event::Event;
 use lampo_common::handler::Handler;
+use lampo_common::ldk::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
 use lampo_common::ldk::chain::chainmonitor::ChainMonitor;
 use lampo_common::ldk::chain::channelmonitor::ChannelMonitor;
 use lampo_common::ldk::chain::{BestBlock, Confirm, Filter, Watch};
 use lampo_common::ldk::ln::channelmanager::{
-    ChainParameters, ChannelManager, ChannelManagerReadArgs,
+    ChainParameters, ChannelManager, ChannelManagerReadArgs, MIN_CLTV_EXPIRY_DELTA,
 };
+use lampo_common::ldk::ln::script::ShutdownScript;
 use lampo_common::ldk::persister::fs_store::FilesystemStore;
-use lampo_common::ldk::routing::gossip::NetworkGraph;
+use lampo_common::ldk::routing::gossip::{NetworkGraph, NodeId};
 use lampo_common::ldk::routing::scoring::{
     ProbabilisticScorer, ProbabilisticScoringDecayParameters, ProbabilisticScoringFeeParameters,
 };
+use lampo_common::ldk::sign::EntropySource;
 use lampo_common::ldk::sign::InMemorySigner;
 use lampo_common::ldk::sign::KeysManager;
-use lampo_common::ldk::util::persist::read_channel_monitors;
+use lampo_common::ldk::util::persist::{read_channel_monitors, KVStore};
 use lampo_common::ldk::util::ser::ReadableArgs;
 use lampo_common::model::request;
-use lampo_common::model::response::{self, Channel, Channels};
+use lampo_common::model::request::{ChannelTypeFeature, OpenChannelOptions};
+use lampo_common::model::response::{self, Channel, ChannelFeeConfig, Channels};
+use lampo_common::model::response::{GossipChannel, GossipChannels, GossipNode, GraphStats, Nodes};
+use lampo_common::secp256k1::PublicKey;
+use lampo_common::types::{ChannelId, ChannelState};
 
 use crate::actions::handler::LampoHandler;
 use crate::chain::{LampoChainManager, WalletManager};
@@ -71,6 +81,55 @@ pub type LampoRouter = super::route::LampoRouter<
     LampoScorer,
 >;
 
+/// On chain funds (in sats) kept by default for each anchor channel.
+pub const DEFAULT_ANCHOR_RESERVE_SAT: u64 = 25_000;
+
+const NAMESPACE: &str = "channels";
+const CLOSE_TO: &str = "close_to";
+
+/// How the funding transaction of a channel opened by
+/// us should be built.
+#[derive(Debug, Clone, Default)]
+pub struct FundingOptions {
+    pub feerate: Option<u32>,
+    /// The UTXOs to spend, empty when the wallet selects them.
+    pub utxos: Vec<OutPoint>,
+    pub minconf: Option<u32>,
+}
+
+/// The funding output of a channel funded by a
+/// transaction built outside of the node.
+#[derive(Debug, Clone)]
+pub struct ExternalFunding {
+    pub temporary_channel_id: ChannelId,
+    pub node_id: PublicKey,
+    pub output_script: ScriptBuf,
+    pub amount: u64,
+}
+
+/// A channel of a batch, that is funded when the funding
+/// outputs of all the channels of the batch are known.
+struct BatchChannel {
+    user_channel_id: u128,
+    node_id: PublicKey,
+    temporary_channel_id: Option<ChannelId>,
+    output: Option<(ScriptBuf, u64)>,
+}
+
+/// Channels funded by the same transaction.
+struct FundingBatch {
+    options: FundingOptions,
+    channels: Vec<BatchChannel>,
+}
+
+impl FundingBatch {
+    fn channel(&mut self, user_channel_id: u128) -> Option<&mut BatchChannel> {
+        self.channels
+            .iter_mut()
+            .find(|channel| channel.user_channel_id == user_channel_id)
+    }
+}
+
 pub struct LampoChannelManager {
     monitor: Option<Arc<LampoChainMonitor>>,
     wallet_manager: Arc<dyn WalletManager>,
@@ -79,6 +138,14 @@ pub struct LampoChannelManager {
     score: Option<Arc<Mutex<LampoScorer>>>,
     handler: RefCell<Option<Arc<LampoHandler>>>,
     router: Option<Arc<LampoRouter>>,
+    /// Funding options of the channels waiting for the funding
+    /// transaction, by user channel id.
+    funding: Mutex<HashMap<u128, FundingOptions>>,
+    /// The batches of channels waiting for the funding transaction.
+    batches: Mutex<Vec<FundingBatch>>,
+    /// The channels funded outside of the node, by user channel id,
+    /// with the funding output once the peer accepted the channel.
+    external_funding: Mutex<HashMap<u128, Option<ExternalFunding>>>,
 
     pub(crate) onchain: Arc<LampoChainManager>,
     pub(crate) conf: LampoConf,
@@ -114,6 +181,9 @@ impl LampoChannelManager {
             graph: None,
             score: None,
             router: None,
+            funding: Mutex::new(HashMap::new()),
+            batches: Mutex::new(Vec::new()),
+            external_funding: Mutex::new(HashMap::new()),
         }
     }
 
@@ -207,11 +277,597 @@ impl LampoChannelManager {
                 public: channel.is_public,
                 available_balance_for_send_msat: channel.outbound_capacity_msat,
                 available_balance_for_recv_msat: channel.inbound_capacity_msat,
+                fee_config: channel.config.map(ChannelFeeConfig::from),
             })
             .collect();
         Channels { channels }
     }
 
+    /// Return the options of the funding transaction chosen
+    /// when the channel was opened, if any.
+    pub fn take_funding_options(&self, user_channel_id: u128) -> Option<FundingOptions> {
+        self.funding.lock().unwrap().remove(&user_channel_id)
+    }
+
+    /// The UTXOs selected to fund the channels that are waiting
+    /// the funding, so they are not spent by anything else.
+    pub fn reserved_utxos(&self) -> Vec<OutPoint> {
+        let funding = self.funding.lock().unwrap();
+        let batches = self.batches.lock().unwrap();
+        funding
+            .values()
+            .chain(batches.iter().map(|batch| &batch.options))
+            .flat_map(|options| options.utxos.iter().cloned())
+            .collect()
+    }
+
+    /// Parse the UTXOs selected by the user, checking that they are
+    /// not already used by another channel that is waiting the funding.
+    ///
+    /// Nb. the caller must keep the locks until the UTXOs are reserved,
+    /// so two channels opened at the same time can not use the same ones.
+    fn funding_utxos(
+        funding: &HashMap<u128, FundingOptions>,
+        batches: &[FundingBatch],
+        utxos: &[String],
+    ) -> error::Result<Vec<OutPoint>> {
+        let mut outpoints: Vec<OutPoint> = Vec::new();
+        for utxo in utxos {
+            let outpoint = OutPoint::from_str(utxo)
+                .map_err(|err| error::anyhow!("invalid utxo `{utxo}`: {err}"))?;
+            let reserved = funding
+                .values()
+                .chain(batches.iter().map(|batch| &batch.options))
+                .any(|options| options.utxos.contains(&outpoint));
+            if reserved || outpoints.contains(&outpoint) {
+                error::bail!("utxo `{utxo}` is already used to fund a channel");
+            }
+            outpoints.push(outpoint);
+        }
+        Ok(outpoints)
+    }
+
+    /// Add the funding output of a channel to its batch, and fund all the
+    /// channels once the outputs of the batch are known.
+    ///
+    /// Return false when the channel is not part of a batch.
+    pub fn fund_batch(
+        &self,
+        user_channel_id: u128,
+        temporary_channel_id: ChannelId,
+        output_script: ScriptBuf,
+        channel_value_satoshis: u64,
+    ) -> error::Result<bool> {
+        let mut batches = self.batches.lock().unwrap();
+        let Some(index) = batches
+            .iter_mut()
+            .position(|batch| batch.channel(user_channel_id).is_some())
+        else {
+            return Ok(false);
+        };
+        if let Some(channel) = batches[index].channel(user_channel_id) {
+            channel.temporary_channel_id = Some(temporary_channel_id);
+            channel.output = Some((output_script, channel_value_satoshis));
+        }
+        if batches[index]
+            .channels
+            .iter()
+            .any(|channel| channel.output.is_none())
+        {
+            log::debug!(target: "channel_manager", "channel `{user_channel_id}` waits the others of the batch to be funded");
+            return Ok(true);
+        }
+        let batch = batches.remove(index);
+        drop(batches);
+        if let Err(err) = self.fund_batch_transaction(&batch) {
+            self.cancel_channels(&batch, &format!("{err}"));
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    fn fund_batch_transaction(&self, batch: &FundingBatch) -> error::Result<()> {
+        let fee = match batch.options.feerate {
+            Some(fee) => fee,
+            None => self.estimate_funding_feerate(6)?,
+        };
+        let outputs = batch
+            .channels
+            .iter()
+            .filter_map(|channel| channel.output.clone())
+            .collect::<Vec<_>>();
+        let transaction = self.wallet_manager.create_batch_transaction(
+            &outputs,
+            fee,
+            &batch.options.utxos,
+            batch.options.minconf,
+        )?;
+        log::info!(target: "channel_manager", "batch funding transaction created `{}`", transaction.txid());
+        let channels = batch
+            .channels
+            .iter()
+            .filter_map(|channel| Some((channel.temporary_channel_id?, channel.node_id)))
+            .collect::<Vec<_>>();
+        self.manager()
+            .batch_funding_transaction_generated(
+                &channels
+                    .iter()
+                    .map(|(channel_id, node_id)| (channel_id, node_id))
+                    .collect::<Vec<_>>(),
+                transaction.clone(),
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        for channel in batch.channels.iter() {
+            let (Some(temporary_channel_id), Some((_, channel_value_satoshis))) =
+                (channel.temporary_channel_id, channel.output.as_ref())
+            else {
+                continue;
+            };
+            self.handler()
+                .emit(Event::Lightning(LightningEvent::FundingChannelEnd {
+                    counterparty_node_id: channel.node_id,
+                    temporary_channel_id,
+                    channel_value_satoshis: *channel_value_satoshis,
+                    funding_transaction: transaction.clone(),
+                }));
+        }
+        Ok(())
+    }
+
+    /// Cancel the batch of the channel, if any, closing the
+    /// channels of the batch that are still open.
+    pub fn cancel_batch(&self, user_channel_id: u128, reason: &str) {
+        let mut batches = self.batches.lock().unwrap();
+        let Some(index) = batches
+            .iter_mut()
+            .position(|batch| batch.channel(user_channel_id).is_some())
+        else {
+            return;
+        };
+        let batch = batches.remove(index);
+        drop(batches);
+        self.cancel_channels(&batch, reason);
+    }
+
+    fn cancel_channels(&self, batch: &FundingBatch, reason: &str) {
+        log::warn!(target: "channel_manager", "cancel the batch funding: {reason}");
+        for channel in batch.channels.iter() {
+            let Some(temporary_channel_id) = channel.temporary_channel_id else {
+                continue;
+            };
+            // Nb. the channel may be already closed by the peer.
+            if let Err(err) = self
+                .manager()
+                .force_close_without_broadcasting_txn(&temporary_channel_id, &channel.node_id)
+            {
+                log::debug!(target: "channel_manager", "impossible close the channel `{temporary_channel_id}`: {:?}", err);
+            }
+        }
+        self.handler()
+            .emit(Event::Lightning(LightningEvent::ChannelEvent {
+                state: ChannelState::OpeningError,
+                message: format!("Batch Funding Cancelled: {reason}"),
+            }));
+    }
+
+    /// Start to open a channel funded by a transaction built outside of
+    /// the node, and return the user channel id and the temporary channel id.
+    pub fn start_external_funding(
+        &self,
+        open_channel: &request::OpenChannel,
+    ) -> error::Result<(u128, ChannelId)> {
+        let options = &open_channel.options;
+        if options.feerate.is_some()
+            || options.conf_target.is_some()
+            || options.utxos.is_some()
+            || options.minconf.is_some()
+        {
+            error::bail!("the funding transaction is built outside of the node, so the funding options can not be used");
+        }
+        let config = self.channel_config(open_channel.public, options)?;
+        if config
+            .channel_handshake_config
+            .negotiate_anchors_zero_fee_htlc_tx
+        {
+            // the channel is not funded by the wallet, but the
+            // wallet bumps the fee of its transactions.
+            self.check_anchor_reserve(0)?;
+        }
+        if let Some(ref close_to) = options.close_to {
+            self.shutdown_script(close_to)?;
+        }
+        let user_channel_id = self.user_channel_id(options.user_channel_id)?;
+        self.external_funding
+            .lock()
+            .unwrap()
+            .insert(user_channel_id, None);
+        match self.create_channel(open_channel, user_channel_id, config) {
+            Ok((temporary_channel_id, _)) => Ok((user_channel_id, temporary_channel_id)),
+            Err(err) => {
+                self.cancel_external_funding(user_channel_id);
+                Err(err)
+            }
+        }
+    }
+
+    /// Store the funding output of a channel funded outside of the node.
+    ///
+    /// Return false when the channel is funded by the node.
+    pub fn external_funding_ready(
+        &self,
+        user_channel_id: u128,
+        temporary_channel_id: ChannelId,
+        node_id: PublicKey,
+        output_script: ScriptBuf,
+        amount: u64,
+    ) -> bool {
+        let mut external_funding = self.external_funding.lock().unwrap();
+        let Some(funding) = external_funding.get_mut(&user_channel_id) else {
+            return false;
+        };
+        *funding = Some(ExternalFunding {
+            temporary_channel_id,
+            node_id,
+            output_script,
+            amount,
+        });
+        true
+    }
+
+    /// Return the funding output of the channel, if the peer
+    /// already accepted the channel.
+    pub fn external_funding(&self, user_channel_id: u128) -> Option<ExternalFunding> {
+        self.external_funding
+            .lock()
+            .unwrap()
+            .get(&user_channel_id)
+            .cloned()
+            .flatten()
+    }
+
+    pub fn cancel_external_funding(&self, user_channel_id: u128) {
+        self.external_funding
+            .lock()
+            .unwrap()
+            .remove(&user_channel_id);
+    }
+
+    /// Check that the transaction pays the funding output of the channel,
+    /// and give it to LDK that broadcasts it when the peer signed the
+    /// commitment transaction.
+    pub fn complete_external_funding(
+        &self,
+        temporary_channel_id: &str,
+        transaction: Transaction,
+    ) -> error::Result<ChannelId> {
+        let mut external_funding = self.external_funding.lock().unwrap();
+        let Some((user_channel_id, funding)) = external_funding.iter().find_map(|(id, funding)| {
+            let funding = funding.as_ref()?;
+            funding
+                .temporary_channel_id
+                .to_string()
+                .eq_ignore_ascii_case(temporary_channel_id)
+                .then(|| (*id, funding.clone()))
+        }) else {
+            error::bail!("no channel `{temporary_channel_id}` is waiting the funding transaction");
+        };
+        let index = check_funding_transaction(&funding, &transaction)?;
+        let txid = transaction.txid();
+        self.manager()
+            .funding_transaction_generated(
+                &funding.temporary_channel_id,
+                &funding.node_id,
+                transaction,
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        external_funding.remove(&user_channel_id);
+        log::info!(target: "channel_manager", "channel `{temporary_channel_id}` funded with the external transaction `{txid}`");
+        Ok(ChannelId::v1_from_funding_outpoint(
+            lampo_common::ldk::chain::transaction::OutPoint {
+                txid,
+                index: index as u16,
+            },
+        ))
+    }
+
+    /// Return the user channel id chosen when opening the channel, checking
+    /// that it is not used by another channel, or a new random one.
+    fn user_channel_id(&self, user_channel_id: Option<u128>) -> error::Result<u128> {
+        let Some(user_channel_id) = user_channel_id else {
+            return Ok(self.new_user_channel_id());
+        };
+        // Nb. the inbound channels are accepted with the id 0.
+        if user_channel_id == 0 {
+            error::bail!("user channel id `0` is reserved for the inbound channels");
+        }
+        let used = self
+            .manager()
+            .list_channels()
+            .iter()
+            .any(|channel| channel.user_channel_id == user_channel_id)
+            || self.funding.lock().unwrap().contains_key(&user_channel_id)
+            || self
+                .external_funding
+                .lock()
+                .unwrap()
+                .contains_key(&user_channel_id)
+            || self.batches.lock().unwrap().iter().any(|batch| {
+                batch
+                    .channels
+                    .iter()
+                    .any(|channel| channel.user_channel_id == user_channel_id)
+            })
+            || self.close_to(user_channel_id)?.is_some();
+        if used {
+            error::bail!("user channel id `{user_channel_id}` is already used by another channel");
+        }
+        Ok(user_channel_id)
+    }
+
+    fn new_user_channel_id(&self) -> u128 {
+        let bytes = self
+            .wallet_manager
+            .ldk_keys()
+            .keys_manager
+            .get_secure_random_bytes();
+        let mut id = [0; 16];
+        id.copy_from_slice(&bytes[..16]);
+        u128::from_be_bytes(id)
+    }
+
+    fn funding_feerate(
+        &self,
+        feerate: Option<u32>,
+        conf_target: Option<u64>,
+    ) -> error::Result<Option<u32>> {
+        match (feerate, conf_target) {
+            (Some(feerate), _) => Ok(Some(feerate)),
+            (None, Some(target)) => Ok(Some(self.estimate_funding_feerate(target)?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Estimate the feerate (in sats per 1000 weight) of a funding
+    /// transaction confirmed within `target` blocks.
+    pub fn estimate_funding_feerate(&self, target: u64) -> error::Result<u32> {
+        // the backend estimates the feerate in sats per 1000 vbytes,
+        // that are 4000 weight.
+        let feerate = self.onchain.backend.fee_rate_estimation(target)? / 4;
+        Ok(feerate.max(FEERATE_FLOOR_SATS_PER_KW))
+    }
+
+    /// Start to open the channel with the peer, the funding transaction
+    /// is created when the peer accepts the channel.
+    fn create_channel(
+        &self,
+        open_channel: &request::OpenChannel,
+        user_channel_id: u128,
+        config: UserConfig,
+    ) -> error::Result<(ChannelId, response::OpenChannel)> {
+        let push_msat = open_channel.options.push_msat.unwrap_or(0);
+        let temporary_channel_id = self
+            .manager()
+            .create_channel(
+                open_channel.node_id()?,
+                open_channel.amount,
+                push_msat,
+                user_channel_id,
+                None,
+                Some(config),
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        if let Some(ref close_to) = open_channel.options.close_to {
+            self.persister.write(
+                NAMESPACE,
+                CLOSE_TO,
+                &format!("{user_channel_id:032x}"),
+                close_to.as_bytes(),
+            )?;
+        }
+        let channel = response::OpenChannel {
+            node_id: open_channel.node_id.clone(),
+            amount: open_channel.amount,
+            public: open_channel.public,
+            push_mst: push_msat,
+            to_self_delay: config.channel_handshake_config.our_to_self_delay as u64,
+            temporary_channel_id: temporary_channel_id.to_string(),
+            user_channel_id,
+            state: response::OpenChannelState::Pending,
+            tx: None,
+        };
+        Ok((temporary_channel_id, channel))
+    }
+
+    /// Build the configuration of a new channel, overriding the
+    /// node configuration with the options of the request.
+    fn channel_config(
+        &self,
+        public: bool,
+        options: &OpenChannelOptions,
+    ) -> error::Result<UserConfig> {
+        let mut config = self.conf.ldk_conf;
+        config.channel_handshake_config.announced_channel = public;
+        if let Some(to_self_delay) = options.to_self_delay {
+            config.channel_handshake_config.our_to_self_delay = to_self_delay;
+        }
+        if options.close_to.is_some() {
+            // the script is sent when the channel is closed, so
+            // it can not be committed when the channel is opened.
+            config
+                .channel_handshake_config
+                .commit_upfront_shutdown_pubkey = false;
+        }
+        if let Some(ref features) = options.channel_type {
+            let scid_alias = features.contains(&ChannelTypeFeature::ScidAlias);
+            if scid_alias && public {
+                error::bail!(
+                    "`scid_alias` can be used only by the channels that are not announced"
+                );
+            }
+            config
+                .channel_handshake_config
+                .negotiate_anchors_zero_fee_htlc_tx =
+                features.contains(&ChannelTypeFeature::Anchors);
+            config.channel_handshake_config.negotiate_scid_privacy = scid_alias;
+            config.channel_handshake_limits.trust_own_funding_0conf =
+                features.contains(&ChannelTypeFeature::ZeroConf);
+        }
+        Ok(config)
+    }
+
+    fn shutdown_script(&self, address: &str) -> error::Result<ShutdownScript> {
+        let script = Address::from_str(address)?
+            .require_network(self.conf.network)?
+            .script_pubkey();
+        ShutdownScript::try_from(script)
+            .map_err(|_| error::anyhow!("`{address}` is not a valid shutdown address"))
+    }
+
+    /// Return the address chosen with `close_to` when
+    /// the channel was opened.
+    fn close_to(&self, user_channel_id: u128) -> error::Result<Option<String>> {
+        match self
+            .persister
+            .read(NAMESPACE, CLOSE_TO, &format!("{user_channel_id:032x}"))
+        {
+            Ok(buff) => Ok(Some(String::from_utf8(buff)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Forget the address chosen with `close_to`, when the channel is closed.
+    pub fn remove_close_to(&self, user_channel_id: u128) -> error::Result<()> {
+        match self.persister.remove(
+            NAMESPACE,
+            CLOSE_TO,
+            &format!("{user_channel_id:032x}"),
+            false,
+        ) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Check that the wallet keeps enough confirmed funds to bump the fee
+    /// of all the anchor channels, including a new one funded with
+    /// `funding_sat` from the wallet.
+    pub fn check_anchor_reserve(&self, funding_sat: u64) -> error::Result<()> {
+        self.check_reserve(Some(funding_sat), 1)
+    }
+
+    /// Check that the wallet keeps enough confirmed funds to bump the fee
+    /// of the anchor channels after sending `amount_sat` (or all the
+    /// funds when it is `None`) on chain.
+    pub fn check_withdraw_reserve(&self, amount_sat: Option<u64>) -> error::Result<()> {
+        self.check_reserve(amount_sat, 0)
+    }
+
+    fn check_reserve(&self, spent_sat: Option<u64>, new_channels: u64) -> error::Result<()> {
+        let reserve = self
+            .conf
+            .anchor_reserve_sat
+            .unwrap_or(DEFAULT_ANCHOR_RESERVE_SAT);
+        let channels = self
+            .manager()
+            .list_channels()
+            .iter()
+            .filter(|channel| {
+                channel.channel_type.as_ref().map_or(false, |features| {
+                    features.supports_anchors_zero_fee_htlc_tx()
+                })
+            })
+            .count() as u64
+            + new_channels;
+        if channels == 0 {
+            return Ok(());
+        }
+        let available = self
+            .wallet_manager
+            .list_confirmed_utxos()?
+            .iter()
+            .map(|utxo| utxo.output.value)
+            .sum::<u64>();
+        check_reserve_amount(reserve, channels, available, spent_sat)
+    }
+
+    /// Update the forwarding fees of the channels selected by the request,
+    /// and return the channels updated.
+    pub fn set_channel_fee(&self, request: &request::SetChannelFee) -> error::Result<Channels> {
+        let peer_id = request
+            .peer_id
+            .as_ref()
+            .map(|peer_id| PublicKey::from_str(peer_id))
+            .transpose()?;
+        let manager = self.manager();
+        let channels = manager
+            .list_channels()
+            .into_iter()
+            .filter(|channel| peer_id.map_or(true, |id| channel.counterparty.node_id == id))
+            .filter(|channel| {
+                request
+                    .channel_id
+                    .as_ref()
+                    .map_or(true, |id| channel.channel_id.to_string() == *id)
+            })
+            .filter(|channel| {
+                request
+                    .short_channel_id
+                    .map_or(true, |scid| channel.short_channel_id == Some(scid))
+            })
+            .collect::<Vec<_>>();
+        if channels.is_empty() {
+            error::bail!("no channel found for the request");
+        }
+        // Nb. validate before touching any channel, LDK refuses the
+        // same values only after the previous channels are updated.
+        if let Some(cltv_expiry_delta) = request.cltv_expiry_delta {
+            if cltv_expiry_delta < MIN_CLTV_EXPIRY_DELTA {
+                error::bail!("`cltv_expiry_delta` must be at least `{MIN_CLTV_EXPIRY_DELTA}`");
+            }
+        }
+        for (idx, channel) in channels.iter().enumerate() {
+            let mut config = channel.config.unwrap_or_default();
+            if let Some(fee_base_msat) = request.fee_base_msat {
+                config.forwarding_fee_base_msat = fee_base_msat;
+            }
+            if let Some(fee_ppm) = request.fee_ppm {
+                config.forwarding_fee_proportional_millionths = fee_ppm;
+            }
+            if let Some(cltv_expiry_delta) = request.cltv_expiry_delta {
+                config.cltv_expiry_delta = cltv_expiry_delta;
+            }
+            if let Err(err) = manager.update_channel_config(
+                &channel.counterparty.node_id,
+                &[channel.channel_id],
+                &config,
+            ) {
+                // restore the channels already updated, so the request
+                // is applied to all the channels or to none.
+                for channel in channels[..idx].iter() {
+                    let _ = manager.update_channel_config(
+                        &channel.counterparty.node_id,
+                        &[channel.channel_id],
+                        &channel.config.unwrap_or_default(),
+                    );
+                }
+                error::bail!("channel `{}` not updated: {:?}", channel.channel_id, err);
+            }
+        }
+        let updated = self
+            .list_channel()
+            .channels
+            .into_iter()
+            .filter(|channel| {
+                channels
+                    .iter()
+                    .any(|updated| updated.channel_id.to_string() == channel.channel_id)
+            })
+            .collect();
+        Ok(Channels { channels: updated })
+    }
+
     pub fn load_channel_monitors(&self, watch: bool) -> error::Result<()> {
         let keys = self.wallet_manager.ldk_keys().inner();
         let mut monitors = read_channel_monitors(self.persister.clone(), keys.clone(), keys)?;
@@ -248,6 +904,82 @@ impl LampoChannelManager {
         self.score.clone().unwrap()
     }
 
+    /// List the nodes of the network graph, or only the
+    /// node specified.
+    pub fn list_nodes(&self, node_id: Option<PublicKey>) -> Nodes {
+        let graph = self.graph();
+        let graph = graph.read_only();
+        let nodes = match node_id {
+            Some(node_id) => {
+                let node_id = NodeId::from_pubkey(&node_id);
+                graph
+                    .node(&node_id)
+                    .map(|info| vec![GossipNode::from((&node_id, info))])
+                    .unwrap_or_default()
+            }
+            None => graph
+                .nodes()
+                .unordered_iter()
+                .map(GossipNode::from)
+                .collect(),
+        };
+        Nodes { nodes }
+    }
+
+    /// List the public channels of the network graph, filtered by
+    /// short channel id or by one of the nodes of the channel.
+    pub fn list_gossip_channels(
+        &self,
+        short_channel_id: Option<u64>,
+        node_id: Option<PublicKey>,
+    ) -> GossipChannels {
+        let graph = self.graph();
+        let graph = graph.read_only();
+        let node_id = node_id.map(|node_id| NodeId::from_pubkey(&node_id));
+        let channels = graph
+            .channels()
+            .unordered_iter()
+            .filter(|(scid, _)| short_channel_id.map_or(true, |id| **scid == id))
+            .filter(|(_, info)| {
+                node_id.map_or(true, |id| info.node_one == id || info.node_two == id)
+            })
+            .map(|(scid, info)| GossipChannel::from((*scid, info)))
+            .collect();
+        GossipChannels { channels }
+    }
+
+    pub fn graph_stats(&self) -> GraphStats {
+        let graph = self.graph();
+        let graph = graph.read_only();
+        let mut stats = GraphStats {
+            nodes: graph.nodes().len(),
+            announced_nodes: graph
+                .nodes()
+                .unordered_iter()
+                .filter(|(_, info)| info.announcement_info.is_some())
+                .count(),
+            channels: graph.channels().len(),
+            disabled_channels: 0,
+            channels_without_updates: 0,
+            total_capacity_sat: 0,
+        };
+        for (_, info) in graph.channels().unordered_iter() {
+            let directions = [info.one_to_two.as_ref(), info.two_to_one.as_ref()];
+            if directions.iter().all(|update| update.is_none()) {
+                stats.channels_without_updates += 1;
+            }
+            if directions.iter().flatten().any(|update| !update.enabled) {
+                stats.disabled_channels += 1;
+            }
+            stats.total_capacity_sat += info.capacity_sats.unwrap_or_default();
+        }
+        stats
+    }
+
+    pub fn router(&self) -> Arc<LampoRouter> {
+        self.router.clone().unwrap()
+    }
+
     pub fn network_graph(&mut self) -> Arc<LampoRouter> {
         if self.router.is_none() {
             let network_graph_path = format!("{}/network_graph", self.conf.path());
@@ -400,44 +1132,206 @@ impl ChannelEvents for LampoChannelManager {
         &self,
         open_channel: request::OpenChannel,
     ) -> error::Result<response::OpenChannel> {
-        self.manager()
-            .create_channel(
-                open_channel.node_id()?,
-                open_channel.amount,
-                0,
-                0,
-                None,
-                Some(self.conf.ldk_conf),
-            )
-            .map_err(|err| error::anyhow!("{:?}", err))?;
-
-        // Wait for SendRawTransaction to be received so to get the funding transaction
-        // FIXME: we can loop forever here
-        let tx: Option<Transaction> = loop {
-            let events = self.handler().events();
-            let event = events.recv_timeout(std::time::Duration::from_secs(30))?;
+        let options = &open_channel.options;
+        let config = self.channel_config(open_channel.public, options)?;
+        if config
+            .channel_handshake_config
+            .negotiate_anchors_zero_fee_htlc_tx
+        {
+            self.check_anchor_reserve(open_channel.amount)?;
+        }
+        // validate the address before opening the channel.
+        if let Some(ref close_to) = options.close_to {
+            self.shutdown_script(close_to)?;
+        }
+        let feerate = self.funding_feerate(options.feerate, options.conf_target)?;
+        let user_channel_id = self.user_channel_id(options.user_channel_id)?;
+        {
+            let mut funding = self.funding.lock().unwrap();
+            let utxos = Self::funding_utxos(
+                &funding,
+                &self.batches.lock().unwrap(),
+                options.utxos.as_deref().unwrap_or_default(),
+            )?;
+            funding.insert(
+                user_channel_id,
+                FundingOptions {
+                    feerate,
+                    utxos,
+                    minconf: options.minconf,
+                },
+            );
+        }
+        // Nb. the funding transaction is created when the peer accepts
+        // the channel, so we do not wait for it here.
+        match self.create_channel(&open_channel, user_channel_id, config) {
+            Ok((_, channel)) => Ok(channel),
+            Err(err) => {
+                self.take_funding_options(user_channel_id);
+                Err(err)
+            }
+        }
+    }
 
-            if let Event::OnChain(OnChainEvent::SendRawTransaction(tx)) = event {
-                break Some(tx);
+    fn multi_open_channel(
+        &self,
+        request: request::MultiFundChannel,
+    ) -> error::Result<response::MultiFundChannel> {
+        if request.destinations.is_empty() {
+            error::bail!("no channels to fund");
+        }
+        let mut configs = Vec::new();
+        let mut anchors = 0;
+        let mut anchors_funding = 0;
+        for destination in request.destinations.iter() {
+            let options = &destination.options;
+            if options.feerate.is_some()
+                || options.conf_target.is_some()
+                || options.utxos.is_some()
+                || options.minconf.is_some()
+            {
+                error::bail!(
+                    "the funding options of `{}` must be specified for the whole batch",
+                    destination.node_id
+                );
             }
-        };
+            let config = self.channel_config(destination.public, options)?;
+            if config
+                .channel_handshake_config
+                .negotiate_anchors_zero_fee_htlc_tx
+            {
+                anchors += 1;
+                anchors_funding += destination.amount;
+            }
+            if let Some(ref close_to) = options.close_to {
+                self.shutdown_script(close_to)?;
+            }
+            let user_channel_id = self.user_channel_id(options.user_channel_id)?;
+            if configs.iter().any(|(id, _)| *id == user_channel_id) {
+                error::bail!("user channel id `{user_channel_id}` is used more than once");
+            }
+            configs.push((user_channel_id, config));
+        }
+        if anchors > 0 {
+            // `check_anchor_reserve` accounts the reserve of one new
+            // channel, so we add the reserve of the others to the funding.
+            let reserve = self
+                .conf
+                .anchor_reserve_sat
+                .unwrap_or(DEFAULT_ANCHOR_RESERVE_SAT);
+            self.check_anchor_reserve(anchors_funding + reserve * (anchors - 1))?;
+        }
+        let feerate = self.funding_feerate(request.feerate, request.conf_target)?;
+        let channels = request
+            .destinations
+            .iter()
+            .zip(configs.iter())
+            .map(|(destination, (user_channel_id, _))| {
+                Ok(BatchChannel {
+                    user_channel_id: *user_channel_id,
+                    node_id: destination.node_id()?,
+                    temporary_channel_id: None,
+                    output: None,
+                })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        // Nb. the batch is stored before opening the channels, so
+        // the funding events find it.
+        {
+            let funding = self.funding.lock().unwrap();
+            let mut batches = self.batches.lock().unwrap();
+            let utxos = Self::funding_utxos(
+                &funding,
+                &batches,
+                request.utxos.as_deref().unwrap_or_default(),
+            )?;
+            batches.push(FundingBatch {
+                options: FundingOptions {
+                    feerate,
+                    utxos,
+                    minconf: request.minconf,
+                },
+                channels,
+            });
+        }
 
-        Ok(response::OpenChannel {
-            node_id: open_channel.node_id,
-            amount: open_channel.amount,
-            public: open_channel.public,
-            push_mst: 0,
-            to_self_delay: 2016,
-            tx,
-        })
+        let mut channels = Vec::new();
+        for (destination, (user_channel_id, config)) in request.destinations.iter().zip(configs) {
+            let (temporary_channel_id, channel) =
+                match self.create_channel(destination, user_channel_id, config) {
+                    Ok(channel) => channel,
+                    Err(err) => {
+                        let reason = format!(
+                            "impossible open the channel with `{}`: {err}",
+                            destination.node_id
+                        );
+                        self.cancel_batch(user_channel_id, &reason);
+                        error::bail!("{reason}");
+                    }
+                };
+            let mut batches = self.batches.lock().unwrap();
+            let Some(batch_channel) = batches
+                .iter_mut()
+                .find_map(|batch| batch.channel(user_channel_id))
+            else {
+                // the batch was cancelled while we were opening the
+                // channels, e.g. a peer rejected the channel.
+                drop(batches);
+                let _ = self.manager().force_close_without_broadcasting_txn(
+                    &temporary_channel_id,
+                    &destination.node_id()?,
+                );
+                error::bail!("the batch funding was cancelled");
+            };
+            batch_channel.temporary_channel_id = Some(temporary_channel_id);
+            channels.push(channel);
+        }
+        Ok(response::MultiFundChannel { channels })
     }
 
     fn close_channel(&self, channel: request::CloseChannel) -> error::Result<()> {
         let channel_id = channel.channel_id()?;
         let node_id = channel.counterpart_node_id()?;
 
+        if channel.force.unwrap_or(false) {
+            // the options of the cooperative close are not used by our
+            // commitment transaction, so we do not ignore them silently.
+            let options = [
+                ("destination", channel.destination.is_some()),
+                ("feerate", channel.feerate.is_some()),
+                ("unilateral_timeout", channel.unilateral_timeout.is_some()),
+            ];
+            if let Some((option, _)) = options.iter().find(|(_, used)| *used) {
+                error::bail!("`{option}` can not be used to force close a channel");
+            }
+            log::warn!(target: "channel_manager", "force closing the channel `{channel_id}` with `{node_id}`");
+            self.manager()
+                .force_close_broadcasting_latest_txn(&channel_id, &node_id)
+                .map_err(|err| error::anyhow!("{:?}", err))?;
+            return Ok(());
+        }
+        let destination = match channel.destination {
+            Some(destination) => Some(destination),
+            None => match self
+                .manager()
+                .list_channels()
+                .iter()
+                .find(|details| details.channel_id == channel_id)
+            {
+                Some(details) => self.close_to(details.user_channel_id)?,
+                None => None,
+            },
+        };
+        let shutdown_script = destination
+            .map(|destination| self.shutdown_script(&destination))
+            .transpose()?;
         self.manager()
-            .close_channel(&channel_id, &node_id)
+            .close_channel_with_feerate_and_script(
+                &channel_id,
+                &node_id,
+                channel.feerate,
+                shutdown_script,
+            )
             .map_err(|err| error::anyhow!("{:?}", err))?;
         Ok(())
     }
@@ -445,3 +1339,139 @@ impl ChannelEvents for LampoChannelManager {
         unimplemented!()
     }
 }
+
+/// Check that the transaction pays the funding output exactly once,
+/// with the amount of the channel, and return the index of the output.
+fn check_funding_transaction(
+    funding: &ExternalFunding,
+    transaction: &Transaction,
+) -> error::Result<usize> {
+    let outputs = transaction
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey == funding.output_script)
+        .collect::<Vec<_>>();
+    let (index, output) = match outputs.as_slice() {
+        [(index, output)] => (*index, *output),
+        [] => error::bail!("the transaction does not pay the funding output of the channel"),
+        _ => error::bail!("the transaction pays the funding output of the channel more than once"),
+    };
+    if output.value != funding.amount {
+        error::bail!(
+            "the transaction pays {} sats to the funding output, but {} sats are expected",
+            output.value,
+            funding.amount
+        );
+    }
+    if transaction
+        .input
+        .iter()
+        .any(|input| input.witness.is_empty())
+    {
+        error::bail!("the funding transaction is not signed, or it spends non segwit outputs");
+    }
+    Ok(index)
+}
+
+/// Check that `available_sat` covers `reserve_sat` for each of the anchor
+/// `channels` after spending `spent_sat` (or all the funds when it is `None`).
+fn check_reserve_amount(
+    reserve_sat: u64,
+    channels: u64,
+    available_sat: u64,
+    spent_sat: Option<u64>,
+) -> error::Result<()> {
+    let required = reserve_sat * channels + spent_sat.unwrap_or(available_sat);
+    if available_sat < required {
+        error::bail!(
+            "{available_sat} sats of confirmed on chain funds, but {required} sats are required to keep the reserve of {channels} anchor channels"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use lampo_common::bitcoin::absolute::LockTime;
+    use lampo_common::bitcoin::{ScriptBuf, Transaction, TxIn, TxOut, Witness};
+    use lampo_common::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use lampo_common::types::ChannelId;
+
+    use super::{check_funding_transaction, ExternalFunding};
+    use super::{check_reserve_amount, DEFAULT_ANCHOR_RESERVE_SAT};
+
+    fn funding() -> ExternalFunding {
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        ExternalFunding {
+            temporary_channel_id: ChannelId([2; 32]),
+            node_id: PublicKey::from_secret_key(&Secp256k1::new(), &secret),
+            output_script: ScriptBuf::from_bytes(vec![0x00, 0x20, 0x03]),
+            amount: 100_000,
+        }
+    }
+
+    fn transaction(outputs: Vec<TxOut>, witness: Witness) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                witness,
+                ..Default::default()
+            }],
+            output: outputs,
+        }
+    }
+
+    #[test]
+    fn check_the_external_funding_transaction() {
+        let funding = funding();
+        let signed = Witness::from_slice(&[vec![1; 72], vec![2; 33]]);
+        let funding_output = TxOut {
+            value: funding.amount,
+            script_pubkey: funding.output_script.clone(),
+        };
+        let change = TxOut {
+            value: 50_000,
+            script_pubkey: ScriptBuf::new(),
+        };
+
+        let tx = transaction(vec![change.clone(), funding_output.clone()], signed.clone());
+        assert_eq!(check_funding_transaction(&funding, &tx).unwrap(), 1);
+
+        // the funding output is missing, or paid twice.
+        let tx = transaction(vec![change.clone()], signed.clone());
+        assert!(check_funding_transaction(&funding, &tx).is_err());
+        let tx = transaction(
+            vec![funding_output.clone(), funding_output.clone()],
+            signed.clone(),
+        );
+        assert!(check_funding_transaction(&funding, &tx).is_err());
+
+        // the amount is not the one of the channel.
+        let wrong_amount = TxOut {
+            value: funding.amount - 1,
+            ..funding_output.clone()
+        };
+        let tx = transaction(vec![wrong_amount], signed);
+        assert!(check_funding_transaction(&funding, &tx).is_err());
+
+        // the inputs are not signed.
+        let tx = transaction(vec![funding_output], Witness::new());
+        assert!(check_funding_transaction(&funding, &tx).is_err());
+    }
+
+    #[test]
+    fn check_the_anchor_reserve() {
+        let reserve = DEFAULT_ANCHOR_RESERVE_SAT;
+        // a new anchor channel funded with 100k sats.
+        assert!(check_reserve_amount(reserve, 1, 125_000, Some(100_000)).is_ok());
+        assert!(check_reserve_amount(reserve, 1, 124_999, Some(100_000)).is_err());
+        // the reserve grows with the anchor channels already open.
+        assert!(check_reserve_amount(reserve, 3, 125_000, Some(50_001)).is_err());
+        assert!(check_reserve_amount(reserve, 3, 125_000, Some(50_000)).is_ok());
+        // withdraw all the funds with anchor channels open.
+        assert!(check_reserve_amount(reserve, 1, 125_000, None).is_err());
+        assert!(check_reserve_amount(reserve, 0, 125_000, None).is_ok());
+    }
+}
@@ -30,6 +30,12 @@ pub trait ChannelEvents {
         open_channel: request::OpenChannel,
     ) -> error::Result<response::OpenChannel>;
 
+    /// Open more channels funded by the same transaction
+    fn multi_open_channel(
+        &self,
+        request: request::MultiFundChannel,
+    ) -> error::Result<response::MultiFundChannel>;
+
     /// Close a channel
     fn close_channel(&self, channel: request::CloseChannel) -> error::Result<()>;
 
@@ -4,6 +4,7 @@ mod inventory_manager;
 mod offchain_manager;
 mod onion_message;
 mod peer_manager;
+mod rapid_gossip_sync;
 mod route;
 
 pub mod events;
@@ -13,3 +14,4 @@ pub use channel_manager::LampoChannelManager;
 pub use inventory_manager::LampoInventoryManager;
 pub use offchain_manager::OffchainManager;
 pub use peer_manager::LampoPeerManager;
+pub use rapid_gossip_sync::LampoRapidSync;
@@ -10,6 +10,7 @@
 //! with the network graph. But this is not so clear yet.
 //!
 //! Author: Vincenzo Palazzo <vincenzopalazzo@member.fsf.org>
+use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -21,24 +22,58 @@ use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::ldk;
 use lampo_common::ldk::ln::channelmanager::Retry;
-use lampo_common::ldk::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use lampo_common::ldk::ln::channelmanager::{
+    PaymentId, RecentPaymentDetails, RecipientOnionFields, RetryableSendFailure,
+};
 use lampo_common::ldk::ln::{PaymentHash, PaymentPreimage};
 use lampo_common::ldk::offers::offer::Amount;
 use lampo_common::ldk::offers::offer::Offer;
+use lampo_common::ldk::offers::parse::Bolt12SemanticError;
+use lampo_common::ldk::routing::gossip::NodeId;
+use lampo_common::ldk::routing::router::{InFlightHtlcs, Router};
 use lampo_common::ldk::routing::router::{PaymentParameters, RouteParameters};
 use lampo_common::ldk::sign::EntropySource;
 use lampo_common::ldk::sign::KeysManager;
+use lampo_common::model::request::{GetRoute, PayOptions};
+use lampo_common::model::response;
+use lampo_common::model::response::{PaymentDetails, PaymentRecord, PaymentStatus};
 
 use super::LampoChannelManager;
 use crate::chain::LampoChainManager;
+use crate::persistence::payments::now;
+use crate::persistence::PaymentStore;
 use crate::utils::logger::LampoLogger;
 
+/// CLTV delta expected by the destination when not specified,
+/// the same used by the keysend payments when the node does
+/// not configure `final-cltv-expiry-delta`.
+const DEFAULT_FINAL_CLTV_EXPIRY_DELTA: u32 = 40;
+
+/// The error returned by LDK when a payment is sent.
+trait SendError: fmt::Debug {
+    /// Return true if LDK is already tracking a payment with the same id.
+    fn is_duplicate(&self) -> bool;
+}
+
+impl SendError for RetryableSendFailure {
+    fn is_duplicate(&self) -> bool {
+        matches!(self, RetryableSendFailure::DuplicatePayment)
+    }
+}
+
+impl SendError for Bolt12SemanticError {
+    fn is_duplicate(&self) -> bool {
+        matches!(self, Bolt12SemanticError::DuplicatePaymentId)
+    }
+}
+
 pub struct OffchainManager {
     channel_manager: Arc<LampoChannelManager>,
     keys_manager: Arc<KeysManager>,
     logger: Arc<LampoLogger>,
     lampo_conf: Arc<LampoConf>,
     chain_manager: Arc<LampoChainManager>,
+    payments: Arc<PaymentStore>,
 }
 
 impl OffchainManager {
@@ -49,6 +84,7 @@ impl OffchainManager {
         logger: Arc<LampoLogger>,
         lampo_conf: Arc<LampoConf>,
         chain_manager: Arc<LampoChainManager>,
+        payments: Arc<PaymentStore>,
     ) -> error::Result<Self> {
         Ok(Self {
             channel_manager,
@@ -56,9 +92,279 @@ impl OffchainManager {
             logger,
             lampo_conf,
             chain_manager,
+            payments,
+        })
+    }
+
+    pub fn payments(&self) -> Arc<PaymentStore> {
+        self.payments.clone()
+    }
+
+    /// Store the payment before sending it, so the result of the
+    /// payment is recorded also when it is received before `send`
+    /// returns. When `send` fails the payment is marked as failed.
+    ///
+    /// When LDK is still tracking a payment with the same id, this
+    /// attempt is not started and the previous record is kept.
+    fn track_payment<R, E: SendError>(
+        &self,
+        payment_id: PaymentId,
+        record: PaymentRecord,
+        send: impl FnOnce() -> Result<R, E>,
+    ) -> error::Result<R> {
+        let previous = self.payments.add_payment(record)?;
+        match send() {
+            Ok(result) => Ok(result),
+            Err(err) if err.is_duplicate() => {
+                self.payments.restore_payment(&payment_id, previous)?;
+                error::bail!("{:?}", err)
+            }
+            Err(err) => {
+                self.payments
+                    .payment_failed(&payment_id, format!("{:?}", err))?;
+                error::bail!("{:?}", err)
+            }
+        }
+    }
+
+    /// Apply the options of the user to the parameters used to route the payment.
+    fn apply_pay_options(
+        &self,
+        params: &mut RouteParameters,
+        options: &PayOptions,
+    ) -> error::Result<()> {
+        Self::apply_route_limits(params, options)?;
+        let excluded = self.excluded_channels(&options.exclude_nodes, &options.exclude_channels);
+        params
+            .payment_params
+            .previously_failed_channels
+            .extend(excluded);
+        Ok(())
+    }
+
+    /// Apply the fee, parts and CLTV limits asked by the user.
+    fn apply_route_limits(params: &mut RouteParameters, options: &PayOptions) -> error::Result<()> {
+        params.max_total_routing_fee_msat = Self::max_fee_msat(params.final_value_msat, options)?
+            .or(params.max_total_routing_fee_msat);
+        if let Some(max_parts) = options.max_parts {
+            if max_parts == 0 {
+                error::bail!("`max_parts` must be greater than zero");
+            }
+            params.payment_params.max_path_count = max_parts;
+        }
+        if let Some(max_cltv_expiry) = options.max_cltv_expiry {
+            params.payment_params.max_total_cltv_expiry_delta = max_cltv_expiry;
+        }
+        Ok(())
+    }
+
+    /// Return the final CLTV delta of a keysend payment, that must
+    /// fit inside the `max_cltv_expiry` of the user.
+    fn keysend_cltv_expiry_delta(
+        final_cltv_expiry_delta: Option<u32>,
+        options: &PayOptions,
+    ) -> error::Result<u32> {
+        let final_cltv_expiry_delta =
+            final_cltv_expiry_delta.unwrap_or(DEFAULT_FINAL_CLTV_EXPIRY_DELTA);
+        if let Some(max_cltv_expiry) = options.max_cltv_expiry {
+            if max_cltv_expiry < final_cltv_expiry_delta {
+                error::bail!(
+                    "`max_cltv_expiry` must be at least the final CLTV delta `{final_cltv_expiry_delta}`"
+                );
+            }
+        }
+        Ok(final_cltv_expiry_delta)
+    }
+
+    /// Return the channels that the router should not use.
+    ///
+    /// Nb. the router skips the channels that are failed before, so
+    /// we exclude a node by excluding all its channels.
+    fn excluded_channels(&self, nodes: &[pubkey], channels: &[u64]) -> Vec<u64> {
+        let graph = self.channel_manager.graph();
+        let graph = graph.read_only();
+        let mut excluded = channels.to_vec();
+        for node in nodes {
+            if let Some(info) = graph.node(&NodeId::from_pubkey(node)) {
+                excluded.extend(info.channels.iter());
+            }
+            excluded.extend(
+                self.channel_manager
+                    .manager()
+                    .list_channels_with_counterparty(node)
+                    .iter()
+                    .filter_map(|channel| channel.short_channel_id),
+            );
+        }
+        excluded
+    }
+
+    /// Compute the route that would be used to pay the destination,
+    /// without sending anything.
+    pub fn get_route(&self, request: &GetRoute) -> error::Result<response::Route> {
+        let manager = self.channel_manager.manager();
+        let mut payment_params = PaymentParameters::from_node_id(
+            request.destination,
+            request.cltv.unwrap_or(
+                self.lampo_conf
+                    .final_cltv_expiry_delta
+                    .unwrap_or(DEFAULT_FINAL_CLTV_EXPIRY_DELTA),
+            ),
+        );
+        payment_params.previously_failed_channels =
+            self.excluded_channels(&request.exclude_nodes, &request.exclude_channels);
+        let params = RouteParameters {
+            payment_params,
+            final_value_msat: request.amount_msat,
+            max_total_routing_fee_msat: request.maxfee_msat,
+        };
+        let first_hops = manager.list_usable_channels();
+        let first_hops = first_hops.iter().collect::<Vec<_>>();
+        let router = self.channel_manager.router();
+        let route = router
+            .find_route(
+                &manager.get_our_node_id(),
+                &params,
+                Some(&first_hops),
+                InFlightHtlcs::new(),
+            )
+            .map_err(|err| error::anyhow!("{}", err.err))?;
+
+        let scorer = self.channel_manager.scorer();
+        let scorer = scorer.lock().unwrap();
+        let mut paths = Vec::new();
+        for path in route.paths.iter() {
+            let mut hops = Vec::new();
+            // the amount that flows through the channel is the amount
+            // delivered plus the fees of the following hops.
+            let mut amount_msat = path.final_value_msat() + path.fee_msat();
+            for hop in path.hops.iter() {
+                let target = NodeId::from_pubkey(&hop.pubkey);
+                let probability = scorer
+                    .historical_estimated_payment_success_probability(
+                        hop.short_channel_id,
+                        &target,
+                        amount_msat,
+                        router.score_params(),
+                    )
+                    .or_else(|| {
+                        scorer.live_estimated_payment_success_probability(
+                            hop.short_channel_id,
+                            &target,
+                            amount_msat,
+                            router.score_params(),
+                        )
+                    });
+                hops.push(response::RouteHop {
+                    node_id: hop.pubkey.to_string(),
+                    short_channel_id: hop.short_channel_id,
+                    amount_msat,
+                    fee_msat: hop.fee_msat,
+                    cltv_expiry_delta: hop.cltv_expiry_delta,
+                    probability,
+                });
+                amount_msat -= hop.fee_msat;
+            }
+            // the last hop carries the amount paid.
+            if let Some(last) = hops.last_mut() {
+                last.fee_msat = 0;
+            }
+            paths.push(response::RoutePath {
+                amount_msat: path.final_value_msat(),
+                fee_msat: path.fee_msat(),
+                cltv_expiry_delta: hops.iter().map(|hop| hop.cltv_expiry_delta).sum(),
+                // the channels unknown to the scorer (e.g. our channels) are
+                // not penalized by the router, so we count them as certain.
+                probability: hops.iter().filter_map(|hop| hop.probability).product(),
+                hops,
+            });
+        }
+        Ok(response::Route {
+            amount_msat: route.get_total_amount(),
+            fee_msat: route.get_total_fees(),
+            probability: paths.iter().map(|path| path.probability).product(),
+            paths,
         })
     }
 
+    fn max_fee_msat(amount_msat: u64, options: &PayOptions) -> error::Result<Option<u64>> {
+        match (options.maxfee_msat, options.maxfeepercent) {
+            (Some(_), Some(_)) => {
+                error::bail!("`maxfee_msat` and `maxfeepercent` are mutually exclusive")
+            }
+            (Some(maxfee_msat), None) => Ok(Some(maxfee_msat)),
+            (None, Some(percent)) if !(0.0..=100.0).contains(&percent) => {
+                error::bail!("`maxfeepercent` must be between 0 and 100")
+            }
+            (None, Some(percent)) => Ok(Some((amount_msat as f64 * percent / 100.0) as u64)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn retry_strategy(options: &PayOptions, default: Retry) -> error::Result<Retry> {
+        match (options.retry, options.retry_for) {
+            (Some(_), Some(_)) => error::bail!("`retry` and `retry_for` are mutually exclusive"),
+            (Some(attempts), None) => Ok(Retry::Attempts(attempts)),
+            (None, Some(secs)) => Ok(Retry::Timeout(Duration::from_secs(secs))),
+            (None, None) => Ok(default),
+        }
+    }
+
+    /// Return the payment with all the attempts made so far.
+    ///
+    /// The payments that are not inside the store (e.g. sent before
+    /// the store existed) are looked up between the recent payments
+    /// known by the channel manager.
+    pub fn payment_details(&self, payment_id: &PaymentId) -> error::Result<Option<PaymentDetails>> {
+        let attempts = self.payments.sendpays(payment_id)?;
+        if let Some(payment) = self.payments.payment(payment_id)? {
+            return Ok(Some(PaymentDetails { payment, attempts }));
+        }
+        let recent = self
+            .channel_manager
+            .manager()
+            .list_recent_payments()
+            .into_iter()
+            .find_map(|payment| match payment {
+                RecentPaymentDetails::AwaitingInvoice { payment_id: id } if id == *payment_id => {
+                    Some((PaymentStatus::Pending, None, None))
+                }
+                RecentPaymentDetails::Pending {
+                    payment_id: id,
+                    payment_hash,
+                    total_msat,
+                } if id == *payment_id => {
+                    Some((PaymentStatus::Pending, Some(payment_hash), Some(total_msat)))
+                }
+                RecentPaymentDetails::Fulfilled {
+                    payment_id: id,
+                    payment_hash,
+                } if id == *payment_id => Some((PaymentStatus::Complete, payment_hash, None)),
+                RecentPaymentDetails::Abandoned {
+                    payment_id: id,
+                    payment_hash,
+                } if id == *payment_id => Some((PaymentStatus::Failed, Some(payment_hash), None)),
+                _ => None,
+            });
+        let Some((status, payment_hash, amount_msat)) = recent else {
+            return Ok(None);
+        };
+        let payment = PaymentRecord {
+            payment_id: hex::encode(payment_id.0),
+            payment_hash: payment_hash.map(|hash| hex::encode(hash.0)),
+            destination: None,
+            invoice: None,
+            amount_msat,
+            fee_msat: None,
+            status,
+            payment_preimage: None,
+            failure_reason: None,
+            created_at: None,
+            completed_at: None,
+        };
+        Ok(Some(PaymentDetails { payment, attempts }))
+    }
+
     /// Generate an invoice with a specific amount and a specific
     /// description.
     pub fn generate_invoice(
@@ -66,9 +372,15 @@ impl OffchainManager {
         amount_msat: Option<u64>,
         description: &str,
         expiring_in: u32,
+        label: Option<String>,
     ) -> error::Result<ldk::invoice::Bolt11Invoice> {
         let currency = ldk::invoice::Currency::try_from(self.lampo_conf.network)?;
-        let invoice = ldk::invoice::utils::create_invoice_from_channelmanager(
+        // reserve the label before creating the invoice, that LDK
+        // would keep waiting for a payment.
+        if let Some(ref label) = label {
+            self.payments.reserve_label(label)?;
+        }
+        let invoice = match ldk::invoice::utils::create_invoice_from_channelmanager(
             &self.channel_manager.manager(),
             self.keys_manager.clone(),
             self.logger.clone(),
@@ -77,8 +389,16 @@ impl OffchainManager {
             description.to_string(),
             expiring_in,
             None,
-        )
-        .map_err(|err| error::anyhow!(err))?;
+        ) {
+            Ok(invoice) => invoice,
+            Err(err) => {
+                if let Some(ref label) = label {
+                    self.payments.release_label(label);
+                }
+                return Err(error::anyhow!(err));
+            }
+        };
+        self.payments.add_invoice(&invoice, label)?;
         Ok(invoice)
     }
 
@@ -87,7 +407,12 @@ impl OffchainManager {
         Ok(invoice)
     }
 
-    pub fn pay_offer(&self, offer_str: &str, amount_msat: Option<u64>) -> error::Result<()> {
+    pub fn pay_offer(
+        &self,
+        offer_str: &str,
+        amount_msat: Option<u64>,
+        options: &PayOptions,
+    ) -> error::Result<PaymentId> {
         // check if it is an invoice or an offer
         let offer_hash = Sha256::hash(offer_str.as_bytes());
         let payment_id = PaymentId(*offer_hash.as_ref());
@@ -101,27 +426,54 @@ impl OffchainManager {
             ),
             None => amount_msat.ok_or(error::anyhow!("An amount need to be specified"))?,
         };
+        // the route is computed by LDK only when the invoice is received.
+        if options.max_parts.is_some()
+            || options.max_cltv_expiry.is_some()
+            || !options.exclude_nodes.is_empty()
+            || !options.exclude_channels.is_empty()
+        {
+            error::bail!("`max_parts`, `max_cltv_expiry` and the exclusions are not supported when paying an offer");
+        }
+        let max_fee_msat = Self::max_fee_msat(amount, options)?;
+        let retry = Self::retry_strategy(options, Retry::Attempts(10))?;
 
-        self.channel_manager
-            .manager()
-            .pay_for_offer(
+        let record = PaymentRecord {
+            payment_id: hex::encode(payment_id.0),
+            payment_hash: None,
+            destination: offer.signing_pubkey().map(|key| key.to_string()),
+            invoice: Some(offer_str.to_owned()),
+            amount_msat: Some(amount),
+            fee_msat: None,
+            status: PaymentStatus::Pending,
+            payment_preimage: None,
+            failure_reason: None,
+            created_at: Some(now()),
+            completed_at: None,
+        };
+        self.track_payment(payment_id, record, || {
+            self.channel_manager.manager().pay_for_offer(
                 &offer,
                 None,
                 Some(amount),
                 None,
                 payment_id,
-                Retry::Attempts(10),
-                None,
+                retry,
+                max_fee_msat,
             )
-            .map_err(|err| error::anyhow!("{:?}", err))?;
-        Ok(())
+        })?;
+        Ok(payment_id)
     }
 
-    pub fn pay_invoice(&self, invoice_str: &str, amount_msat: Option<u64>) -> error::Result<()> {
+    pub fn pay_invoice(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        options: &PayOptions,
+    ) -> error::Result<PaymentId> {
         // check if it is an invoice or an offer
         let invoice = self.decode_invoice(invoice_str)?;
         let payment_id = PaymentId((*invoice.payment_hash()).to_byte_array());
-        let (payment_hash, onion, route) = if invoice.amount_milli_satoshis().is_none() {
+        let (payment_hash, onion, mut route) = if invoice.amount_milli_satoshis().is_none() {
             ldk::invoice::payment::payment_parameters_from_zero_amount_invoice(
                 &invoice,
                 amount_msat.ok_or(error::anyhow!(
@@ -133,14 +485,45 @@ impl OffchainManager {
             ldk::invoice::payment::payment_parameters_from_invoice(&invoice)
                 .map_err(|err| error::anyhow!("{:?}", err))?
         };
-        self.channel_manager
-            .manager()
-            .send_payment(payment_hash, onion, payment_id, route, Retry::Attempts(10))
-            .map_err(|err| error::anyhow!("{:?}", err))?;
-        Ok(())
+        self.apply_pay_options(&mut route, options)?;
+        let retry = Self::retry_strategy(options, Retry::Attempts(10))?;
+        let record = PaymentRecord {
+            payment_id: hex::encode(payment_id.0),
+            payment_hash: Some(hex::encode(payment_hash.0)),
+            destination: Some(
+                invoice
+                    .payee_pub_key()
+                    .cloned()
+                    .unwrap_or_else(|| invoice.recover_payee_pub_key())
+                    .to_string(),
+            ),
+            invoice: Some(invoice_str.to_owned()),
+            amount_msat: Some(route.final_value_msat),
+            fee_msat: None,
+            status: PaymentStatus::Pending,
+            payment_preimage: None,
+            failure_reason: None,
+            created_at: Some(now()),
+            completed_at: None,
+        };
+        self.track_payment(payment_id, record, || {
+            self.channel_manager.manager().send_payment(
+                payment_hash,
+                onion,
+                payment_id,
+                route,
+                retry,
+            )
+        })?;
+        Ok(payment_id)
     }
 
-    pub fn keysend(&self, destination: pubkey, amount_msat: u64) -> error::Result<PaymentHash> {
+    pub fn keysend(
+        &self,
+        destination: pubkey,
+        amount_msat: u64,
+        options: &PayOptions,
+    ) -> error::Result<PaymentHash> {
         let payment_preimage = PaymentPreimage(
             self.chain_manager
                 .wallet_manager
@@ -151,26 +534,138 @@ impl OffchainManager {
         );
         let PaymentPreimage(bytes) = payment_preimage;
         let payment_hash = PaymentHash(Sha256::hash(&bytes).to_byte_array());
-        // The 40 here is the max CheckLockTimeVerify which locks the output of the transaction for a certain
-        // period of time.The false here stands for the allow_mpp, which is to allow the multi part route payments.
-        let route_params = RouteParameters {
-            payment_params: PaymentParameters::for_keysend(destination, 40, false),
+        // The final CLTV delta here is the max CheckLockTimeVerify which locks the output of the transaction for a certain
+        // period of time. The multi part payments are allowed only when the user asks for more than one part.
+        let final_cltv_expiry_delta =
+            Self::keysend_cltv_expiry_delta(self.lampo_conf.final_cltv_expiry_delta, options)?;
+        let allow_mpp = options.max_parts.map_or(false, |parts| parts > 1);
+        let mut route_params = RouteParameters {
+            payment_params: PaymentParameters::for_keysend(
+                destination,
+                final_cltv_expiry_delta,
+                allow_mpp,
+            ),
             final_value_msat: amount_msat,
             max_total_routing_fee_msat: None,
         };
+        self.apply_pay_options(&mut route_params, options)?;
+        let retry = Self::retry_strategy(options, Retry::Timeout(Duration::from_secs(10)))?;
         log::info!("Initialised Keysend");
-        let payment_result = self
-            .channel_manager
-            .manager()
-            .send_spontaneous_payment_with_retry(
-                Some(payment_preimage),
-                RecipientOnionFields::spontaneous_empty(),
-                PaymentId(payment_hash.0),
-                route_params,
-                Retry::Timeout(Duration::from_secs(10)),
-            )
-            .map_err(|err| error::anyhow!("{:?}", err))?;
+        let record = PaymentRecord {
+            payment_id: hex::encode(payment_hash.0),
+            payment_hash: Some(hex::encode(payment_hash.0)),
+            destination: Some(destination.to_string()),
+            invoice: None,
+            amount_msat: Some(amount_msat),
+            fee_msat: None,
+            status: PaymentStatus::Pending,
+            payment_preimage: None,
+            failure_reason: None,
+            created_at: Some(now()),
+            completed_at: None,
+        };
+        let payment_result = self.track_payment(PaymentId(payment_hash.0), record, || {
+            self.channel_manager
+                .manager()
+                .send_spontaneous_payment_with_retry(
+                    Some(payment_preimage),
+                    RecipientOnionFields::spontaneous_empty(),
+                    PaymentId(payment_hash.0),
+                    route_params,
+                    retry,
+                )
+        })?;
         log::info!("Keysend successfully done!");
         Ok(payment_result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lampo_common::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use lampo_common::ldk::routing::router::{PaymentParameters, RouteParameters};
+    use lampo_common::model::request::PayOptions;
+
+    use super::{OffchainManager, DEFAULT_FINAL_CLTV_EXPIRY_DELTA};
+
+    fn route_params(amount_msat: u64) -> RouteParameters {
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let destination = PublicKey::from_secret_key(&Secp256k1::new(), &secret);
+        RouteParameters {
+            payment_params: PaymentParameters::for_keysend(
+                destination,
+                DEFAULT_FINAL_CLTV_EXPIRY_DELTA,
+                true,
+            ),
+            final_value_msat: amount_msat,
+            max_total_routing_fee_msat: None,
+        }
+    }
+
+    #[test]
+    fn reject_zero_max_parts() {
+        let mut params = route_params(1000);
+        let options = PayOptions {
+            max_parts: Some(0),
+            ..PayOptions::default()
+        };
+        assert!(OffchainManager::apply_route_limits(&mut params, &options).is_err());
+
+        let options = PayOptions {
+            max_parts: Some(4),
+            ..PayOptions::default()
+        };
+        OffchainManager::apply_route_limits(&mut params, &options).unwrap();
+        assert_eq!(params.payment_params.max_path_count, 4);
+    }
+
+    #[test]
+    fn max_fee_from_msat_or_percent() {
+        let options = PayOptions {
+            maxfee_msat: Some(50),
+            ..PayOptions::default()
+        };
+        let fee = OffchainManager::max_fee_msat(10_000, &options).unwrap();
+        assert_eq!(fee, Some(50));
+
+        let options = PayOptions {
+            maxfeepercent: Some(1.5),
+            ..PayOptions::default()
+        };
+        let fee = OffchainManager::max_fee_msat(10_000, &options).unwrap();
+        assert_eq!(fee, Some(150));
+
+        let options = PayOptions {
+            maxfee_msat: Some(50),
+            maxfeepercent: Some(1.5),
+            ..PayOptions::default()
+        };
+        assert!(OffchainManager::max_fee_msat(10_000, &options).is_err());
+
+        let options = PayOptions {
+            maxfeepercent: Some(101.0),
+            ..PayOptions::default()
+        };
+        assert!(OffchainManager::max_fee_msat(10_000, &options).is_err());
+
+        let fee = OffchainManager::max_fee_msat(10_000, &PayOptions::default()).unwrap();
+        assert_eq!(fee, None);
+    }
+
+    #[test]
+    fn keysend_cltv_fits_the_max_cltv_expiry() {
+        let options = PayOptions::default();
+        let delta = OffchainManager::keysend_cltv_expiry_delta(None, &options).unwrap();
+        assert_eq!(delta, DEFAULT_FINAL_CLTV_EXPIRY_DELTA);
+        let delta = OffchainManager::keysend_cltv_expiry_delta(Some(80), &options).unwrap();
+        assert_eq!(delta, 80);
+
+        let options = PayOptions {
+            max_cltv_expiry: Some(60),
+            ..PayOptions::default()
+        };
+        assert!(OffchainManager::keysend_cltv_expiry_delta(Some(80), &options).is_err());
+        let delta = OffchainManager::keysend_cltv_expiry_delta(None, &options).unwrap();
+        assert_eq!(delta, DEFAULT_FINAL_CLTV_EXPIRY_DELTA);
+    }
+}
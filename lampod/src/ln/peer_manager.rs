@@ -1,15 +1,22 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::thread::JoinHandle;
 use std::time::SystemTime;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
 use lampo_common::ldk;
 use lampo_common::ldk::blinded_path::EmptyNodeIdLookUp;
+use lampo_common::ldk::ln::features::{InitFeatures, NodeFeatures};
+use lampo_common::ldk::ln::msgs::{Init, OnionMessage, OnionMessageHandler};
 use lampo_common::ldk::ln::peer_handler::MessageHandler;
 use lampo_common::ldk::ln::peer_handler::{IgnoringMessageHandler, PeerManager};
 use lampo_common::ldk::net;
@@ -17,12 +24,14 @@ use lampo_common::ldk::net::SocketDescriptor;
 use lampo_common::ldk::onion_message::messenger::OnionMessenger;
 use lampo_common::ldk::routing::gossip::P2PGossipSync;
 use lampo_common::ldk::sign::KeysManager;
+use lampo_common::model::response::{Peer, Peers};
 use lampo_common::model::Connect;
 use lampo_common::types::NodeId;
 
 use crate::async_run;
 use crate::chain::{LampoChainManager, WalletManager};
 use crate::ln::LampoChannelManager;
+use crate::persistence::PeerStore;
 use crate::utils::logger::LampoLogger;
 
 use super::channel_manager::{LampoArcChannelManager, LampoChainMonitor, LampoGraph};
@@ -44,7 +53,7 @@ pub type SimpleArcPeerManager<M, T, L> = PeerManager<
     SocketDescriptor,
     Arc<LampoArcChannelManager<M, T, T, L>>,
     Arc<P2PGossipSync<Arc<LampoGraph>, Arc<T>, Arc<L>>>,
-    Arc<LampoArcOnionMessenger<L>>,
+    Arc<LampoPeerHandler>,
     Arc<L>,
     IgnoringMessageHandler,
     Arc<KeysManager>,
@@ -53,19 +62,85 @@ pub type SimpleArcPeerManager<M, T, L> = PeerManager<
 type InnerLampoPeerManager =
     SimpleArcPeerManager<LampoChainMonitor, LampoChainManager, LampoLogger>;
 
+/// The onion message handler given to LDK, that also emits the
+/// peer events because LDK tells every message handler when the
+/// connection with a peer is established or dropped.
+pub struct LampoPeerHandler {
+    onion_messenger: Arc<LampoArcOnionMessenger<LampoLogger>>,
+    channel_manager: Arc<LampoChannelManager>,
+}
+
+impl LampoPeerHandler {
+    fn emit(&self, event: LightningEvent) {
+        self.channel_manager.handler().emit(Event::Lightning(event));
+    }
+}
+
+impl OnionMessageHandler for LampoPeerHandler {
+    fn handle_onion_message(&self, peer_node_id: &NodeId, msg: &OnionMessage) {
+        self.onion_messenger.handle_onion_message(peer_node_id, msg)
+    }
+
+    fn next_onion_message_for_peer(&self, peer_node_id: NodeId) -> Option<OnionMessage> {
+        self.onion_messenger
+            .next_onion_message_for_peer(peer_node_id)
+    }
+
+    fn peer_connected(&self, their_node_id: &NodeId, init: &Init, inbound: bool) -> Result<(), ()> {
+        self.onion_messenger
+            .peer_connected(their_node_id, init, inbound)?;
+        log::info!(target: "lampo", "peer `{their_node_id}` connected");
+        self.emit(LightningEvent::PeerConnect {
+            counterparty_node_id: *their_node_id,
+        });
+        Ok(())
+    }
+
+    fn peer_disconnected(&self, their_node_id: &NodeId) {
+        self.onion_messenger.peer_disconnected(their_node_id);
+        log::info!(target: "lampo", "peer `{their_node_id}` disconnected");
+        self.emit(LightningEvent::PeerDisconnect {
+            counterparty_node_id: *their_node_id,
+        });
+    }
+
+    fn timer_tick_occurred(&self) {
+        self.onion_messenger.timer_tick_occurred()
+    }
+
+    fn provided_node_features(&self) -> NodeFeatures {
+        self.onion_messenger.provided_node_features()
+    }
+
+    fn provided_init_features(&self, their_node_id: &NodeId) -> InitFeatures {
+        self.onion_messenger.provided_init_features(their_node_id)
+    }
+}
+
+/// Delay before the first attempt to reconnect with a peer, it
+/// is doubled at every attempt up to `MAX_RECONNECT_BACKOFF`.
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(600);
+
 pub struct LampoPeerManager {
     peer_manager: Option<Arc<InnerLampoPeerManager>>,
     channel_manager: Option<Arc<LampoChannelManager>>,
+    peers: Arc<PeerStore>,
     conf: LampoConf,
     logger: Arc<LampoLogger>,
 }
 
 impl LampoPeerManager {
-    pub fn new(conf: &LampoConf, logger: Arc<LampoLogger>) -> LampoPeerManager {
+    pub fn new(
+        conf: &LampoConf,
+        logger: Arc<LampoLogger>,
+        peers: Arc<PeerStore>,
+    ) -> LampoPeerManager {
         LampoPeerManager {
             peer_manager: None,
             conf: conf.to_owned(),
             logger,
+            peers,
             channel_manager: None,
         }
     }
@@ -105,9 +180,16 @@ impl LampoPeerManager {
             self.logger.clone(),
         ));
 
+        // Nb. the handler of the channel manager is set after
+        // the init, but before the node accepts any connection.
+        let peer_handler = Arc::new(LampoPeerHandler {
+            onion_messenger,
+            channel_manager: channel_manager.clone(),
+        });
+
         let lightning_msg_handler = MessageHandler {
             chan_handler: channel_manager.channeld.clone().unwrap(),
-            onion_message_handler: onion_messenger,
+            onion_message_handler: peer_handler,
             route_handler: gossip_sync,
             custom_message_handler: IgnoringMessageHandler {},
         };
@@ -215,6 +297,105 @@ impl LampoPeerManager {
         };
         manager.peer_by_node_id(&peer_id).is_some()
     }
+
+    /// Reconnect with the peers that we have channels with, and keep
+    /// retrying the dropped connections with an exponential backoff.
+    pub fn reconnect(self: Arc<Self>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            async_run!(async move {
+                let mut backoff: HashMap<NodeId, (Instant, Duration)> = HashMap::new();
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                loop {
+                    interval.tick().await;
+                    let peers = match self.peers_to_reconnect() {
+                        Ok(peers) => peers,
+                        Err(err) => {
+                            log::error!(target: "lampo", "impossible load the peers: {err}");
+                            continue;
+                        }
+                    };
+                    for (node_id, addr) in peers {
+                        if self.is_connected_with(node_id) {
+                            backoff.remove(&node_id);
+                            continue;
+                        }
+                        let now = Instant::now();
+                        if backoff.get(&node_id).map_or(false, |(next, _)| *next > now) {
+                            continue;
+                        }
+                        // Nb. the connection can also be dropped right after a
+                        // success, so the backoff is reset only when the peer
+                        // is found connected.
+                        let delay = backoff
+                            .get(&node_id)
+                            .map_or(MIN_RECONNECT_BACKOFF, |(_, delay)| {
+                                (*delay * 2).min(MAX_RECONNECT_BACKOFF)
+                            });
+                        backoff.insert(node_id, (now + delay, delay));
+                        log::info!(target: "lampo", "reconnecting with peer `{node_id}` at `{addr}`");
+                        if let Err(err) = self.connect(node_id, addr).await {
+                            log::warn!(target: "lampo", "impossible reconnect with `{node_id}`, retrying in {}s: {err}", delay.as_secs());
+                        }
+                    }
+                }
+            })
+        })
+    }
+
+    /// Return the known address of the peers that we have channels with,
+    /// except the ones disconnected on purpose.
+    fn peers_to_reconnect(&self) -> error::Result<Vec<(NodeId, SocketAddr)>> {
+        let channels = self
+            .channel_manager
+            .as_ref()
+            .ok_or(error::anyhow!("channel manager is None"))?
+            .manager()
+            .list_channels();
+        let peers = self
+            .peers
+            .list_peers()?
+            .into_iter()
+            .filter(|peer| !peer.disconnected)
+            .filter(|peer| {
+                channels
+                    .iter()
+                    .any(|channel| channel.counterparty.node_id == peer.node_id)
+            })
+            .map(|peer| (peer.node_id, peer.address))
+            .collect();
+        Ok(peers)
+    }
+
+    /// List the connected peers, with the channels open with them.
+    pub fn list_peers(&self, peer_id: Option<NodeId>) -> error::Result<Peers> {
+        let channels = self
+            .channel_manager
+            .as_ref()
+            .ok_or(error::anyhow!("channel manager is None"))?
+            .list_channel()
+            .channels;
+        let peers = self
+            .manager()
+            .list_peers()
+            .into_iter()
+            .filter(|peer| peer_id.map_or(true, |id| peer.counterparty_node_id == id))
+            .map(|peer| {
+                let node_id = peer.counterparty_node_id.to_string();
+                Peer {
+                    channels: channels
+                        .iter()
+                        .filter(|channel| channel.peer_id == node_id)
+                        .cloned()
+                        .collect(),
+                    node_id,
+                    address: peer.socket_address.map(|addr| addr.to_string()),
+                    features: peer.init_features.to_string(),
+                    inbound: peer.is_inbound_connection,
+                }
+            })
+            .collect();
+        Ok(Peers { peers })
+    }
 }
 
 #[async_trait]
@@ -251,7 +432,13 @@ impl PeerEvents for LampoPeerManager {
             }
             // Avoid blocking the tokio context by sleeping a bit
             match manager.peer_by_node_id(&node_id) {
-                Some(_) => return Ok(()),
+                Some(_) => {
+                    // store the address to reconnect after a restart.
+                    if let Err(err) = self.peers.add_peer(&node_id, &host) {
+                        log::error!("impossible store the address of `{node_id}`: {err}");
+                    }
+                    return Ok(());
+                }
                 None => tokio::time::sleep(Duration::from_millis(10)).await,
             }
         }
@@ -263,6 +450,9 @@ impl PeerEvents for LampoPeerManager {
             error::bail!("Error: Could not find peer `{node_id}`");
         }
 
+        // Nb. mark the peer before disconnecting, so the reconnect
+        // loop does not dial it again in between.
+        self.peers.peer_disconnected(&node_id)?;
         self.manager().disconnect_by_node_id(node_id);
         Ok(())
     }
@@ -0,0 +1,109 @@
+//! Rapid Gossip Sync implementation.
+//!
+//! A fresh node has an empty network graph, and it is not able
+//! to route until the P2P gossip catches up. The Rapid Gossip Sync
+//! snapshot allows to bootstrap the graph in one shot, and then
+//! the snapshot is refreshed periodically.
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lampo_common::error;
+use lampo_common::ldk::rapid_gossip_sync::RapidGossipSync;
+
+use crate::ln::channel_manager::LampoGraph;
+use crate::utils::logger::LampoLogger;
+
+pub type LampoRapidGossipSync = RapidGossipSync<Arc<LampoGraph>, Arc<LampoLogger>>;
+
+/// How often the snapshot is refreshed when not specified.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub struct LampoRapidSync {
+    rapid_sync: Arc<LampoRapidGossipSync>,
+    graph: Arc<LampoGraph>,
+    /// A local file or the URL of a server.
+    source: String,
+    interval: Duration,
+}
+
+impl LampoRapidSync {
+    pub fn new(
+        graph: Arc<LampoGraph>,
+        logger: Arc<LampoLogger>,
+        source: String,
+        interval: Option<u64>,
+    ) -> Self {
+        Self {
+            rapid_sync: Arc::new(RapidGossipSync::new(graph.clone(), logger)),
+            graph,
+            source,
+            interval: interval
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_REFRESH_INTERVAL),
+        }
+    }
+
+    pub fn rapid_sync(&self) -> Arc<LampoRapidGossipSync> {
+        self.rapid_sync.clone()
+    }
+
+    /// Apply the last snapshot to the network graph, and return
+    /// the timestamp of the snapshot.
+    pub fn sync(&self) -> error::Result<u32> {
+        let snapshot = self.fetch()?;
+        let timestamp = self
+            .rapid_sync
+            .update_network_graph(&snapshot)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        log::info!(target: "lampo", "network graph synced with rapid gossip sync at `{timestamp}`");
+        Ok(timestamp)
+    }
+
+    /// Return true if the snapshot is fetched from a server, a local
+    /// file is not refreshed, so it is applied only once.
+    fn is_remote(&self) -> bool {
+        self.source.starts_with("http://") || self.source.starts_with("https://")
+    }
+
+    fn fetch(&self) -> error::Result<Vec<u8>> {
+        if !self.is_remote() {
+            return Ok(std::fs::read(&self.source)?);
+        }
+        // The server returns only the updates after the last sync.
+        let timestamp = self
+            .graph
+            .get_last_rapid_gossip_sync_timestamp()
+            .unwrap_or(0);
+        let url = format!("{}/{timestamp}", self.source.trim_end_matches('/'));
+        log::debug!(target: "lampo", "fetching rapid gossip sync snapshot from `{url}`");
+        let response = minreq::get(&url).with_timeout(60).send()?;
+        if response.status_code != 200 {
+            error::bail!(
+                "impossible to fetch the snapshot from `{url}`: {} {}",
+                response.status_code,
+                response.reason_phrase
+            );
+        }
+        Ok(response.into_bytes())
+    }
+
+    /// Bootstrap the network graph, and then refresh the snapshot
+    /// periodically, only for a remote source.
+    ///
+    /// Nb. the first sync runs on the thread too, so the node does
+    /// not wait for the server to start.
+    pub fn listen(self: Arc<Self>) -> JoinHandle<()> {
+        std::thread::spawn(move || loop {
+            // the node can run also when the snapshot is not available,
+            // the graph will be synced by the P2P gossip.
+            if let Err(err) = self.sync() {
+                log::error!(target: "lampo", "rapid gossip sync failed: {err}");
+            }
+            if !self.is_remote() {
+                return;
+            }
+            std::thread::sleep(self.interval);
+        })
+    }
+}
@@ -91,6 +91,11 @@ where
             message_router,
         })
     }
+
+    /// The parameters used by the scorer to penalize the channels.
+    pub fn score_params(&self) -> &SP {
+        &self.score_params
+    }
 }
 
 impl<
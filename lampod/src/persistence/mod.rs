@@ -10,3 +10,9 @@ use lampo_common::ldk::persister::fs_store::FilesystemStore;
 // giving more time to understand how to make a custom one without
 // lost funds :-P
 pub type LampoPersistence = FilesystemStore;
+
+pub(crate) mod payments;
+pub(crate) mod peers;
+
+pub use payments::PaymentStore;
+pub use peers::PeerStore;
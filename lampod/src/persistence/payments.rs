@@ -0,0 +1,447 @@
+//! Payment store implementation.
+//!
+//! Keep track of the invoices issued by the node, of the
+//! payments sent and of the payments forwarded, so they survive
+//! a restart and can be listed by the user. Each record is stored as JSON inside the
+//! `payments` namespace of the lampo persistence.
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::error;
+use lampo_common::json;
+use lampo_common::ldk;
+use lampo_common::ldk::ln::channelmanager::PaymentId;
+use lampo_common::ldk::ln::{PaymentHash, PaymentPreimage};
+use lampo_common::ldk::util::persist::KVStore;
+use lampo_common::model::response::{
+    ForwardRecord, InvoiceRecord, InvoiceStatus, PaymentRecord, PaymentStatus, SendPayRecord,
+};
+
+use super::LampoPersistence;
+
+const NAMESPACE: &str = "payments";
+const INVOICES: &str = "invoices";
+const PAYMENTS: &str = "sent";
+const SENDPAYS: &str = "sendpays";
+const FORWARDS: &str = "forwards";
+
+pub struct PaymentStore {
+    store: Arc<LampoPersistence>,
+    // Nb. the updates are read-modify-write operations, so
+    // we serialize them to not lose any of them. The lock also
+    // keeps the labels of the invoices, that are loaded the first
+    // time that a label is reserved.
+    lock: Mutex<Option<HashSet<String>>>,
+}
+
+impl PaymentStore {
+    pub fn new(store: Arc<LampoPersistence>) -> Self {
+        Self {
+            store,
+            lock: Mutex::new(None),
+        }
+    }
+
+    /// Store an invoice just issued by the node, the label
+    /// must be reserved with `reserve_label` before creating it.
+    pub fn add_invoice(
+        &self,
+        invoice: &ldk::invoice::Bolt11Invoice,
+        label: Option<String>,
+    ) -> error::Result<InvoiceRecord> {
+        let _guard = self.lock.lock().unwrap();
+        let created_at = invoice.duration_since_epoch().as_secs();
+        let record = InvoiceRecord {
+            label,
+            bolt11: invoice.to_string(),
+            payment_hash: hex::encode(invoice.payment_hash().to_byte_array()),
+            description: match invoice.description() {
+                ldk::invoice::Bolt11InvoiceDescription::Direct(description) => {
+                    Some(description.to_string())
+                }
+                ldk::invoice::Bolt11InvoiceDescription::Hash(_) => None,
+            },
+            amount_msat: invoice.amount_milli_satoshis(),
+            status: InvoiceStatus::Unpaid,
+            amount_received_msat: None,
+            payment_preimage: None,
+            created_at,
+            expires_at: created_at + invoice.expiry_time().as_secs(),
+            paid_at: None,
+        };
+        self.write(INVOICES, &record.payment_hash, &record)?;
+        Ok(record)
+    }
+
+    /// Reserve the label for an invoice that is going to be created,
+    /// so two invoices can not be created with the same label.
+    pub fn reserve_label(&self, label: &str) -> error::Result<()> {
+        let mut labels = self.lock.lock().unwrap();
+        if labels.is_none() {
+            let invoices = self.list::<InvoiceRecord>(INVOICES)?;
+            *labels = Some(
+                invoices
+                    .into_iter()
+                    .filter_map(|invoice| invoice.label)
+                    .collect(),
+            );
+        }
+        // SAFETY: the labels are loaded above.
+        if !labels.as_mut().unwrap().insert(label.to_owned()) {
+            error::bail!("an invoice with label `{label}` already exists");
+        }
+        Ok(())
+    }
+
+    /// Release the label reserved for an invoice that was not created.
+    pub fn release_label(&self, label: &str) {
+        if let Some(labels) = self.lock.lock().unwrap().as_mut() {
+            labels.remove(label);
+        }
+    }
+
+    /// Mark the invoice as paid, when the payment is claimed.
+    pub fn invoice_paid(
+        &self,
+        payment_hash: &PaymentHash,
+        amount_msat: u64,
+        preimage: Option<PaymentPreimage>,
+    ) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let payment_hash = hex::encode(payment_hash.0);
+        let Some(mut record) = self.read::<InvoiceRecord>(INVOICES, &payment_hash)? else {
+            // e.g. a keysend payment.
+            log::debug!("payment `{payment_hash}` claimed without an invoice");
+            return Ok(());
+        };
+        record.status = InvoiceStatus::Paid;
+        record.amount_received_msat = Some(amount_msat);
+        record.payment_preimage = preimage.map(|preimage| hex::encode(preimage.0));
+        record.paid_at = Some(now());
+        self.write(INVOICES, &payment_hash, &record)
+    }
+
+    pub fn list_invoices(&self) -> error::Result<Vec<InvoiceRecord>> {
+        let now = now();
+        let mut invoices = self.list::<InvoiceRecord>(INVOICES)?;
+        for invoice in invoices.iter_mut() {
+            if invoice.status == InvoiceStatus::Unpaid && invoice.expires_at <= now {
+                invoice.status = InvoiceStatus::Expired;
+            }
+        }
+        invoices.sort_by_key(|invoice| invoice.created_at);
+        Ok(invoices)
+    }
+
+    /// Store a payment that is just started, and return the record
+    /// of the previous attempt to pay it.
+    ///
+    /// Only a failed payment can be retried, a payment that is pending
+    /// or complete is never overwritten.
+    pub fn add_payment(&self, record: PaymentRecord) -> error::Result<Option<PaymentRecord>> {
+        let _guard = self.lock.lock().unwrap();
+        let previous = self.read::<PaymentRecord>(PAYMENTS, &record.payment_id)?;
+        match previous {
+            Some(PaymentRecord {
+                status: PaymentStatus::Pending,
+                ..
+            }) => error::bail!("payment `{}` is already pending", record.payment_id),
+            Some(PaymentRecord {
+                status: PaymentStatus::Complete,
+                ..
+            }) => error::bail!("payment `{}` is already complete", record.payment_id),
+            _ => {}
+        }
+        self.write(PAYMENTS, &record.payment_id, &record)?;
+        Ok(previous)
+    }
+
+    /// Put back the record of the payment as it was before `add_payment`,
+    /// when the payment is not started.
+    pub fn restore_payment(
+        &self,
+        payment_id: &PaymentId,
+        previous: Option<PaymentRecord>,
+    ) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let key = hex::encode(payment_id.0);
+        match previous {
+            Some(record) => self.write(PAYMENTS, &key, &record),
+            None => Ok(self.store.remove(NAMESPACE, PAYMENTS, &key, false)?),
+        }
+    }
+
+    pub fn payment_sent(
+        &self,
+        payment_id: &PaymentId,
+        preimage: &PaymentPreimage,
+        fee_msat: Option<u64>,
+    ) -> error::Result<()> {
+        self.update_payment(payment_id, |record| {
+            record.status = PaymentStatus::Complete;
+            record.payment_preimage = Some(hex::encode(preimage.0));
+            record.fee_msat = fee_msat;
+            record.completed_at = Some(now());
+        })
+    }
+
+    pub fn payment_failed(&self, payment_id: &PaymentId, reason: String) -> error::Result<()> {
+        self.update_payment(payment_id, |record| {
+            record.status = PaymentStatus::Failed;
+            record.failure_reason = Some(reason);
+            record.completed_at = Some(now());
+        })
+    }
+
+    fn update_payment<F>(&self, payment_id: &PaymentId, update: F) -> error::Result<()>
+    where
+        F: FnOnce(&mut PaymentRecord),
+    {
+        let _guard = self.lock.lock().unwrap();
+        let payment_id = hex::encode(payment_id.0);
+        let Some(mut record) = self.read::<PaymentRecord>(PAYMENTS, &payment_id)? else {
+            log::warn!("payment `{payment_id}` not found inside the payment store");
+            return Ok(());
+        };
+        update(&mut record);
+        self.write(PAYMENTS, &payment_id, &record)
+    }
+
+    pub fn payment(&self, payment_id: &PaymentId) -> error::Result<Option<PaymentRecord>> {
+        self.read(PAYMENTS, &hex::encode(payment_id.0))
+    }
+
+    pub fn list_payments(&self) -> error::Result<Vec<PaymentRecord>> {
+        let mut payments = self.list::<PaymentRecord>(PAYMENTS)?;
+        payments.sort_by_key(|payment| payment.created_at);
+        Ok(payments)
+    }
+
+    /// Store an attempt to deliver the payment, all the
+    /// attempts of a payment are stored under the same key.
+    pub fn add_sendpay(&self, record: SendPayRecord) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut sendpays = self
+            .read::<Vec<SendPayRecord>>(SENDPAYS, &record.payment_id)?
+            .unwrap_or_default();
+        let key = record.payment_id.clone();
+        sendpays.push(record);
+        self.write(SENDPAYS, &key, &sendpays)
+    }
+
+    pub fn sendpays(&self, payment_id: &PaymentId) -> error::Result<Vec<SendPayRecord>> {
+        Ok(self
+            .read::<Vec<SendPayRecord>>(SENDPAYS, &hex::encode(payment_id.0))?
+            .unwrap_or_default())
+    }
+
+    /// Return the last attempt of the payment that failed.
+    pub fn last_failed_sendpay(
+        &self,
+        payment_id: &PaymentId,
+    ) -> error::Result<Option<SendPayRecord>> {
+        Ok(self
+            .sendpays(payment_id)?
+            .into_iter()
+            .rev()
+            .find(|sendpay| sendpay.status == PaymentStatus::Failed))
+    }
+
+    pub fn list_sendpays(&self) -> error::Result<Vec<SendPayRecord>> {
+        let mut sendpays = self
+            .list::<Vec<SendPayRecord>>(SENDPAYS)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        sendpays.sort_by_key(|sendpay| sendpay.created_at);
+        Ok(sendpays)
+    }
+
+    /// Store a forward once it is resolved.
+    pub fn add_forward(&self, record: ForwardRecord) -> error::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        // the forwards do not have an id, so we use the time
+        // when they are stored.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        self.write(FORWARDS, &format!("{nanos:032x}"), &record)
+    }
+
+    pub fn list_forwards(&self) -> error::Result<Vec<ForwardRecord>> {
+        let mut forwards = self.list::<ForwardRecord>(FORWARDS)?;
+        forwards.sort_by_key(|forward| forward.resolved_at);
+        Ok(forwards)
+    }
+
+    fn read<T: json::DeserializeOwned>(&self, kind: &str, key: &str) -> error::Result<Option<T>> {
+        match self.store.read(NAMESPACE, kind, key) {
+            Ok(buff) => Ok(Some(json::from_slice(&buff)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write<T: json::Serialize>(&self, kind: &str, key: &str, value: &T) -> error::Result<()> {
+        let buff = json::to_vec(value)?;
+        self.store.write(NAMESPACE, kind, key, &buff)?;
+        Ok(())
+    }
+
+    fn list<T: json::DeserializeOwned>(&self, kind: &str) -> error::Result<Vec<T>> {
+        let keys = match self.store.list(NAMESPACE, kind) {
+            Ok(keys) => keys,
+            // nothing was stored yet.
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut values = Vec::new();
+        for key in keys {
+            if let Some(value) = self.read(kind, &key)? {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Seconds since the unix epoch.
+pub(crate) fn now() -> u64 {
+    // SAFETY: the clock is after the unix epoch.
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lampo_common::ldk::ln::channelmanager::PaymentId;
+    use lampo_common::ldk::ln::PaymentPreimage;
+    use lampo_common::model::response::{
+        InvoiceRecord, InvoiceStatus, PaymentRecord, PaymentStatus,
+    };
+
+    use super::{PaymentStore, INVOICES};
+    use crate::persistence::LampoPersistence;
+
+    fn store(name: &str) -> PaymentStore {
+        let path = std::env::temp_dir().join(format!("lampo-payments-{name}-{}", super::now()));
+        let _ = std::fs::remove_dir_all(&path);
+        PaymentStore::new(Arc::new(LampoPersistence::new(path)))
+    }
+
+    fn payment(payment_id: &PaymentId) -> PaymentRecord {
+        PaymentRecord {
+            payment_id: hex::encode(payment_id.0),
+            payment_hash: Some(hex::encode(payment_id.0)),
+            destination: None,
+            invoice: None,
+            amount_msat: Some(1000),
+            fee_msat: None,
+            status: PaymentStatus::Pending,
+            payment_preimage: None,
+            failure_reason: None,
+            created_at: Some(super::now()),
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn update_the_payment_status() {
+        let store = store("update");
+        let payment_id = PaymentId([1; 32]);
+        assert!(store.add_payment(payment(&payment_id)).unwrap().is_none());
+        store
+            .payment_sent(&payment_id, &PaymentPreimage([2; 32]), Some(10))
+            .unwrap();
+        let record = store.payment(&payment_id).unwrap().unwrap();
+        assert_eq!(record.status, PaymentStatus::Complete);
+        assert_eq!(record.payment_preimage, Some(hex::encode([2; 32])));
+        assert_eq!(record.fee_msat, Some(10));
+        assert!(record.completed_at.is_some());
+        assert_eq!(store.list_payments().unwrap().len(), 1);
+        // a payment that is not stored is ignored.
+        store
+            .payment_failed(&PaymentId([3; 32]), "unknown".to_owned())
+            .unwrap();
+        assert!(store.payment(&PaymentId([3; 32])).unwrap().is_none());
+    }
+
+    #[test]
+    fn do_not_overwrite_a_pending_or_complete_payment() {
+        let store = store("overwrite");
+        let payment_id = PaymentId([1; 32]);
+        store.add_payment(payment(&payment_id)).unwrap();
+        assert!(store.add_payment(payment(&payment_id)).is_err());
+
+        store
+            .payment_sent(&payment_id, &PaymentPreimage([2; 32]), None)
+            .unwrap();
+        assert!(store.add_payment(payment(&payment_id)).is_err());
+        let record = store.payment(&payment_id).unwrap().unwrap();
+        assert_eq!(record.status, PaymentStatus::Complete);
+    }
+
+    #[test]
+    fn retry_a_failed_payment() {
+        let store = store("retry");
+        let payment_id = PaymentId([1; 32]);
+        store.add_payment(payment(&payment_id)).unwrap();
+        store
+            .payment_failed(&payment_id, "no route".to_owned())
+            .unwrap();
+
+        let previous = store.add_payment(payment(&payment_id)).unwrap().unwrap();
+        assert_eq!(previous.status, PaymentStatus::Failed);
+        assert_eq!(previous.failure_reason, Some("no route".to_owned()));
+        let record = store.payment(&payment_id).unwrap().unwrap();
+        assert_eq!(record.status, PaymentStatus::Pending);
+
+        // the attempt is not started, so the failure is restored.
+        store.restore_payment(&payment_id, Some(previous)).unwrap();
+        let record = store.payment(&payment_id).unwrap().unwrap();
+        assert_eq!(record.status, PaymentStatus::Failed);
+
+        let payment_id = PaymentId([4; 32]);
+        store.add_payment(payment(&payment_id)).unwrap();
+        store.restore_payment(&payment_id, None).unwrap();
+        assert!(store.payment(&payment_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn check_the_invoice_label() {
+        let store = store("label");
+        let record = InvoiceRecord {
+            label: Some("coffee".to_owned()),
+            bolt11: "lnbcrt1".to_owned(),
+            payment_hash: hex::encode([1; 32]),
+            description: None,
+            amount_msat: None,
+            status: InvoiceStatus::Unpaid,
+            amount_received_msat: None,
+            payment_preimage: None,
+            created_at: 0,
+            expires_at: 0,
+            paid_at: None,
+        };
+        store
+            .write(INVOICES, &record.payment_hash, &record)
+            .unwrap();
+        assert!(store.reserve_label("coffee").is_err());
+        assert!(store.reserve_label("tea").is_ok());
+        // the label is reserved until it is released.
+        assert!(store.reserve_label("tea").is_err());
+        store.release_label("tea");
+        assert!(store.reserve_label("tea").is_ok());
+        // the expired invoices are reported as expired.
+        let invoices = store.list_invoices().unwrap();
+        assert_eq!(invoices[0].status, InvoiceStatus::Expired);
+    }
+}
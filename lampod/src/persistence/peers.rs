@@ -0,0 +1,176 @@
+//! Peer store implementation.
+//!
+//! Keep track of the address of the peers that we connected with, so
+//! we can reconnect with them after a restart. Each peer is stored as JSON
+//! inside the `peers` namespace of the lampo persistence, and cached
+//! in memory after the first read.
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use lampo_common::error;
+use lampo_common::json;
+use lampo_common::json::prelude::serde::{Deserialize, Serialize};
+use lampo_common::ldk::util::persist::KVStore;
+use lampo_common::types::NodeId;
+
+use super::payments::now;
+use super::LampoPersistence;
+
+const NAMESPACE: &str = "peers";
+const ADDRESSES: &str = "addresses";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "lampo_common::json::prelude::serde")]
+pub struct PeerRecord {
+    pub node_id: NodeId,
+    pub address: SocketAddr,
+    pub connected_at: u64,
+    /// The peer was disconnected on purpose, so we do not
+    /// reconnect with it until the next explicit `connect`.
+    #[serde(default)]
+    pub disconnected: bool,
+}
+
+pub struct PeerStore {
+    store: Arc<LampoPersistence>,
+    /// The records inside the store, loaded at the first access.
+    peers: Mutex<Option<HashMap<NodeId, PeerRecord>>>,
+}
+
+impl PeerStore {
+    pub fn new(store: Arc<LampoPersistence>) -> Self {
+        Self {
+            store,
+            peers: Mutex::new(None),
+        }
+    }
+
+    /// Store the address of a peer that we just connected with.
+    pub fn add_peer(&self, node_id: &NodeId, address: &SocketAddr) -> error::Result<()> {
+        let record = PeerRecord {
+            node_id: *node_id,
+            address: *address,
+            connected_at: now(),
+            disconnected: false,
+        };
+        self.write(record)
+    }
+
+    /// Remember that the peer was disconnected on purpose.
+    pub fn peer_disconnected(&self, node_id: &NodeId) -> error::Result<()> {
+        let Some(mut record) = self.peer(node_id)? else {
+            return Ok(());
+        };
+        if record.disconnected {
+            return Ok(());
+        }
+        record.disconnected = true;
+        self.write(record)
+    }
+
+    pub fn peer(&self, node_id: &NodeId) -> error::Result<Option<PeerRecord>> {
+        self.with_peers(|peers| peers.get(node_id).cloned())
+    }
+
+    pub fn list_peers(&self) -> error::Result<Vec<PeerRecord>> {
+        self.with_peers(|peers| peers.values().cloned().collect())
+    }
+
+    fn write(&self, record: PeerRecord) -> error::Result<()> {
+        let buff = json::to_vec(&record)?;
+        let mut peers = self.peers.lock().unwrap();
+        self.store
+            .write(NAMESPACE, ADDRESSES, &record.node_id.to_string(), &buff)?;
+        if let Some(peers) = peers.as_mut() {
+            peers.insert(record.node_id, record);
+        }
+        Ok(())
+    }
+
+    fn with_peers<T>(&self, f: impl FnOnce(&HashMap<NodeId, PeerRecord>) -> T) -> error::Result<T> {
+        let mut peers = self.peers.lock().unwrap();
+        if peers.is_none() {
+            *peers = Some(self.read_peers()?);
+        }
+        // SAFETY: the cache is loaded above.
+        Ok(f(peers.as_ref().unwrap()))
+    }
+
+    fn read_peers(&self) -> error::Result<HashMap<NodeId, PeerRecord>> {
+        let keys = match self.store.list(NAMESPACE, ADDRESSES) {
+            Ok(keys) => keys,
+            // nothing was stored yet.
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut peers = HashMap::new();
+        for key in keys {
+            let buff = self.store.read(NAMESPACE, ADDRESSES, &key)?;
+            let record: PeerRecord = json::from_slice(&buff)?;
+            peers.insert(record.node_id, record);
+        }
+        Ok(peers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use lampo_common::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::PeerStore;
+    use crate::persistence::LampoPersistence;
+
+    fn store(name: &str) -> Arc<LampoPersistence> {
+        let path = std::env::temp_dir().join(format!("lampo-peers-{name}-{}", super::now()));
+        let _ = std::fs::remove_dir_all(&path);
+        Arc::new(LampoPersistence::new(path))
+    }
+
+    fn node_id(byte: u8) -> PublicKey {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::from_secret_key(&Secp256k1::new(), &secret)
+    }
+
+    #[test]
+    fn persist_the_peers() {
+        let store = store("persist");
+        let peers = PeerStore::new(store.clone());
+        assert!(peers.list_peers().unwrap().is_empty());
+
+        let address = SocketAddr::from_str("127.0.0.1:9735").unwrap();
+        peers.add_peer(&node_id(1), &address).unwrap();
+        peers.add_peer(&node_id(2), &address).unwrap();
+        assert_eq!(peers.list_peers().unwrap().len(), 2);
+
+        // the records survive a restart.
+        let peers = PeerStore::new(store);
+        let record = peers.peer(&node_id(1)).unwrap().unwrap();
+        assert_eq!(record.address, address);
+        assert!(!record.disconnected);
+        assert_eq!(peers.list_peers().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn remember_the_disconnected_peers() {
+        let store = store("disconnected");
+        let peers = PeerStore::new(store.clone());
+        let address = SocketAddr::from_str("127.0.0.1:9735").unwrap();
+        peers.add_peer(&node_id(1), &address).unwrap();
+        peers.peer_disconnected(&node_id(1)).unwrap();
+        // an unknown peer is ignored.
+        peers.peer_disconnected(&node_id(2)).unwrap();
+        assert!(peers.peer(&node_id(2)).unwrap().is_none());
+
+        let peers = PeerStore::new(store);
+        assert!(peers.peer(&node_id(1)).unwrap().unwrap().disconnected);
+        // an explicit connect enables the reconnection again.
+        peers.add_peer(&node_id(1), &address).unwrap();
+        assert!(!peers.peer(&node_id(1)).unwrap().unwrap().disconnected);
+    }
+}
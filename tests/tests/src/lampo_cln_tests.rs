@@ -80,6 +80,7 @@ pub fn fund_a_simple_channel_from_lampo() {
         Err(())
     });
 
+    let funding_events = lampo.events();
     let response: json::Value = lampo
         .call(
             "fundchannel",
@@ -89,10 +90,11 @@ pub fn fund_a_simple_channel_from_lampo() {
                 amount: 100000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
-    assert!(response.get("tx").is_some());
+    wait_funding_end(&funding_events, &response);
 
     wait!(|| {
         while let Ok(event) = events.recv_timeout(Duration::from_millis(100)) {
@@ -298,6 +300,7 @@ pub fn decode_cln_offer_from_lampo() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -406,6 +409,7 @@ pub fn fetchinvoice_cln_offer_from_lampo() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -610,6 +614,7 @@ pub fn pay_invoice_to_cln() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -716,6 +721,7 @@ fn be_able_to_kesend_payments() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
@@ -769,6 +775,7 @@ fn be_able_to_kesend_payments() {
         request::KeySend {
             destination: PublicKey::from_str(info_cln.id.as_str()).unwrap(),
             amount_msat: 100_00_000,
+            options: Default::default(),
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -810,6 +817,7 @@ fn test_closing_two_channels_without_channelid_fails() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
@@ -824,6 +832,7 @@ fn test_closing_two_channels_without_channelid_fails() {
                 amount: 1_000_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
@@ -876,6 +885,10 @@ fn test_closing_two_channels_without_channelid_fails() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: None,
         },
     );
 
@@ -918,6 +931,7 @@ fn test_lampo_to_cln_close_channel_with_channel_id_success() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
@@ -971,6 +985,10 @@ fn test_lampo_to_cln_close_channel_with_channel_id_success() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: Some(channels.channels.first().unwrap().channel_id.to_string()),
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: None,
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -1020,6 +1038,7 @@ fn test_lampo_to_cln_close_channel_without_channel_id_success() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
             },
         )
         .unwrap();
@@ -1073,6 +1092,10 @@ fn test_lampo_to_cln_close_channel_without_channel_id_success() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: None,
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -1120,8 +1143,149 @@ fn test_close_channel_without_opening_a_channel_fails() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: None,
         },
     );
     assert!(result.is_err(), "{:?}", result);
     async_run!(cln.stop()).unwrap();
 }
+
+#[test]
+fn test_lampo_to_cln_close_channel_after_unilateral_timeout() {
+    init();
+    let mut cln = async_run!(cln::Node::with_params(
+        "--developer --dev-bitcoind-poll=1 --dev-fast-gossip --dev-allow-localhost",
+        "regtest"
+    ))
+    .unwrap();
+    let btc = cln.btc();
+    let lampo_manager = LampoTesting::new(btc.clone()).unwrap();
+    let lampo = lampo_manager.lampod();
+    let _info: response::GetInfo = lampo.call("getinfo", json::json!({})).unwrap();
+    let info_cln = cln.rpc().getinfo().unwrap();
+    let events = lampo.events();
+    let address = lampo_manager.fund_wallet(101).unwrap();
+    wait!(|| {
+        let Ok(Event::OnChain(OnChainEvent::NewBestBlock((_, height)))) =
+            events.recv_timeout(Duration::from_millis(100))
+        else {
+            return Err(());
+        };
+        if height.to_consensus_u32() == 101 {
+            return Ok(());
+        }
+        Err(())
+    });
+    let _: json::Value = lampo
+        .call(
+            "fundchannel",
+            request::OpenChannel {
+                node_id: cln.rpc().getinfo().unwrap().id,
+                port: Some(cln.port.into()),
+                amount: 1_500_000_000,
+                public: true,
+                addr: Some("127.0.0.1".to_owned()),
+                options: Default::default(),
+            },
+        )
+        .unwrap();
+
+    // Get the transaction confirmed
+    let _ = btc.rpc().generate_to_address(6, &address).unwrap();
+    wait!(|| {
+        log::info!(target: "tests", "wait for confimetion");
+        let _ = btc.rpc().generate_to_address(1, &address).unwrap();
+        // Get the transaction confirmed
+        for _ in 0..100 {
+            let Ok(event) = events.recv_timeout(Duration::from_nanos(100)) else {
+                continue;
+            };
+            log::info!(target: "tests", "lampo event: {:?}", event);
+            match event {
+                Event::Lightning(LightningEvent::ChannelReady { .. }) => return Ok(()),
+                _ => continue,
+            };
+        }
+        Err(())
+    });
+
+    wait!(|| {
+        let channels = cln.rpc().listfunds().unwrap().channels;
+        if channels.is_empty() {
+            return Err(());
+        }
+
+        let mut channels = cln.rpc().listfunds().unwrap().channels;
+        let origin_size = channels.len();
+        channels.retain(|chan| chan.state == "CHANNELD_NORMAL");
+        if channels.len() == origin_size {
+            return Ok(());
+        }
+
+        let channels: response::Channels = lampo.call("channels", json::json!({})).unwrap();
+        if !channels.channels.first().unwrap().ready {
+            return Err(());
+        }
+        let address = cln.rpc().newaddr(None).unwrap();
+        fund_wallet(btc.clone(), &address.bech32.unwrap(), 1).unwrap();
+        crate::wait_cln_sync!(cln);
+        Err(())
+    });
+
+    let channels: response::Channels = lampo.call("channels", json::json!({})).unwrap();
+    let channel_id = channels.channels.first().unwrap().channel_id.to_string();
+
+    // an invalid destination is refused before closing the channel.
+    let result: Result<response::CloseChannel, _> = lampo.call(
+        "close",
+        request::CloseChannel {
+            node_id: info_cln.id.to_string(),
+            channel_id: Some(channel_id.clone()),
+            force: None,
+            destination: Some("not an address".to_owned()),
+            feerate: None,
+            unilateral_timeout: None,
+        },
+    );
+    assert!(result.is_err(), "{:?}", result);
+    // the options of the cooperative close can not be used with `force`.
+    let result: Result<response::CloseChannel, _> = lampo.call(
+        "close",
+        request::CloseChannel {
+            node_id: info_cln.id.to_string(),
+            channel_id: Some(channel_id.clone()),
+            force: Some(true),
+            destination: None,
+            feerate: Some(1000),
+            unilateral_timeout: None,
+        },
+    );
+    assert!(result.is_err(), "{:?}", result);
+    let channels: response::Channels = lampo.call("channels", json::json!({})).unwrap();
+    assert!(channels
+        .channels
+        .iter()
+        .any(|channel| channel.channel_id == channel_id));
+
+    // the cooperative close cannot complete without waiting,
+    // so the channel is force closed.
+    let result: Result<response::CloseChannel, _> = lampo.call(
+        "close",
+        request::CloseChannel {
+            node_id: info_cln.id.to_string(),
+            channel_id: Some(channel_id.clone()),
+            force: None,
+            destination: None,
+            feerate: None,
+            unilateral_timeout: Some(0),
+        },
+    );
+    assert!(result.is_ok(), "{:?}", result);
+    let result = result.unwrap();
+    assert_eq!(result.channel_id, channel_id);
+    assert!(result.message.contains("force-closed"), "{:?}", result);
+    async_run!(cln.stop()).unwrap();
+}
@@ -17,6 +17,7 @@ use lampo_testing::wait;
 use lampo_testing::LampoTesting;
 
 use crate::init;
+use crate::utils::wait_funding_end;
 
 #[test]
 pub fn init_connection_test_between_lampo() -> error::Result<()> {
@@ -74,6 +75,7 @@ pub fn fund_a_simple_channel_from() -> error::Result<()> {
         Err(())
     });
 
+    let funding_events = node1.lampod().events();
     let response: json::Value = node1
         .lampod()
         .call(
@@ -84,10 +86,11 @@ pub fn fund_a_simple_channel_from() -> error::Result<()> {
                 public: true,
                 port: None,
                 addr: None,
+                options: Default::default(),
             },
         )
         .unwrap();
-    assert!(response.get("tx").is_some());
+    wait_funding_end(&funding_events, &response);
 
     let events = node2.lampod().events();
     wait!(|| {
@@ -142,6 +145,7 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
         Err(())
     });
 
+    let funding_events = node1.lampod().events();
     let response: json::Value = node1
         .lampod()
         .call(
@@ -152,10 +156,11 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                options: Default::default(),
             },
         )
         .unwrap();
-    assert!(response.get("tx").is_some());
+    wait_funding_end(&funding_events, &response);
 
     wait!(|| {
         while let Ok(event) = events.recv_timeout(Duration::from_millis(10)) {
@@ -199,6 +204,7 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
     let invoice: response::Invoice = node2.lampod().call(
         "invoice",
         request::GenerateInvoice {
+            label: None,
             description: "making sure that we can work betwen lampo version".to_owned(),
             amount_msat: Some(100_000_000),
             expiring_in: None,
@@ -212,6 +218,9 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
         request::Pay {
             invoice_str: invoice.bolt11,
             amount: None,
+            async_pay: None,
+            timeout: None,
+            options: Default::default(),
         },
     )?;
     log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
@@ -240,6 +249,7 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
         Err(())
     });
 
+    let funding_events = node1.lampod().events();
     let response: json::Value = node1
         .lampod()
         .call(
@@ -250,10 +260,11 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                options: Default::default(),
             },
         )
         .unwrap();
-    assert!(response.get("tx").is_some());
+    wait_funding_end(&funding_events, &response);
 
     wait!(|| {
         while let Ok(event) = events.recv_timeout(Duration::from_millis(10)) {
@@ -309,6 +320,9 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
         request::Pay {
             invoice_str: offer.bolt12,
             amount: None,
+            async_pay: None,
+            timeout: None,
+            options: Default::default(),
         },
     )?;
     log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
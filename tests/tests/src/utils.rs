@@ -1,12 +1,18 @@
 //! Test Utils
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use lampo_testing::prelude::bitcoincore_rpc;
 use lampo_testing::prelude::bitcoincore_rpc::RpcApi;
 use lampo_testing::prelude::btc;
 
+use lampo_common::chan;
 use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::json;
+use lampo_testing::wait;
 
 #[macro_export]
 macro_rules! wait_cln_sync {
@@ -72,3 +78,29 @@ pub fn fund_wallet(btc: Arc<btc::BtcNode>, addr: &str, blocks: u64) -> error::Re
 
     Ok(address.to_string())
 }
+
+/// Check that `fundchannel` returned a pending channel, and wait
+/// that the opener creates the funding transaction of it.
+///
+/// Nb. `events` must be subscribed before calling `fundchannel`.
+pub fn wait_funding_end(events: &chan::Receiver<Event>, response: &json::Value) {
+    assert_eq!(response["state"], "pending", "{response}");
+    let temporary_channel_id = response["temporary_channel_id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("`temporary_channel_id` missing: {response}"))
+        .to_owned();
+    wait!(|| {
+        while let Ok(event) = events.recv_timeout(Duration::from_millis(100)) {
+            if let Event::Lightning(LightningEvent::FundingChannelEnd {
+                temporary_channel_id: channel_id,
+                ..
+            }) = event
+            {
+                if channel_id.to_string() == temporary_channel_id {
+                    return Ok(());
+                }
+            }
+        }
+        Err(())
+    });
+}